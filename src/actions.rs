@@ -0,0 +1,111 @@
+use serde::Deserialize;
+use std::net::IpAddr;
+
+use crate::links::words;
+
+// Token actions ======================================
+// What double-clicking a recognized token in the output does, e.g.:
+//
+//   [[token_actions]]
+//   token = "git_sha"
+//   action = "open"
+//   url = "https://github.com/me/project/commit/{}"
+//
+//   [[token_actions]]
+//   token = "container_id"
+//   action = "send"    # type it into the active pane
+//
+// Rules are tried in order and the first one matching a token wins, which matters
+// for tokens that fit more than one kind (a 12 character container ID is also a short SHA).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    GitSha,
+    IpAddress,
+    ContainerId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenActionKind {
+    Copy,
+    Open,  // Open `url` with {} replaced by the token
+    Send,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct TokenRule {
+    pub token: TokenKind,
+    pub action: TokenActionKind,
+    pub url: Option<String>,
+}
+
+// A double-clicked token and what to do with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenAction {
+    pub kind: TokenActionKind,
+    pub token: String,
+    pub url: Option<String>,
+}
+
+impl TokenRule {
+    pub fn matches(&self, word: &str) -> bool {
+        match self.token {
+            TokenKind::GitSha => is_hex_id(word) && (7..=40).contains(&word.len()),
+            TokenKind::ContainerId => is_hex_id(word) && matches!(word.len(), 12 | 64),
+            TokenKind::IpAddress => word.parse::<IpAddr>().is_ok(),
+        }
+    }
+
+    pub fn action_for(&self, token: &str) -> TokenAction {
+        TokenAction {
+            kind: self.action,
+            token: token.to_string(),
+            url: self.url.as_ref().map(|template| template.replace("{}", token)),
+        }
+    }
+
+    // Short hint shown when hovering a token
+    pub fn describe(&self, token: &str) -> String {
+        match self.action {
+            TokenActionKind::Copy => "Double-click to copy".to_string(),
+            TokenActionKind::Send => "Double-click to send to the active pane".to_string(),
+            TokenActionKind::Open => match &self.url {
+                Some(template) => format!("Double-click to open {}", template.replace("{}", token)),
+                None => "No url set for this token action".to_string(),
+            },
+        }
+    }
+}
+
+// Lowercase hex with at least one letter and one digit, so plain numbers and
+// words like "decade" don't count
+fn is_hex_id(word: &str) -> bool {
+    word.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        && word.chars().any(|c| c.is_ascii_digit())
+        && word.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+// Words in `text` that some rule applies to, as (start, end, rule)
+pub fn find_token_actions<'a>(text: &str, rules: &'a [TokenRule]) -> Vec<(usize, usize, &'a TokenRule)> {
+    if rules.is_empty() {
+        return Vec::new();
+    }
+    words(text)
+        .filter_map(|(start, word)| {
+            let rule = rules.iter().find(|rule| rule.matches(word))?;
+            Some((start, start + word.len(), rule))
+        })
+        .collect()
+}
+
+// Open a URL with the desktop's default handler
+pub fn open_url(url: &str) {
+    match std::process::Command::new("xdg-open").arg(url).spawn() {
+        // Reap it in the background so it doesn't linger as a zombie
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("Warning: Failed to open {}: {}", url, e),
+    }
+}
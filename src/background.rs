@@ -0,0 +1,118 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::links::resolve_path;
+
+// Background image ===================================
+// A picture behind a pane's output, scaled to cover it and dimmed toward the
+// background color so the text on top stays readable, optionally blurred
+
+const MAX_IMAGE_SIZE: u32 = 1920;  // Larger pictures are scaled down first
+pub const MAX_BLUR: f32 = 20.0;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackgroundImage {
+    pub path: String,  // As typed, ~ allowed
+    pub dim: f32,  // 0 shows the picture as is, 1 hides it
+    pub blur: f32,  // Radius in pixels
+}
+
+impl BackgroundImage {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_string(), dim: 0.6, blur: 0.0 }
+    }
+}
+
+// The picture as a texture, made again when the path or blur changes (but not while
+// a slider is being dragged, blurring takes a moment)
+#[derive(Clone, Default)]
+pub struct BackgroundTexture {
+    made_from: Option<(String, f32)>,
+    texture: Option<egui::TextureHandle>,  // None if the picture couldn't be read
+}
+
+impl BackgroundTexture {
+    pub fn get(&mut self, ctx: &egui::Context, image: &BackgroundImage) -> Option<&egui::TextureHandle> {
+        let key = (image.path.clone(), image.blur);
+        let dragging = ctx.input(|i| i.pointer.any_down());
+        if self.made_from.as_ref() != Some(&key) && !(dragging && self.made_from.is_some()) {
+            self.texture = load_image(&image.path, image.blur)
+                .map(|pixels| ctx.load_texture(format!("background {}", image.path), pixels, egui::TextureOptions::LINEAR));
+            self.made_from = Some(key);
+        }
+        self.texture.as_ref()
+    }
+}
+
+// Paint the picture over `rect` the way CSS's `background-size: cover` does, then
+// the dimming on top
+pub fn paint(painter: &egui::Painter, rect: egui::Rect, texture: &egui::TextureHandle, opacity: f32, dim: f32, background: egui::Color32) {
+    let size = texture.size_vec2();
+    let scale = (rect.width() / size.x).max(rect.height() / size.y);
+    // Margins of the picture cut off on each side, as a fraction of its size
+    let crop = (size - rect.size() / scale) / size / 2.0;
+    let uv = egui::Rect::from_min_max(egui::pos2(crop.x, crop.y), egui::pos2(1.0 - crop.x, 1.0 - crop.y));
+    painter.image(texture.id(), rect, uv, egui::Color32::WHITE.gamma_multiply(opacity));
+    painter.rect_filled(rect, 0.0, background.gamma_multiply(dim * opacity));
+}
+
+fn load_image(path: &str, blur: f32) -> Option<egui::ColorImage> {
+    let file = resolve_path(path, None);
+    let image = match image::open(&file) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("Warning: Failed to load background image {}: {}", file.display(), e);
+            return None;
+        }
+    };
+    let image = if image.width() > MAX_IMAGE_SIZE || image.height() > MAX_IMAGE_SIZE {
+        image.resize(MAX_IMAGE_SIZE, MAX_IMAGE_SIZE, image::imageops::FilterType::Triangle)
+    } else {
+        image
+    };
+    let mut pixels = image.to_rgba8();
+    let radius = blur.clamp(0.0, MAX_BLUR).round() as usize;
+    if radius > 0 {
+        // Three box blurs come close to a gaussian one
+        let (width, height) = (pixels.width() as usize, pixels.height() as usize);
+        for _ in 0..3 {
+            box_blur(&mut pixels, width, height, radius, true);
+            box_blur(&mut pixels, width, height, radius, false);
+        }
+    }
+    let size = [pixels.width() as usize, pixels.height() as usize];
+    Some(egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_raw()))
+}
+
+// Average each pixel with `radius` on either side along its row (or column), the
+// edge pixels standing in past the edges
+fn box_blur(pixels: &mut [u8], width: usize, height: usize, radius: usize, horizontal: bool) {
+    let (lines, len) = if horizontal { (height, width) } else { (width, height) };
+    let offset = |line: usize, i: usize| if horizontal { (line * width + i) * 4 } else { (i * width + line) * 4 };
+    let window = (2 * radius + 1) as u32;
+    let mut source = vec![[0u8; 4]; len];
+    for line in 0..lines {
+        for (i, pixel) in source.iter_mut().enumerate() {
+            let at = offset(line, i);
+            pixel.copy_from_slice(&pixels[at..at + 4]);
+        }
+        let sample = |i: isize| source[i.clamp(0, len as isize - 1) as usize];
+        let mut sum = [0u32; 4];
+        for i in -(radius as isize)..=radius as isize {
+            for (s, c) in sum.iter_mut().zip(sample(i)) {
+                *s += c as u32;
+            }
+        }
+        for i in 0..len {
+            let at = offset(line, i);
+            for channel in 0..4 {
+                pixels[at + channel] = (sum[channel] / window) as u8;
+            }
+            let (entering, leaving) = (sample((i + radius + 1) as isize), sample(i as isize - radius as isize));
+            for channel in 0..4 {
+                sum[channel] = sum[channel] + entering[channel] as u32 - leaving[channel] as u32;
+            }
+        }
+    }
+}
+
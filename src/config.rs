@@ -0,0 +1,285 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::actions::TokenRule;
+use crate::dropdown::DropdownConfig;
+use crate::grid::DEFAULT_SCROLLBACK_LINES;
+use crate::keybindings::Keybindings;
+use crate::schedule::DarkModeSchedule;
+use crate::scrollback::ScrollbackLimit;
+
+// Config =============================================
+// ~/.config/sigmaterm/config.toml; every setting is optional, e.g.:
+//
+//   shell = "zsh"
+//   font = "Fira Code"    # or a path, "~/.local/share/fonts/FiraCode-Regular.ttf"
+//   font_size = 16
+//   theme = "light"    # or "dark"; follows the desktop if unset
+//   color_scheme = "Nord"    # or one imported into ~/.config/sigmaterm/themes/
+//   terminals = 3
+//   max_terminals = 8
+//   layout = "columns"
+//   on_exit = "close_on_success"
+//   bell = "both"
+//   bell_sound = "/usr/share/sounds/freedesktop/stereo/bell.oga"
+//   silence_seconds = 30
+//   scrollback_lines = 10000    # or "unlimited", spilling older output to disk
+//
+//   [palette]    # any of the 16 ANSI colors, black..white and bright_black..bright_white
+//   red = "#e06c75"
+//   bright_black = "#5c6370"
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Program started in each pane
+    pub shell: Option<String>,
+    // What a pane does when its shell exits
+    pub on_exit: OnExit,
+    // Ask before closing a pane, tab or the window while programs are running in it
+    pub confirm_close: bool,
+    // What a BEL from a program does; profiles can override it
+    pub bell: Bell,
+    // Sound file played for the bell (with paplay); the desktop's bell sound if unset
+    pub bell_sound: Option<String>,
+    // Mark a pane whose output stopped for this long (a build or log tail going quiet)
+    // while it wasn't being looked at; off if unset. Profiles can override it.
+    pub silence_seconds: Option<u64>,
+    // Font for the terminal text, an installed family ("Fira Code") or a .ttf/.otf
+    // file; the bundled JetBrains Mono covers whatever it lacks
+    pub font: Option<String>,
+    pub font_size: f32,
+    // Hue (0-360) of the first pane's colors; each new pane moves on around the color wheel
+    pub hue: f32,
+    // Light or dark mode to start in, or "system" to follow the desktop's setting.
+    // The window bar's toggle overrides it until the desktop (or schedule) switches.
+    pub theme: Theme,
+    // Colors for the output instead of the hue's, by name (see theme.rs); profiles can override it
+    pub color_scheme: Option<String>,
+    // Changes to the ANSI colors of panes following their hue, by name (see theme.rs)
+    pub palette: BTreeMap<String, String>,
+    // Panes opened at startup and where new ones go (splitting a pane puts it anywhere)
+    pub terminals: usize,
+    pub max_terminals: Option<usize>,  // Per tab; no limit if unset
+    pub layout: Layout,
+    // Reopen the tabs and panes from the last time Sigmaterm was closed
+    pub restore_session: bool,
+    pub keybindings: Keybindings,
+    // Characters (besides letters and digits) that count as part of a word for
+    // double-click selection and Ctrl+word editing. Add "/.:" to treat paths and
+    // URLs as a single word.
+    pub word_chars: String,
+    // Lines of output each pane keeps, or "unlimited" to keep everything, the older
+    // part on disk; profiles can override it
+    pub scrollback_lines: ScrollbackLimit,
+    // Profile applied to new panes, by name
+    pub default_profile: Option<String>,
+    pub profiles: Vec<Profile>,
+    // System-wide shortcut that raises Sigmaterm, e.g. "ctrl+alt+t"
+    pub summon_hotkey: Option<String>,
+    // Run as a Quake-style dropdown terminal, shown and hidden with a hotkey (see dropdown.rs)
+    pub dropdown: Option<DropdownConfig>,
+    // Switch dark/light mode automatically at fixed times or sunset/sunrise
+    pub dark_mode_schedule: Option<DarkModeSchedule>,
+    // What double-clicking a git SHA, IP address or container ID does
+    pub token_actions: Vec<TokenRule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shell: None,
+            on_exit: OnExit::Hold,
+            confirm_close: true,
+            bell: Bell::Visual,
+            bell_sound: None,
+            silence_seconds: None,
+            font: None,
+            font_size: 18.0,
+            hue: 180.0,
+            theme: Theme::System,
+            color_scheme: None,
+            palette: BTreeMap::new(),
+            terminals: 2,
+            max_terminals: None,
+            layout: Layout::Grid,
+            restore_session: true,
+            keybindings: Keybindings::default(),
+            word_chars: "_-".to_string(),
+            scrollback_lines: ScrollbackLimit::Lines(DEFAULT_SCROLLBACK_LINES),
+            default_profile: None,
+            profiles: Vec::new(),
+            summon_hotkey: None,
+            dropdown: None,
+            dark_mode_schedule: None,
+            token_actions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    Dark,
+    Light,
+    System,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnExit {
+    Hold,            // Keep the output with a banner offering to restart or close
+    Close,           // Close the pane
+    CloseOnSuccess,  // Close it if the shell exited with status 0, else hold
+}
+
+// A ringing pane's header and tab get a 🔔 until it's looked at, whichever of these is set
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bell {
+    Visual,  // Flash the pane's border
+    Sound,   // Play the bell sound
+    Both,
+    Off,     // Not even the indicator
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    Grid,     // Each new pane takes half of the active one, along its longer side
+    Columns,  // New panes go in a new column on the right
+}
+
+// Profile ============================================
+// Kinds of pane, opened from the menu next to the add button, e.g. a Python REPL, or an
+// "ops" profile that always logs:
+//
+//   [[profiles]]
+//   name = "Python REPL"
+//   command = "python3"
+//   hue = 60
+//   bell = "sound"
+//   color_scheme = "Dracula"
+//   palette = { blue = "#6272a4" }
+//
+//   [[profiles]]
+//   name = "ops"
+//   shell = "zsh"
+//   args = ["--login"]
+//   log_output = true
+//   env = { KUBECONFIG = "/home/me/.kube/prod" }
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub shell: Option<String>,  // Instead of the config's shell
+    pub args: Vec<String>,  // Passed to the shell
+    pub command: Option<String>,  // Typed into the shell once it's started
+    pub env: BTreeMap<String, String>,  // Extra environment variables for the shell
+    pub hue: Option<f32>,  // Instead of the next color around the wheel
+    pub theme: Option<Theme>,  // Keep the pane dark (or light) whatever the window's mode
+    pub color_scheme: Option<String>,
+    pub palette: BTreeMap<String, String>,  // Changes to the ANSI colors, over the scheme's or hue's
+    pub font_size: Option<f32>,
+    pub scrollback_lines: Option<ScrollbackLimit>,  // Keep only this many lines of output, or "unlimited"
+    pub bell: Option<Bell>,  // Instead of the config's bell
+    pub mute_bell: bool,  // Same as bell = "off"
+    pub silence_seconds: Option<u64>,  // Instead of the config's silence_seconds
+    pub log_output: bool,  // Append everything the pane receives to a log file
+}
+
+impl Config {
+    // Load ~/.config/sigmaterm/config.toml, falling back to defaults if it's missing or broken
+    pub fn load() -> Self {
+        Self::try_load().unwrap_or_else(|e| {
+            eprintln!("Warning: {}", e);
+            Self::default()
+        })
+    }
+
+    // Like load(), but a broken file is an error rather than the defaults, so a reload
+    // can keep the settings in use
+    pub fn try_load() -> Result<Self, String> {
+        let Some(path) = config_path() else {
+            return Ok(Self::default());
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn default_profile(&self) -> Option<&Profile> {
+        self.default_profile.as_deref().and_then(|name| self.profile(name))
+    }
+
+    pub fn is_word_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || self.word_chars.contains(c)
+    }
+}
+
+// Config watcher =====================================
+// Notices edits to the config file by checking its modification time every second
+pub struct ConfigWatcher {
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl Default for ConfigWatcher {
+    fn default() -> Self {
+        Self {
+            modified: config_modified(),
+            last_check: Instant::now(),
+        }
+    }
+}
+
+impl ConfigWatcher {
+    // True once for each change to the file (a deleted file doesn't count)
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < Duration::from_secs(1) {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let modified = config_modified();
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        modified.is_some()
+    }
+}
+
+fn config_modified() -> Option<SystemTime> {
+    std::fs::metadata(config_path()?).ok()?.modified().ok()
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    if let Ok(dir) = std::env::var("XDG_CONFIG_HOME")
+        && !dir.is_empty()
+    {
+        return Some(PathBuf::from(dir).join("sigmaterm"));
+    }
+    std::env::var("HOME").ok().map(|home| PathBuf::from(home).join(".config").join("sigmaterm"))
+}
+
+// Where session logs and other generated files go (~/.local/share/sigmaterm)
+pub fn data_dir() -> Option<PathBuf> {
+    if let Ok(dir) = std::env::var("XDG_DATA_HOME")
+        && !dir.is_empty()
+    {
+        return Some(PathBuf::from(dir).join("sigmaterm"));
+    }
+    std::env::var("HOME").ok().map(|home| PathBuf::from(home).join(".local").join("share").join("sigmaterm"))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
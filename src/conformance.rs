@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use crate::grid::{cells_text, Grid};
+use crate::parser::Utf8Decoder;
+
+// VT conformance =====================================
+// Escape sequence scenarios run through the grid, checking the screen they leave
+// behind, so VT features that work keep working. Each scenario is a .vt file in
+// tests/vt:
+//
+//   # What it checks
+//   size 20x5
+//   == input
+//   \e[2;3HA\r\n
+//   == screen
+//   first row
+//     second row
+//   == cursor
+//   2,0
+//
+// Input is escaped: \e, \r, \n, \t, \a, \xNN and \\; the file's own line breaks are only
+// there for reading. Screen rows are compared with trailing blanks trimmed, and rows
+// left off the bottom are blank. The cursor is row,column counted from 0. `scrollback`
+// (the lines above the screen, oldest first), `title` and `responses` (what the terminal
+// answered, escaped like the input) can be checked too; sections left out aren't.
+// Captures of real programs are made with tests/vt/record.sh. `cargo test conformance`
+// runs them all and lists every fixture that doesn't match, not just the first.
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vt");
+
+#[derive(Default)]
+struct Fixture {
+    cols: usize,
+    rows: usize,
+    input: Vec<u8>,
+    screen: Option<Vec<String>>,
+    cursor: Option<(usize, usize)>,
+    scrollback: Option<Vec<String>>,
+    title: Option<String>,
+    responses: Option<String>,
+}
+
+fn parse_fixture(contents: &str) -> Result<Fixture, String> {
+    let mut fixture = Fixture::default();
+    let mut lines = contents.lines().peekable();
+    // Comments and the size, up to the first section
+    while let Some(line) = lines.next_if(|line| !line.starts_with("== ")) {
+        if let Some(size) = line.strip_prefix("size ") {
+            let (cols, rows) = size.split_once('x').ok_or("size isn't COLSxROWS")?;
+            fixture.cols = cols.trim().parse().map_err(|_| "bad column count")?;
+            fixture.rows = rows.trim().parse().map_err(|_| "bad row count")?;
+        } else if !line.starts_with('#') && !line.trim().is_empty() {
+            return Err(format!("unexpected line before the first section: {:?}", line));
+        }
+    }
+    if fixture.cols == 0 || fixture.rows == 0 {
+        return Err("no size".to_string());
+    }
+    while let Some(header) = lines.next() {
+        let section: Vec<&str> = std::iter::from_fn(|| lines.next_if(|line| !line.starts_with("== "))).collect();
+        match header.trim_start_matches("== ").trim() {
+            "input" => fixture.input = unescape(&section.concat())?,
+            "screen" => fixture.screen = Some(section.iter().map(|row| row.trim_end().to_string()).collect()),
+            "cursor" => {
+                let (row, col) = section.concat().split_once(',')
+                    .and_then(|(row, col)| Some((row.trim().parse().ok()?, col.trim().parse().ok()?)))
+                    .ok_or("cursor isn't ROW,COLUMN")?;
+                fixture.cursor = Some((row, col));
+            }
+            "scrollback" => fixture.scrollback = Some(section.iter().map(|line| line.trim_end().to_string()).collect()),
+            "title" => fixture.title = Some(section.concat()),
+            "responses" => fixture.responses = Some(String::from_utf8_lossy(&unescape(&section.concat())?).into_owned()),
+            other => return Err(format!("unknown section {:?}", other)),
+        }
+    }
+    Ok(fixture)
+}
+
+// Bytes rather than text, since output doesn't have to be valid UTF-8
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('e') => bytes.push(0x1b),
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('a') => bytes.push(0x07),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).map_err(|_| format!("bad escape \\x{}", hex))?);
+            }
+            other => return Err(format!("unknown escape \\{}", other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(bytes)
+}
+
+// What didn't match, one entry per section
+fn run(fixture: &Fixture) -> Vec<String> {
+    let mut grid = Grid::new(fixture.cols, fixture.rows);
+    grid.feed(&Utf8Decoder::default().decode(&fixture.input));
+    let mut failures = Vec::new();
+
+    if let Some(expected) = &fixture.screen {
+        let actual: Vec<String> = grid.rows().iter().map(|row| cells_text(row).trim_end().to_string()).collect();
+        let mismatched: Vec<String> = (0..actual.len().max(expected.len()))
+            .filter_map(|row| {
+                let (want, got) = (expected.get(row).map_or("", String::as_str), actual.get(row).map_or("<no row>", String::as_str));
+                (want != got).then(|| format!("  row {}: expected {:?}\n          got      {:?}", row, want, got))
+            })
+            .collect();
+        if !mismatched.is_empty() {
+            failures.push(format!("screen:\n{}", mismatched.join("\n")));
+        }
+    }
+    if let Some(expected) = fixture.cursor
+        && grid.cursor() != expected
+    {
+        failures.push(format!("cursor: expected {:?}, got {:?}", expected, grid.cursor()));
+    }
+    if let Some(expected) = &fixture.scrollback {
+        let actual: Vec<String> = grid.scrollback().iter().map(|line| cells_text(&line).trim_end().to_string()).collect();
+        if actual != *expected {
+            failures.push(format!("scrollback: expected {:?}\n            got      {:?}", expected, actual));
+        }
+    }
+    if let Some(expected) = &fixture.title {
+        let actual = grid.take_title();
+        if actual.as_ref() != Some(expected) {
+            failures.push(format!("title: expected {:?}, got {:?}", expected, actual));
+        }
+    }
+    if let Some(expected) = &fixture.responses {
+        let actual = grid.take_responses();
+        if actual != *expected {
+            failures.push(format!("responses: expected {:?}, got {:?}", expected, actual));
+        }
+    }
+    failures
+}
+
+fn run_file(path: &Path) -> Result<(), String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let failures = run(&parse_fixture(&contents)?);
+    if failures.is_empty() { Ok(()) } else { Err(failures.join("\n")) }
+}
+
+#[test]
+fn fixtures_match() {
+    let mut paths: Vec<_> = std::fs::read_dir(FIXTURES)
+        .expect("tests/vt is missing")
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vt"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no fixtures in tests/vt");
+
+    let failures: Vec<String> = paths.iter()
+        .filter_map(|path| run_file(path).err().map(|e| format!("{}:\n{}", path.file_name().unwrap_or_default().to_string_lossy(), e)))
+        .collect();
+    assert!(failures.is_empty(), "{} of {} fixtures failed\n\n{}", failures.len(), paths.len(), failures.join("\n\n"));
+}
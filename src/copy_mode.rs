@@ -0,0 +1,200 @@
+use eframe::egui;
+
+use crate::grid::Cell;
+use crate::selection::Selection;
+
+// Copy mode ==========================================
+// Selecting without the mouse, like tmux's copy mode: a cursor moves through the
+// output with vi keys (hjkl, w/b, 0/$, g/G) or the arrows, `v` starts a selection,
+// `y` copies it and `/` searches. Lines count the way the mouse selection's do.
+
+pub enum CopyModeAction {
+    None,
+    Copy,
+    Exit,
+    Search,
+    NextMatch,
+    PreviousMatch,
+}
+
+pub struct CopyMode {
+    pub cursor: (usize, usize),  // Line and column
+    anchor: Option<(usize, usize)>,  // Where `v` was pressed
+}
+
+impl CopyMode {
+    pub fn new(cursor: (usize, usize)) -> Self {
+        Self { cursor, anchor: None }
+    }
+
+    // From the anchor to the cursor, the cell under the cursor included as in vi
+    pub fn selection(&self) -> Option<Selection> {
+        let anchor = self.anchor?;
+        let (start, end) = if anchor <= self.cursor { (anchor, self.cursor) } else { (self.cursor, anchor) };
+        Some(Selection { anchor: start, head: (end.0, end.1 + 1), block: false })
+    }
+
+    pub fn move_to(&mut self, cursor: (usize, usize)) {
+        self.cursor = cursor;
+    }
+
+    // Lines fell off the top of the scrollback
+    pub fn shift(&mut self, removed_lines: usize) {
+        self.cursor.0 = self.cursor.0.saturating_sub(removed_lines);
+        if let Some(anchor) = &mut self.anchor {
+            anchor.0 = anchor.0.saturating_sub(removed_lines);
+        }
+    }
+
+    // Arrows, Home/End, PageUp/PageDown, Enter and Escape; `page` is the rows on screen
+    pub fn key(&mut self, key: egui::Key, lines: &[&[Cell]], page: usize) -> CopyModeAction {
+        match key {
+            egui::Key::ArrowLeft => self.left(),
+            egui::Key::ArrowRight => self.right(lines),
+            egui::Key::ArrowUp => self.up(1, lines),
+            egui::Key::ArrowDown => self.down(1, lines),
+            egui::Key::PageUp => self.up(page, lines),
+            egui::Key::PageDown => self.down(page, lines),
+            egui::Key::Home => self.cursor.1 = 0,
+            egui::Key::End => self.line_end(lines),
+            egui::Key::Enter => return CopyModeAction::Copy,
+            egui::Key::Escape => return CopyModeAction::Exit,
+            _ => {}
+        }
+        CopyModeAction::None
+    }
+
+    // The vi keys, typed as text so `$` and `G` work whatever the layout
+    pub fn text(&mut self, text: &str, lines: &[&[Cell]], is_word_char: impl Fn(char) -> bool) -> CopyModeAction {
+        for c in text.chars() {
+            match c {
+                'h' => self.left(),
+                'l' => self.right(lines),
+                'k' => self.up(1, lines),
+                'j' => self.down(1, lines),
+                '0' => self.cursor.1 = 0,
+                '$' => self.line_end(lines),
+                'g' => self.cursor = (0, 0),
+                'G' => self.cursor = (lines.len().saturating_sub(1), 0),
+                'w' => self.word_forward(lines, &is_word_char),
+                'b' => self.word_backward(lines, &is_word_char),
+                'v' => self.anchor = if self.anchor.is_some() { None } else { Some(self.cursor) },
+                'y' => return CopyModeAction::Copy,
+                'q' => return CopyModeAction::Exit,
+                '/' => return CopyModeAction::Search,
+                'n' => return CopyModeAction::NextMatch,
+                'N' => return CopyModeAction::PreviousMatch,
+                _ => {}
+            }
+        }
+        CopyModeAction::None
+    }
+
+    fn left(&mut self) {
+        self.cursor.1 = self.cursor.1.saturating_sub(1);
+    }
+
+    fn right(&mut self, lines: &[&[Cell]]) {
+        self.cursor.1 = (self.cursor.1 + 1).min(last_column(lines, self.cursor.0));
+    }
+
+    fn up(&mut self, count: usize, lines: &[&[Cell]]) {
+        self.cursor.0 = self.cursor.0.saturating_sub(count);
+        self.cursor.1 = self.cursor.1.min(last_column(lines, self.cursor.0));
+    }
+
+    fn down(&mut self, count: usize, lines: &[&[Cell]]) {
+        self.cursor.0 = (self.cursor.0 + count).min(lines.len().saturating_sub(1));
+        self.cursor.1 = self.cursor.1.min(last_column(lines, self.cursor.0));
+    }
+
+    fn line_end(&mut self, lines: &[&[Cell]]) {
+        self.cursor.1 = last_column(lines, self.cursor.0);
+    }
+
+    // Start of the next word, on a later line if this one has no more
+    fn word_forward(&mut self, lines: &[&[Cell]], is_word_char: &impl Fn(char) -> bool) {
+        let (mut line, mut col) = self.cursor;
+        let cells = lines.get(line).copied().unwrap_or_default();
+        let class = |cells: &[Cell], col: usize| char_at(cells, col).map(|c| char_class(c, is_word_char));
+        let start_class = class(cells, col);
+        while start_class.is_some() && class(cells, col) == start_class {
+            col += 1;
+        }
+        loop {
+            let cells = lines.get(line).copied().unwrap_or_default();
+            while class(cells, col) == Some(CharClass::Blank) {
+                col += 1;
+            }
+            if col < text_len(cells) || line + 1 >= lines.len() {
+                break;
+            }
+            line += 1;
+            col = 0;
+        }
+        self.cursor = (line, col.min(last_column(lines, line)));
+    }
+
+    // Start of this word, or of the one before, on an earlier line if need be
+    fn word_backward(&mut self, lines: &[&[Cell]], is_word_char: &impl Fn(char) -> bool) {
+        let (mut line, col) = self.cursor;
+        let Some(cells) = lines.get(line) else {
+            return;
+        };
+        let mut col = col.min(cells.len());
+        let ch = |line: usize, col: usize| char_at(lines[line], col).unwrap_or(' ');
+        loop {
+            // Step back over blanks, to the line above at the start of a line
+            while col > 0 && char_class(ch(line, col - 1), is_word_char) == CharClass::Blank {
+                col -= 1;
+            }
+            if col > 0 || line == 0 {
+                break;
+            }
+            line -= 1;
+            col = text_len(lines[line]);
+        }
+        if col > 0 {
+            let word = char_class(ch(line, col - 1), is_word_char);
+            while col > 0 && char_class(ch(line, col - 1), is_word_char) == word {
+                col -= 1;
+            }
+        }
+        self.cursor = (line, col);
+    }
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn char_class(c: char, is_word_char: &impl Fn(char) -> bool) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if is_word_char(c) {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+// The character in a cell; the right half of a wide character counts as that character
+fn char_at(cells: &[Cell], col: usize) -> Option<char> {
+    let cell = cells.get(col)?;
+    match cell.width {
+        0 if col > 0 => Some(cells[col - 1].ch),
+        _ => Some(cell.ch),
+    }
+}
+
+// Cells up to the last one with something in it
+fn text_len(cells: &[Cell]) -> usize {
+    cells.iter().rposition(|cell| !cell.ch.is_whitespace() || cell.width == 0).map_or(0, |i| i + 1)
+}
+
+fn last_column(lines: &[&[Cell]], line: usize) -> usize {
+    lines.get(line).map_or(0, |cells| text_len(cells).saturating_sub(1))
+}
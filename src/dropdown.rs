@@ -0,0 +1,146 @@
+use eframe::egui;
+use serde::Deserialize;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+// Dropdown mode ======================================
+// Quake-style, like guake or yakuake: a hotkey slides the window down from the top
+// of the screen, and again (or clicking elsewhere) slides it back up out of sight.
+//
+//   [dropdown]
+//   hotkey = "F12"
+//   height = 0.4    # of the screen
+//   monitor = 1     # as numbered by `xrandr --listmonitors`; the primary one if unset
+//
+// Placing the window needs X11; Wayland doesn't let windows move themselves.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct DropdownConfig {
+    pub hotkey: String,
+    pub height: f32,  // Fraction of the screen's height
+    pub width: f32,  // Fraction of its width, centered
+    pub monitor: Option<usize>,
+    pub hide_on_focus_loss: bool,
+}
+
+impl Default for DropdownConfig {
+    fn default() -> Self {
+        Self {
+            hotkey: "F12".to_string(),
+            height: 0.4,
+            width: 1.0,
+            monitor: None,
+            hide_on_focus_loss: true,
+        }
+    }
+}
+
+const SLIDE_TIME: Duration = Duration::from_millis(150);
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Shown,
+    Hidden,
+    SlidingDown(Instant),
+    SlidingUp(Instant),
+}
+
+pub struct Dropdown {
+    config: DropdownConfig,
+    pub hotkey: Option<u32>,
+    state: State,
+    was_focused: bool,
+    on_screen: bool,  // Sized, on top and visible
+    screen: Option<egui::Rect>,  // Where the window drops into, in points
+}
+
+impl Dropdown {
+    // Starts out dropped down
+    pub fn new(config: DropdownConfig, hotkey: Option<u32>) -> Self {
+        Self { config, hotkey, state: State::SlidingDown(Instant::now()), was_focused: false, on_screen: false, screen: None }
+    }
+
+    pub fn toggle(&mut self) {
+        self.state = match self.state {
+            State::Hidden | State::SlidingUp(_) => State::SlidingDown(Instant::now()),
+            State::Shown | State::SlidingDown(_) => State::SlidingUp(Instant::now()),
+        };
+    }
+
+    // Showing or on its way down
+    pub fn is_shown(&self) -> bool {
+        matches!(self.state, State::Shown | State::SlidingDown(_))
+    }
+
+    // Move the window along the slide, and hide it when it's lost focus
+    pub fn update(&mut self, ctx: &egui::Context) {
+        let focused = ctx.input(|i| i.viewport().focused).unwrap_or(true);
+        if self.state == State::Shown && self.config.hide_on_focus_loss && self.was_focused && !focused {
+            self.toggle();
+        }
+        self.was_focused = focused;
+
+        let (started, down) = match self.state {
+            State::SlidingDown(started) => (started, true),
+            State::SlidingUp(started) => (started, false),
+            State::Shown | State::Hidden => return,
+        };
+        let screen = *self.screen.get_or_insert_with(|| screen_rect(ctx, self.config.monitor));
+        let size = egui::vec2(screen.width() * self.config.width.clamp(0.1, 1.0), screen.height() * self.config.height.clamp(0.1, 1.0));
+        let left = screen.center().x - size.x / 2.0;
+
+        let progress = (started.elapsed().as_secs_f32() / SLIDE_TIME.as_secs_f32()).min(1.0);
+        let shown = if down { progress } else { 1.0 - progress };
+        let top = screen.top() - size.y * (1.0 - shown);
+        if down && !self.on_screen {
+            self.on_screen = true;
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+            ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(egui::WindowLevel::AlwaysOnTop));
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        }
+        ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(left, top)));
+
+        if progress < 1.0 {
+            ctx.request_repaint();
+        } else if down {
+            self.state = State::Shown;
+        } else {
+            self.state = State::Hidden;
+            self.on_screen = false;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+    }
+}
+
+// The chosen monitor from xrandr, else the one egui says the window is on, at the origin
+fn screen_rect(ctx: &egui::Context, monitor: Option<usize>) -> egui::Rect {
+    let pixels_per_point = ctx.input(|i| i.viewport().native_pixels_per_point).unwrap_or(1.0);
+    if let Some(rect) = xrandr_monitor(monitor) {
+        return egui::Rect::from_min_size((rect.min.to_vec2() / pixels_per_point).to_pos2(), rect.size() / pixels_per_point);
+    }
+    let size = ctx.input(|i| i.viewport().monitor_size).unwrap_or(egui::vec2(1280.0, 720.0));
+    egui::Rect::from_min_size(egui::Pos2::ZERO, size)
+}
+
+// A monitor's geometry in pixels from `xrandr --listmonitors`, whose lines look like
+// " 1: +*DP-1 2560/597x1440/336+1920+0  DP-1" (* marks the primary)
+fn xrandr_monitor(monitor: Option<usize>) -> Option<egui::Rect> {
+    let output = Command::new("xrandr").arg("--listmonitors").output().ok()?;
+    let listing = String::from_utf8_lossy(&output.stdout).into_owned();
+    let line = listing.lines().skip(1).find(|line| {
+        let line = line.trim_start();
+        match monitor {
+            Some(index) => line.starts_with(&format!("{}:", index)),
+            None => line.split_whitespace().nth(1).is_some_and(|name| name.contains('*')),
+        }
+    })?;
+    // 2560/597x1440/336+1920+0
+    let geometry = line.split_whitespace().nth(2)?;
+    let (width, rest) = geometry.split_once('x')?;
+    let mut rest = rest.split('+');
+    let height = rest.next()?;
+    let (x, y) = (rest.next()?.parse::<f32>().ok()?, rest.next()?.parse::<f32>().ok()?);
+    let number = |dimension: &str| dimension.split('/').next()?.parse::<f32>().ok();
+    Some(egui::Rect::from_min_size(egui::pos2(x, y), egui::vec2(number(width)?, number(height)?)))
+}
@@ -0,0 +1,142 @@
+use eframe::egui;
+
+use crate::grid::{cells_text, Cell};
+use crate::parser::{CellStyle, TermColor};
+use crate::theme::Palette;
+
+// Export =============================================
+// A pane's scrollback saved to share, e.g. a build log: as plain text, as text with
+// the escape sequences that colored it (`cat` or `less -R` shows it as it was), or
+// as an HTML page with the colors inline
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Text,
+    Ansi,
+    Html,
+}
+
+impl ExportFormat {
+    // Added to file names that don't have one
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Ansi => "ansi",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+// What the HTML is drawn in: the pane's own colors
+pub struct ExportColors<'a> {
+    pub palette: &'a Palette,
+    pub foreground: egui::Color32,
+    pub background: egui::Color32,
+}
+
+pub fn export(lines: impl Iterator<Item = impl AsRef<[Cell]>>, format: ExportFormat, colors: &ExportColors) -> String {
+    let mut out = String::new();
+    if format == ExportFormat::Html {
+        out.push_str(&format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Sigmaterm output</title></head>\n\
+             <body style=\"margin: 0; background: {bg}\">\n\
+             <pre style=\"color: {fg}; background: {bg}; font-family: monospace; padding: 8px\">",
+            fg = hex(colors.foreground),
+            bg = hex(colors.background),
+        ));
+    }
+    for cells in lines {
+        let cells = cells.as_ref();
+        let cells = &cells[..content_len(cells)];
+        for run in cells.chunk_by(|a, b| a.style == b.style) {
+            let text = cells_text(run);
+            let style = &run[0].style;
+            match format {
+                ExportFormat::Text => out.push_str(&text),
+                ExportFormat::Ansi => {
+                    out.push_str(&sgr(style));
+                    out.push_str(&text);
+                }
+                ExportFormat::Html if *style == CellStyle::default() => out.push_str(&escape_html(&text)),
+                ExportFormat::Html => {
+                    out.push_str(&format!("<span style=\"{}\">{}</span>", css(style, colors), escape_html(&text)));
+                }
+            }
+        }
+        if format == ExportFormat::Text {
+            // Colored blanks don't show in plain text
+            out.truncate(out.trim_end_matches(' ').len());
+        }
+        if format == ExportFormat::Ansi && cells.last().is_some_and(|cell| cell.style != CellStyle::default()) {
+            out.push_str("\x1b[0m");
+        }
+        out.push('\n');
+    }
+    if format == ExportFormat::Html {
+        out.push_str("</pre>\n</body>\n</html>\n");
+    }
+    out
+}
+
+// Cells up to the last one that shows anything: text, or a colored background
+fn content_len(cells: &[Cell]) -> usize {
+    cells.iter()
+        .rposition(|cell| !cell.ch.is_whitespace() || cell.style.bg != TermColor::Default || cell.style.inverse)
+        .map_or(0, |i| i + 1)
+}
+
+// The escape sequence that sets `style` from scratch
+fn sgr(style: &CellStyle) -> String {
+    let mut params = vec!["0".to_string()];
+    for (on, param) in [
+        (style.bold, "1"),
+        (style.dim, "2"),
+        (style.italic, "3"),
+        (style.underline, "4"),
+        (style.inverse, "7"),
+        (style.strikethrough, "9"),
+    ] {
+        if on {
+            params.push(param.to_string());
+        }
+    }
+    for (color, base, bright_base, extended) in [(style.fg, 30, 90, 38), (style.bg, 40, 100, 48)] {
+        match color {
+            TermColor::Default => {}
+            TermColor::Indexed(n @ 0..=7) => params.push((base + n as u16).to_string()),
+            TermColor::Indexed(n @ 8..=15) => params.push((bright_base + n as u16 - 8).to_string()),
+            TermColor::Indexed(n) => params.push(format!("{};5;{}", extended, n)),
+            TermColor::Rgb(r, g, b) => params.push(format!("{};2;{};{};{}", extended, r, g, b)),
+        }
+    }
+    format!("\x1b[{}m", params.join(";"))
+}
+
+fn css(style: &CellStyle, colors: &ExportColors) -> String {
+    let (color, background) = style.colors(colors.palette, colors.foreground, colors.background);
+    let mut css = format!("color: {}", hex(color));
+    if let Some(background) = background {
+        css.push_str(&format!("; background: {}", hex(background)));
+    }
+    if style.bold {
+        css.push_str("; font-weight: bold");
+    }
+    if style.italic {
+        css.push_str("; font-style: italic");
+    }
+    match (style.underline, style.strikethrough) {
+        (true, true) => css.push_str("; text-decoration: underline line-through"),
+        (true, false) => css.push_str("; text-decoration: underline"),
+        (false, true) => css.push_str("; text-decoration: line-through"),
+        (false, false) => {}
+    }
+    css
+}
+
+fn hex(color: egui::Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,70 @@
+use eframe::egui;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// Fonts ==============================================
+// JetBrains Mono is bundled; the config's `font` can put an installed family or a
+// font file in front of it for the terminal text.
+
+pub fn setup_fonts(ctx: &egui::Context, user_font: Option<&str>) {
+    let mut fonts = egui::FontDefinitions::default();
+    
+    // Load JetBrains Mono
+    fonts.font_data.insert("jetbrains".to_owned(), 
+        Arc::new(egui::FontData::from_static(include_bytes!("../assets/JetBrainsMono-2.304/fonts/ttf/JetBrainsMono-Regular.ttf")))
+    );
+
+    // For Monospace: JetBrains first, then egui's default fallbacks (which include some emoji support)
+    fonts.families.get_mut(&egui::FontFamily::Monospace).unwrap().insert(0, "jetbrains".to_owned());
+    
+    // For Proportional: JetBrains first, then egui's default fallbacks
+    fonts.families.get_mut(&egui::FontFamily::Proportional).unwrap().insert(0, "jetbrains".to_owned());
+
+    // The config's font goes in front for the terminal text, JetBrains Mono stays as a fallback
+    if let Some(font) = user_font {
+        match load_font(font) {
+            Ok(data) => {
+                fonts.font_data.insert("user".to_owned(), Arc::new(data));
+                fonts.families.get_mut(&egui::FontFamily::Monospace).unwrap().insert(0, "user".to_owned());
+            }
+            Err(e) => eprintln!("Warning: Failed to load font {}: {}", font, e),
+        }
+    }
+
+    ctx.set_fonts(fonts);
+}
+
+// A path if it looks like one, else a family name looked up among the system fonts
+fn load_font(font: &str) -> Result<egui::FontData, String> {
+    let is_path = font.contains('/')
+        || [".ttf", ".otf", ".ttc"].iter().any(|ext| font.to_lowercase().ends_with(ext));
+    if is_path {
+        let path = match (font.strip_prefix("~/"), std::env::var("HOME")) {
+            (Some(rest), Ok(home)) => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(font),
+        };
+        return std::fs::read(&path)
+            .map(egui::FontData::from_owned)
+            .map_err(|e| e.to_string());
+    }
+
+    let mut db = fontdb::Database::new();
+    db.load_system_fonts();
+    let query = fontdb::Query {
+        families: &[fontdb::Family::Name(font)],
+        weight: fontdb::Weight::NORMAL,
+        stretch: fontdb::Stretch::Normal,
+        style: fontdb::Style::Normal,
+    };
+    // Family names are matched exactly; fall back to ignoring case ("fira code")
+    let id = db.query(&query)
+        .or_else(|| db.faces()
+            .find(|face| face.families.iter().any(|(name, _)| name.eq_ignore_ascii_case(font)))
+            .map(|face| face.id))
+        .ok_or("no installed font with that family name")?;
+    db.with_face_data(id, |data, index| egui::FontData {
+        index,
+        ..egui::FontData::from_owned(data.to_vec())
+    })
+    .ok_or_else(|| "couldn't read the font file".to_string())
+}
@@ -0,0 +1,227 @@
+use eframe::egui;
+
+use crate::export::{export, ExportColors, ExportFormat};
+use crate::grid::{Cell, Grid};
+use crate::links::find_links;
+use crate::parser::{Tokenizer, Utf8Decoder};
+use crate::search::Search;
+use crate::selection::Selection;
+use crate::theme::default_palette;
+
+// Fuzzing ============================================
+// Random and adversarial output fed through the UTF-8 decoder, tokenizer and grid the
+// way a pane takes it in: in chunks split anywhere, with resizes in between. Nothing may
+// panic or hang, the screen keeps its shape, and where the output was split doesn't
+// change what ends up on screen. Cases are seeded, so a failure names the seed that
+// brings it back. For a longer run: FUZZ_CASES=100000 cargo test --release fuzz
+
+fn cases() -> u64 {
+    std::env::var("FUZZ_CASES").ok().and_then(|n| n.parse().ok()).unwrap_or(300)
+}
+
+// Output that has tripped up terminals before: unfinished and unterminated sequences,
+// huge and missing parameters, strings that never end, controls in odd places, 8-bit
+// controls, wide characters, combining marks and broken UTF-8
+const FRAGMENTS: &[&[u8]] = &[
+    b"\x1b", b"\x1b[", b"\x1b]", b"\x1bP", b"\x1b_", b"\x1b^", b"\x1bX", b"\x1b\\", b"\x1b(", b"\x1b#8",
+    b"\x07", b"\x18", b"\x1a", b"\x7f", b"\x08", b"\t", b"\r", b"\n", b"\r\n", b"\x0b", b"\x0c",
+    b"\x1b[?1049h", b"\x1b[?1049l", b"\x1b[?47h", b"\x1b[?47l", b"\x1b[?6h", b"\x1b[?6l", b"\x1b[?7l", b"\x1b[?7h",
+    b"\x1b[?25l", b"\x1b[?1000h", b"\x1b[?1006h", b"\x1b[?2004h",
+    b"\x1b[r", b"\x1b[5;2r", b"\x1b[2;5r", b"\x1b[0;0r", b"\x1b[65535;65535r",
+    b"\x1b[H", b"\x1b[999;999H", b"\x1b[65535;65535H", b"\x1b[0;0H", b"\x1b[99999999999999d", b"\x1b[65535G",
+    b"\x1b[A", b"\x1b[65535B", b"\x1b[65535C", b"\x1b[65535D", b"\x1b[65535E", b"\x1b[65535F",
+    b"\x1b[65535L", b"\x1b[65535M", b"\x1b[65535P", b"\x1b[65535@", b"\x1b[65535X", b"\x1b[65535S", b"\x1b[65535T",
+    b"\x1b[J", b"\x1b[1J", b"\x1b[2J", b"\x1b[3J", b"\x1b[K", b"\x1b[1K", b"\x1b[2K",
+    b"\x1b7", b"\x1b8", b"\x1bD", b"\x1bM", b"\x1bE", b"\x1bc",
+    b"\x1b[m", b"\x1b[1;2;3;4;7;9m", b"\x1b[38;5;300m", b"\x1b[38;5m", b"\x1b[38;2;1m", b"\x1b[48:2::1:2:3m", b"\x1b[38;2;999;999;999m",
+    b"\x1b]0;title\x07", b"\x1b]2;\x1b\\", b"\x1b]133;A\x07", b"\x1b]133;D;1\x1b\\", b"\x1b]133;D;x\x07",
+    b"\x1b]10;?\x07", b"\x1b]11;?;?\x07", b"\x1b]10;rgb:zz/1/2\x07", b"\x1b]12;#fff\x07", b"\x1b]110\x07",
+    b"\x1b[>1u", b"\x1b[>31u", b"\x1b[<65535u", b"\x1b[=5;3u", b"\x1b[?u",
+    b"\x1b[5n", b"\x1b[6n", b"\x1b[?6n", b"\x1b[c", b"\x1b[>c", b"\x1b[>q",
+    b"\x1bPq#0;2;0;0;0#0~~@@\x1b\\", b"\x1b_Gf=100;AAAA\x1b\\", b"\x1bXsos\x1b\\", b"\x1b^pm\x1b\\",
+    "\u{9b}31m".as_bytes(), "\u{9d}0;t\u{9c}".as_bytes(), "\u{90}".as_bytes(), "\u{9b}".as_bytes(),
+    "漢字".as_bytes(), "e\u{301}".as_bytes(), "\u{301}".as_bytes(), "👩\u{200d}👩\u{200d}👧".as_bytes(), "\u{200d}".as_bytes(), "\u{fe0f}".as_bytes(),
+    "🇯🇵".as_bytes(), "\u{1f3fb}".as_bytes(), "a\u{302}\u{303}\u{304}\u{305}".as_bytes(),
+    b"\xc3", b"\xff", b"\xe2\x82", b"\xf0\x9f\x98", b"\xed\xa0\x80",
+];
+
+fn random_output(rng: &mut fastrand::Rng) -> Vec<u8> {
+    let mut output = Vec::new();
+    for _ in 0..rng.usize(1..200) {
+        match rng.u8(0..6) {
+            0 => output.push(rng.u8(..)),
+            1 => output.extend(std::iter::repeat_n(b'x', rng.usize(0..300))),
+            // Parameters, separators and intermediates, to end up in whatever's open
+            2 => output.extend((0..rng.usize(1..20)).map(|_| b"0123456789;:?<>=! $\"'"[rng.usize(..21)])),
+            3 => output.extend(rng.u64(..).to_string().bytes()),
+            _ => output.extend_from_slice(FRAGMENTS[rng.usize(..FRAGMENTS.len())]),
+        }
+    }
+    output
+}
+
+// Splits output into chunks at random byte offsets, through the middle of sequences
+// and characters alike
+fn random_chunks<'a>(rng: &mut fastrand::Rng, mut output: &'a [u8]) -> Vec<&'a [u8]> {
+    let mut chunks = Vec::new();
+    while !output.is_empty() {
+        let (chunk, rest) = output.split_at(rng.usize(1..=output.len().min(64)));
+        chunks.push(chunk);
+        output = rest;
+    }
+    chunks
+}
+
+fn feed(grid: &mut Grid, decoder: &mut Utf8Decoder, chunk: &[u8]) {
+    grid.feed(&decoder.decode(chunk));
+}
+
+// What the grid must always hold to, whatever it was fed
+fn check_shape(grid: &Grid, cols: usize, rows: usize, seed: u64) {
+    assert_eq!(grid.rows().len(), rows, "seed {}: row count", seed);
+    assert_eq!(grid.cols(), cols, "seed {}: column count", seed);
+    let (row, col) = grid.cursor();
+    assert!(row < rows && col < cols, "seed {}: cursor at {:?} outside {}x{}", seed, (row, col), cols, rows);
+    for (index, cells) in grid.rows().iter().enumerate() {
+        assert_eq!(cells.len(), cols, "seed {}: row {} has {} cells", seed, index, cells.len());
+        check_wide_pairs(cells, seed);
+    }
+    assert!(grid.cursor_line() < grid.scrollback().len() + grid.rows().len(), "seed {}: cursor line past the end", seed);
+}
+
+// A wide character's cell is followed by the blank it covers, and that blank is only
+// ever behind one
+fn check_wide_pairs(cells: &[Cell], seed: u64) {
+    for (col, cell) in cells.iter().enumerate() {
+        match cell.width {
+            2 => assert!(cells.get(col + 1).is_some_and(|next| next.width == 0), "seed {}: wide character at {} has no second cell", seed, col),
+            0 => assert!(col > 0 && cells[col - 1].width == 2, "seed {}: covered cell at {} without a wide character", seed, col),
+            _ => {}
+        }
+    }
+}
+
+// Grid state that can be compared between runs
+#[derive(PartialEq)]
+struct Snapshot {
+    rows: Vec<Vec<Cell>>,
+    scrollback: Vec<Vec<Cell>>,
+    cursor: (usize, usize),
+    title: Option<String>,
+    responses: String,
+}
+
+fn snapshot(grid: &mut Grid) -> Snapshot {
+    Snapshot {
+        rows: grid.rows().to_vec(),
+        scrollback: grid.scrollback().iter().map(|line| line.to_vec()).collect(),
+        cursor: grid.cursor(),
+        title: grid.take_title(),
+        responses: grid.take_responses(),
+    }
+}
+
+#[test]
+fn random_output_keeps_the_grid_in_shape() {
+    for seed in 0..cases() {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let (mut cols, mut rows) = (rng.usize(1..100), rng.usize(1..40));
+        let mut grid = Grid::new(cols, rows);
+        let mut decoder = Utf8Decoder::default();
+        let output = random_output(&mut rng);
+        for chunk in random_chunks(&mut rng, &output) {
+            feed(&mut grid, &mut decoder, chunk);
+            check_shape(&grid, cols, rows, seed);
+            if rng.u8(0..10) == 0 {
+                (cols, rows) = (rng.usize(1..100), rng.usize(1..40));
+                grid.resize(cols, rows);
+                check_shape(&grid, cols, rows, seed);
+            }
+        }
+    }
+}
+
+#[test]
+fn chunk_boundaries_dont_change_the_screen() {
+    for seed in 0..cases() {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let (cols, rows) = (rng.usize(1..100), rng.usize(1..40));
+        let output = random_output(&mut rng);
+
+        let mut whole = Grid::new(cols, rows);
+        feed(&mut whole, &mut Utf8Decoder::default(), &output);
+
+        let mut split = Grid::new(cols, rows);
+        let mut decoder = Utf8Decoder::default();
+        for chunk in random_chunks(&mut rng, &output) {
+            feed(&mut split, &mut decoder, chunk);
+        }
+        assert!(snapshot(&mut whole) == snapshot(&mut split), "seed {}: output split into chunks came out differently", seed);
+    }
+}
+
+#[test]
+fn tokenizer_takes_any_text() {
+    for seed in 0..cases() {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        // Any characters at all, not just the ones the fragments make
+        let text: String = (0..rng.usize(0..2000)).map(|_| rng.char(..)).collect();
+        let mut tokenizer = Tokenizer::default();
+        let mut count = 0;
+        tokenizer.feed(&text, |_| count += 1);
+        assert!(count <= text.chars().count() + 1, "seed {}: more tokens than characters", seed);
+    }
+}
+
+// What's made of the output afterwards works on byte offsets into its text, which
+// must land between characters whatever the characters are
+#[test]
+fn output_text_is_sliced_between_characters() {
+    let colors = ExportColors { palette: &default_palette(), foreground: egui::Color32::WHITE, background: egui::Color32::BLACK };
+    for seed in 0..cases() / 10 {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let (cols, rows) = (rng.usize(1..60), rng.usize(1..20));
+        let mut grid = Grid::new(cols, rows);
+        grid.feed(&Utf8Decoder::default().decode(&random_output(&mut rng)));
+        let lines: Vec<_> = grid.main_lines().collect();
+
+        for cells in &lines {
+            let text = crate::grid::cells_text(cells);
+            for link in find_links(&text) {
+                assert!(text.is_char_boundary(link.start) && text.is_char_boundary(link.end), "seed {}: link at {}..{} in {:?}", seed, link.start, link.end, text);
+            }
+        }
+
+        let mut search = Search::default();
+        search.open();
+        search.query = ["x", ".", "漢", "\\w+", "e\u{301}", "[^x]"][rng.usize(..6)].to_string();
+        search.regex = rng.bool();
+        search.update(lines.iter());
+        for found in &search.matches {
+            assert!(found.start < found.end && found.end <= lines[found.line].len(), "seed {}: match {:?} outside its line", seed, found);
+        }
+
+        let at = |rng: &mut fastrand::Rng| (rng.usize(..lines.len() + 2), rng.usize(..cols + 3));
+        let mut selection = if rng.bool() { Selection::new(at(&mut rng)) } else { Selection::block(at(&mut rng)) };
+        selection.head = at(&mut rng);
+        selection.text(|line| lines.get(line).cloned());
+
+        for format in [ExportFormat::Text, ExportFormat::Ansi, ExportFormat::Html] {
+            export(lines.iter(), format, &colors);
+        }
+    }
+}
+
+#[test]
+fn unterminated_strings_stay_bounded() {
+    // A string that never ends mustn't hold on to everything after it, and the output
+    // after its terminator shows again
+    let mut grid = Grid::new(20, 5);
+    for prefix in ["\x1b]0;", "\x1bP", "\x1b_", "\x1b[", "\x1b[?"] {
+        grid.feed(prefix);
+        grid.feed(&"1;".repeat(100_000));
+        grid.feed("\x07\x1b\\\x1b[H\x1b[2Jok");
+        assert_eq!(grid.rows()[0][0].ch, 'o', "{:?} swallowed the output after it", prefix);
+        assert!(grid.take_title().is_none_or(|title| title.len() <= 4096));
+    }
+}
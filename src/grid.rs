@@ -0,0 +1,883 @@
+use crate::keys::KITTY_SUPPORTED;
+use crate::parser::{CellStyle, Token, Tokenizer};
+use crate::scrollback::{Scrollback, ScrollbackLimit};
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+// Grid ===============================================
+// Screen model: a fixed rows x cols array of styled cells plus a cursor, driven by the
+// escape sequences programs write. Lines scrolling off the top of the main screen go to
+// the scrollback; fullscreen programs (vim, htop, less) draw on the alternate screen.
+
+pub const DEFAULT_SCROLLBACK_LINES: usize = 10_000;
+const MAX_KEYBOARD_STACK: usize = 16;  // Pushes past this drop the oldest entry
+
+// One column of the screen. A double-width character (CJK, most emoji) takes two:
+// its own cell of width 2, then a cell of width 0 that it covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub combining: [char; 3],  // Accents, joiners and variation selectors after `ch` in its grapheme; '\0' for none
+    pub width: u8,
+    pub style: CellStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self { ch: ' ', combining: ['\0'; 3], width: 1, style: CellStyle::default() }
+    }
+}
+
+impl Cell {
+    // What the cell shows; nothing for the right half of a wide character
+    pub fn push_text(&self, text: &mut String) {
+        if self.width == 0 {
+            return;
+        }
+        text.push(self.ch);
+        text.extend(self.combining.iter().take_while(|&&c| c != '\0'));
+    }
+}
+
+// The text a run of cells shows
+pub fn cells_text(cells: &[Cell]) -> String {
+    let mut text = String::with_capacity(cells.len());
+    for cell in cells {
+        cell.push_text(&mut text);
+    }
+    text
+}
+
+pub struct Grid {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<Cell>>,
+    cursor_row: usize,
+    cursor_col: usize,
+    pub cursor_visible: bool,
+    pub app_cursor_keys: bool,  // DECSET 1 (DECCKM): arrows, Home and End send ESC O A rather than ESC [ A
+    pub bracketed_paste: bool,  // DECSET 2004: the program wants pastes wrapped in ESC[200~ ... ESC[201~
+    pub mouse_tracking: MouseTracking,  // DECSET 1000/1002/1003: the program wants mouse events
+    pub sgr_mouse: bool,  // DECSET 1006: report them as ESC[<b;x;yM rather than raw bytes
+    style: CellStyle,      // Applied to newly printed characters
+    scroll_top: usize,     // DECSTBM margins, inclusive: the rows that scroll, below a
+    scroll_bottom: usize,  // status line or above a command line a program keeps in place
+    origin_mode: bool,  // DECSET 6 (DECOM): cursor positions count from the top margin
+    autowrap: bool,  // DECSET 7 (DECAWM): printing past the last column goes on the next line
+    saved_cursor: Option<SavedCursor>,  // ESC 7 (DECSC)
+    wrap_pending: bool,    // Cursor is past the last column; wrap before the next print
+    tokenizer: Tokenizer,
+    saved_screen: Option<SavedScreen>,  // Main screen while the alternate screen is showing
+    scrollback: Scrollback,
+    bell: bool,  // BEL seen since the last take_bell()
+    title: Option<String>,  // Set by OSC 0/2 since the last take_title()
+    shell_marks: Vec<(ShellMark, usize)>,  // OSC 133 marks and their lines since the last take_shell_marks()
+    keyboard_stack: Vec<u8>,  // Kitty keyboard protocol flags the program pushed, the current ones last
+    responses: String,  // Replies to the program's queries since the last take_responses()
+    pub pane_colors: [Rgb; 3],  // The pane's own foreground, background and cursor colors, to answer queries with
+    program_colors: [Option<Rgb>; 3],  // Ones the program set instead (OSC 10/11/12)
+}
+
+pub type Rgb = (u8, u8, u8);
+
+// Shell integration marks (OSC 133, from FinalTerm), which a shell set up for it prints
+// around each prompt and command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShellMark {
+    PromptStart,   // A
+    CommandStart,  // B: the prompt is done, the user types from here
+    OutputStart,   // C: the command was submitted
+    CommandEnd(Option<i32>),  // D, with the command's exit status if the shell gave it
+}
+
+// Which mouse events the program asked for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseTracking {
+    Off,
+    Click,   // 1000: presses, releases and the wheel
+    Drag,    // 1002: also motion while a button is held
+    Motion,  // 1003: all motion
+}
+
+struct SavedScreen {
+    cells: Vec<Vec<Cell>>,
+    cursor: Option<(usize, usize)>,  // Only DECSET 1049 saves the cursor
+    keyboard_stack: Vec<u8>,  // Each screen has its own
+    saved_cursor: Option<SavedCursor>,  // And its own DECSC
+}
+
+// What DECSC saves and DECRC puts back: prompts save the cursor, draw elsewhere (a
+// right prompt, a status line) and come back to where typing goes
+#[derive(Clone, Copy)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    style: CellStyle,
+    wrap_pending: bool,
+    origin_mode: bool,
+}
+
+impl Grid {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        Self {
+            cols,
+            rows,
+            cells: vec![vec![Cell::default(); cols]; rows],
+            cursor_row: 0,
+            cursor_col: 0,
+            cursor_visible: true,
+            app_cursor_keys: false,
+            bracketed_paste: false,
+            mouse_tracking: MouseTracking::Off,
+            sgr_mouse: false,
+            style: CellStyle::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            origin_mode: false,
+            autowrap: true,
+            saved_cursor: None,
+            wrap_pending: false,
+            tokenizer: Tokenizer::default(),
+            saved_screen: None,
+            scrollback: Scrollback::new(ScrollbackLimit::Lines(DEFAULT_SCROLLBACK_LINES)),
+            bell: false,
+            title: None,
+            shell_marks: Vec::new(),
+            keyboard_stack: Vec::new(),
+            responses: String::new(),
+            pane_colors: [(255, 255, 255), (0, 0, 0), (255, 255, 255)],
+            program_colors: [None; 3],
+        }
+    }
+
+    pub fn rows(&self) -> &[Vec<Cell>] {
+        &self.cells
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_row, self.cursor_col)
+    }
+
+    pub fn scrollback(&self) -> &Scrollback {
+        &self.scrollback
+    }
+
+    // Rows of the main screen as normal mode shows them (below the scrollback): down
+    // to the cursor or the last row with something on it
+    pub fn screen_lines(&self) -> &[Vec<Cell>] {
+        let (screen, cursor_row) = match &self.saved_screen {
+            Some(saved) => (&saved.cells, saved.cursor.map_or(0, |(row, _)| row)),
+            None => (&self.cells, self.cursor_row),
+        };
+        let last_used = screen.iter().rposition(|row| row.iter().any(|cell| *cell != Cell::default())).unwrap_or(0);
+        &screen[..cursor_row.max(last_used) + 1]
+    }
+
+    // A line of normal mode's view: scrollback lines first, then the main screen
+    pub fn main_line(&self, index: usize) -> Option<Cow<'_, [Cell]>> {
+        let scrollback_len = self.scrollback.len();
+        if index < scrollback_len {
+            self.scrollback.get(index)
+        } else {
+            self.screen_lines().get(index - scrollback_len).map(|line| Cow::Borrowed(line.as_slice()))
+        }
+    }
+
+    // All of normal mode's view, with any scrollback on disk read back
+    pub fn main_lines(&self) -> impl Iterator<Item = Cow<'_, [Cell]>> {
+        self.scrollback.iter().chain(self.screen_lines().iter().map(|line| Cow::Borrowed(line.as_slice())))
+    }
+
+    // Index of the cursor's line, counting scrollback lines first
+    pub fn cursor_line(&self) -> usize {
+        self.scrollback.len() + self.cursor_row
+    }
+
+    pub fn set_scrollback_limit(&mut self, limit: ScrollbackLimit) {
+        self.scrollback.set_limit(limit);
+    }
+
+    // Counted as dropped lines
+    pub fn clear_scrollback(&mut self) {
+        self.scrollback.clear();
+    }
+
+    // How many lines fell off the top of the scrollback since the last call
+    pub fn take_dropped_lines(&mut self) -> usize {
+        self.scrollback.take_dropped()
+    }
+
+    // True if the bell rang since the last call. Coming from the tokenizer, a BEL
+    // that ends an OSC string never counts, even when the string spans several reads.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
+    // The latest window title a program set (OSC 0 or 2), if it changed since the last call
+    pub fn take_title(&mut self) -> Option<String> {
+        self.title.take()
+    }
+
+    // Shell integration marks seen since the last call, with the lines they're on
+    pub fn take_shell_marks(&mut self) -> Vec<(ShellMark, usize)> {
+        std::mem::take(&mut self.shell_marks)
+    }
+
+    // True while a fullscreen program has switched to the alternate screen
+    // The kitty keyboard protocol flags in effect (see keys.rs), 0 for the legacy encoding
+    pub fn keyboard_flags(&self) -> u8 {
+        self.keyboard_stack.last().copied().unwrap_or(0)
+    }
+
+    // Foreground, background and cursor colors the program picked, None for the pane's
+    pub fn program_colors(&self) -> [Option<Rgb>; 3] {
+        self.program_colors
+    }
+
+    // What to write back to the program
+    pub fn take_responses(&mut self) -> String {
+        std::mem::take(&mut self.responses)
+    }
+
+    pub fn alt_screen(&self) -> bool {
+        self.saved_screen.is_some()
+    }
+
+    // Change the screen size. Rows that no longer fit go off the top into the scrollback
+    // when they're above the cursor (so the prompt stays in view), else the bottom is cut.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        if (cols, rows) == (self.cols, self.rows) {
+            return;
+        }
+
+        let scrolled_off = fit_screen(&mut self.cells, cols, rows, self.cursor_row);
+        self.cursor_row -= scrolled_off.len();
+        if self.alt_screen() {
+            // The alternate screen has no scrollback; the main screen behind it does
+            if let Some(saved) = &mut self.saved_screen {
+                let cursor_row = saved.cursor.map_or(0, |(row, _)| row);
+                for line in fit_screen(&mut saved.cells, cols, rows, cursor_row) {
+                    self.scrollback.push(line);
+                }
+                let shift = cursor_row.saturating_sub(rows - 1);
+                saved.cursor = saved.cursor.map(|(row, col)| (row - shift, col.min(cols - 1)));
+            }
+        } else {
+            for line in scrolled_off {
+                self.scrollback.push(line);
+            }
+        }
+
+        self.cols = cols;
+        self.rows = rows;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.cursor_col = self.cursor_col.min(cols - 1);
+        self.wrap_pending = false;
+    }
+
+    pub fn clear(&mut self) {
+        for row in &mut self.cells {
+            row.fill(Cell::default());
+        }
+        self.cursor_row = 0;
+        self.cursor_col = 0;
+        self.wrap_pending = false;
+    }
+
+    pub fn feed(&mut self, text: &str) {
+        let mut tokens = Vec::new();
+        self.tokenizer.feed(text, |token| tokens.push(token));
+        for token in tokens {
+            self.handle(token);
+        }
+    }
+
+    fn handle(&mut self, token: Token) {
+        match token {
+            Token::Print(ch) => self.print(ch),
+            Token::Control(ch) => self.control(ch),
+            Token::Csi { private, params, action, .. } => self.csi(private, &params, action),
+            Token::Esc { intermediate: None, action } => self.esc(action),
+            Token::Osc(osc) => self.osc(&osc),
+            // Charsets, etc. don't affect the cells
+            Token::Esc { .. } => {}
+        }
+    }
+
+    fn print(&mut self, ch: char) {
+        // ASCII always starts a grapheme of its own
+        if !ch.is_ascii() && self.join_previous(ch) {
+            return;
+        }
+        // Columns as wcwidth() counts them, which is what programs lay their output out by
+        let width = match ch.width() {
+            Some(0) | None => return,  // A stray mark with nothing before it
+            Some(width) => width.min(self.cols),
+        };
+        // A wide character doesn't get split over two lines; it wraps early instead.
+        // Without autowrap, the last column gets overwritten.
+        if self.wrap_pending || self.cursor_col + width > self.cols {
+            if self.autowrap {
+                self.cursor_col = 0;
+                self.linefeed();
+            } else {
+                self.cursor_col = self.cols - width;
+            }
+            self.wrap_pending = false;
+        }
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        for covered in col..col + width {
+            self.split_wide(row, covered);
+        }
+        self.cells[row][col] = Cell { ch, width: width as u8, style: self.style, ..Cell::default() };
+        if width == 2 {
+            self.cells[row][col + 1] = Cell { width: 0, style: self.style, ..Cell::default() };
+        }
+        if col + width < self.cols {
+            self.cursor_col += width;
+        } else {
+            self.cursor_col = self.cols - 1;
+            self.wrap_pending = self.autowrap;
+        }
+    }
+
+    // Add `ch` to the character before the cursor if it continues that character's
+    // grapheme, like an accent or the rest of an emoji sequence. The cell keeps its
+    // width, the way wcwidth() sees it.
+    fn join_previous(&mut self, ch: char) -> bool {
+        let mut col = if self.wrap_pending {
+            self.cursor_col
+        } else if self.cursor_col > 0 {
+            self.cursor_col - 1
+        } else {
+            return false;
+        };
+        if self.cells[self.cursor_row][col].width == 0 && col > 0 {
+            col -= 1;
+        }
+        let cell = &mut self.cells[self.cursor_row][col];
+        let mut grapheme = String::new();
+        cell.push_text(&mut grapheme);
+        grapheme.push(ch);
+        if grapheme.graphemes(true).nth(1).is_some() {
+            return false;
+        }
+        // Past the marks a cell has room for, the rest are dropped
+        if let Some(slot) = cell.combining.iter_mut().find(|c| **c == '\0') {
+            *slot = ch;
+        }
+        true
+    }
+
+    // Overwriting either half of a wide character blanks the other half
+    fn split_wide(&mut self, row: usize, col: usize) {
+        let cells = &mut self.cells[row];
+        match cells[col].width {
+            0 if col > 0 => cells[col - 1] = Cell { style: cells[col - 1].style, ..Cell::default() },
+            2 if col + 1 < cells.len() => cells[col + 1] = Cell { style: cells[col + 1].style, ..Cell::default() },
+            _ => {}
+        }
+    }
+
+    fn control(&mut self, ch: char) {
+        match ch {
+            '\n' | '\x0b' | '\x0c' => self.linefeed(),
+            '\r' => self.cursor_col = 0,
+            '\x08' => self.cursor_col = self.cursor_col.saturating_sub(1),
+            '\t' => self.cursor_col = ((self.cursor_col / 8 + 1) * 8).min(self.cols - 1),
+            '\x07' => {
+                self.bell = true;
+                return;
+            }
+            _ => return,
+        }
+        self.wrap_pending = false;
+    }
+
+    fn esc(&mut self, action: char) {
+        match action {
+            'D' => self.linefeed(),  // IND
+            '7' => {
+                // DECSC
+                self.saved_cursor = Some(SavedCursor {
+                    row: self.cursor_row,
+                    col: self.cursor_col,
+                    style: self.style,
+                    wrap_pending: self.wrap_pending,
+                    origin_mode: self.origin_mode,
+                });
+            }
+            '8' => {
+                // DECRC; with nothing saved, the cursor goes home with the default style
+                let saved = self.saved_cursor.unwrap_or(SavedCursor {
+                    row: 0,
+                    col: 0,
+                    style: CellStyle::default(),
+                    wrap_pending: false,
+                    origin_mode: false,
+                });
+                self.cursor_row = saved.row.min(self.rows - 1);
+                self.cursor_col = saved.col.min(self.cols - 1);
+                self.style = saved.style;
+                self.wrap_pending = saved.wrap_pending && saved.col == self.cursor_col;
+                self.origin_mode = saved.origin_mode;
+            }
+            'E' => {
+                // NEL
+                self.cursor_col = 0;
+                self.linefeed();
+            }
+            'M' => {
+                // RI: reverse index
+                if self.cursor_row == self.scroll_top {
+                    self.scroll_down(self.scroll_top, 1);
+                } else {
+                    self.cursor_row = self.cursor_row.saturating_sub(1);
+                }
+            }
+            // DECKPAM/DECKPNM (keypad application mode) are deliberately ignored, not
+            // tracked: the mode only changes what numpad keys send (SS3 p..y, SS3 M), and
+            // egui-winit maps those to the main-row keys and drops the key location, so a
+            // numpad press can't be told apart. The numpad always sends what the main
+            // keys do, which is what DECKPNM asks for and what programs accept either way.
+            '=' | '>' => {}
+            'c' => {
+                // RIS: full reset
+                self.saved_screen = None;
+                self.clear();
+                self.style = CellStyle::default();
+                self.cursor_visible = true;
+                self.app_cursor_keys = false;
+                self.bracketed_paste = false;
+                self.mouse_tracking = MouseTracking::Off;
+                self.sgr_mouse = false;
+                self.keyboard_stack.clear();
+                self.program_colors = [None; 3];
+                self.scroll_top = 0;
+                self.scroll_bottom = self.rows - 1;
+                self.origin_mode = false;
+                self.autowrap = true;
+                self.saved_cursor = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn osc(&mut self, osc: &str) {
+        let (command, text) = osc.split_once(';').unwrap_or((osc, ""));
+        match command {
+            // "0;title" sets the icon name and title, "2;title" just the title
+            "0" | "2" => self.title = Some(text.chars().filter(|c| !c.is_control()).collect()),
+            // "133;A", "133;D;0" and so on; the main screen only, as that's where the lines stay
+            "133" if !self.alt_screen() => {
+                let mut fields = text.split(';');
+                let mark = match fields.next() {
+                    Some("A") => ShellMark::PromptStart,
+                    Some("B") => ShellMark::CommandStart,
+                    Some("C") => ShellMark::OutputStart,
+                    Some("D") => ShellMark::CommandEnd(fields.next().and_then(|code| code.parse().ok())),
+                    _ => return,
+                };
+                // Counting lines dropped since take_dropped_lines(), like the lines before
+                // them, as the terminal takes those off everything afterwards
+                self.shell_marks.push((mark, self.scrollback.dropped() + self.cursor_line()));
+            }
+            "10" | "11" | "12" => self.dynamic_colors(command, text),
+            // Back to the pane's colors
+            "110" => self.program_colors[0] = None,
+            "111" => self.program_colors[1] = None,
+            "112" => self.program_colors[2] = None,
+            _ => {}
+        }
+    }
+
+    // OSC 10/11/12: the foreground, background and cursor colors. "?" asks for one
+    // (vim does this to tell light backgrounds from dark), a color spec sets it, and
+    // several can follow each other: "10;?;?" asks for the foreground and background.
+    fn dynamic_colors(&mut self, command: &str, text: &str) {
+        let first = match command {
+            "10" => 0,
+            "11" => 1,
+            _ => 2,
+        };
+        for (index, spec) in (first..3).zip(text.split(';')) {
+            if spec == "?" {
+                let (r, g, b) = self.program_colors[index].unwrap_or(self.pane_colors[index]);
+                // 16 bits a channel, like xterm's replies
+                self.responses.push_str(&format!(
+                    "\x1b]{};rgb:{:02x}{:02x}/{:02x}{:02x}/{:02x}{:02x}\x1b\\",
+                    10 + index, r, r, g, g, b, b,
+                ));
+            } else if let Some(color) = parse_color_spec(spec) {
+                self.program_colors[index] = Some(color);
+            }
+        }
+    }
+
+    fn linefeed(&mut self) {
+        if self.cursor_row == self.scroll_bottom {
+            self.scroll_up(self.scroll_top, 1, true);
+        } else if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        }
+    }
+
+    // Scroll the rows from `top` to the bottom margin up, blank lines coming in at the
+    // bottom. Lines scrolled off the top of the main screen go to the scrollback if
+    // `keep`; whatever fullscreen programs draw stays out of it, and so do lines
+    // leaving a region that doesn't start at the top.
+    fn scroll_up(&mut self, top: usize, count: usize, keep: bool) {
+        let bottom = self.scroll_bottom;
+        for _ in 0..count.min(bottom + 1 - top) {
+            let line = self.cells.remove(top);
+            self.cells.insert(bottom, vec![Cell::default(); self.cols]);
+            if keep && top == 0 && !self.alt_screen() {
+                self.scrollback.push(line);
+            }
+        }
+    }
+
+    // And down, blank lines coming in at `top`
+    fn scroll_down(&mut self, top: usize, count: usize) {
+        let bottom = self.scroll_bottom;
+        for _ in 0..count.min(bottom + 1 - top) {
+            self.cells.remove(bottom);
+            self.cells.insert(top, vec![Cell::default(); self.cols]);
+        }
+    }
+
+    // IL/DL: lines go in or out at the cursor, moving the ones below it as far as the
+    // bottom margin; nothing happens with the cursor outside the margins
+    fn insert_lines(&mut self, count: usize) {
+        if (self.scroll_top..=self.scroll_bottom).contains(&self.cursor_row) {
+            self.scroll_down(self.cursor_row, count);
+            self.cursor_col = 0;
+        }
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if (self.scroll_top..=self.scroll_bottom).contains(&self.cursor_row) {
+            self.scroll_up(self.cursor_row, count, false);
+            self.cursor_col = 0;
+        }
+    }
+
+    // ICH/DCH: blanks go in at the cursor or characters come out, the rest of the line
+    // moving right (falling off the end) or left
+    fn insert_chars(&mut self, count: usize) {
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        self.erase_cells(row, col, col);
+        let count = count.min(self.cols - col);
+        let line = &mut self.cells[row];
+        line[col..].rotate_right(count);
+        line[col..col + count].fill(Cell::default());
+        if line[self.cols - 1].width == 2 {
+            line[self.cols - 1] = Cell::default();
+        }
+    }
+
+    fn delete_chars(&mut self, count: usize) {
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        let end = (col + count).min(self.cols);
+        self.erase_cells(row, col, end);
+        self.cells[row][col..].rotate_left(end - col);
+    }
+
+    // Blank the cells from `from` up to `to`, and the other half of any wide character
+    // sticking out of either end
+    fn erase_cells(&mut self, row: usize, from: usize, to: usize) {
+        let line = &mut self.cells[row];
+        if from > 0 && line.get(from).is_some_and(|cell| cell.width == 0) {
+            line[from - 1] = Cell { style: line[from - 1].style, ..Cell::default() };
+        }
+        if line.get(to).is_some_and(|cell| cell.width == 0) {
+            line[to] = Cell { style: line[to].style, ..Cell::default() };
+        }
+        line[from..to].fill(Cell::default());
+    }
+
+    fn csi(&mut self, private: Option<char>, params: &[u16], action: char) {
+        // Missing or zero parameters mean 1 for movement commands
+        let arg = |i: usize| params.get(i).copied().filter(|&n| n > 0).unwrap_or(1) as usize;
+
+        if action == 'u' && private.is_some() {
+            self.keyboard_protocol(private, params);
+            return;
+        }
+        if matches!(action, 'c' | 'n' | 'q') {
+            self.answer_query(private, params, action);
+            return;
+        }
+        if private == Some('?') {
+            if matches!(action, 'h' | 'l') {
+                for &mode in params {
+                    match mode {
+                        1 => self.app_cursor_keys = action == 'h',
+                        6 => {
+                            self.origin_mode = action == 'h';
+                            self.cursor_home();
+                        }
+                        7 => self.autowrap = action == 'h',
+                        25 => self.cursor_visible = action == 'h',
+                        47 | 1047 | 1049 => self.set_alt_screen(action == 'h', mode == 1049),
+                        1000 | 1002 | 1003 if action == 'l' => self.mouse_tracking = MouseTracking::Off,
+                        1000 => self.mouse_tracking = MouseTracking::Click,
+                        1002 => self.mouse_tracking = MouseTracking::Drag,
+                        1003 => self.mouse_tracking = MouseTracking::Motion,
+                        1006 => self.sgr_mouse = action == 'h',
+                        2004 => self.bracketed_paste = action == 'h',
+                        _ => {}
+                    }
+                }
+            }
+            return;
+        }
+        if private.is_some() {
+            return;
+        }
+
+        // Moving up or down stops at the margins, from inside them
+        let top = if self.cursor_row >= self.scroll_top { self.scroll_top } else { 0 };
+        let bottom = if self.cursor_row <= self.scroll_bottom { self.scroll_bottom } else { self.rows - 1 };
+        // Where row 1 is, and the last row positions can reach
+        let (origin, last_row) = if self.origin_mode { (self.scroll_top, self.scroll_bottom) } else { (0, self.rows - 1) };
+
+        self.wrap_pending = false;
+        match action {
+            'A' => self.cursor_row = self.cursor_row.saturating_sub(arg(0)).max(top),
+            'B' | 'e' => self.cursor_row = (self.cursor_row + arg(0)).min(bottom),
+            'C' | 'a' => self.cursor_col = (self.cursor_col + arg(0)).min(self.cols - 1),
+            'D' => self.cursor_col = self.cursor_col.saturating_sub(arg(0)),
+            'E' => {
+                self.cursor_row = (self.cursor_row + arg(0)).min(bottom);
+                self.cursor_col = 0;
+            }
+            'F' => {
+                self.cursor_row = self.cursor_row.saturating_sub(arg(0)).max(top);
+                self.cursor_col = 0;
+            }
+            'G' | '`' => self.cursor_col = (arg(0) - 1).min(self.cols - 1),
+            'd' => self.cursor_row = (origin + arg(0) - 1).min(last_row),
+            'H' | 'f' => {
+                self.cursor_row = (origin + arg(0) - 1).min(last_row);
+                self.cursor_col = (arg(1) - 1).min(self.cols - 1);
+            }
+            'J' => self.erase_in_display(params.first().copied().unwrap_or(0)),
+            'K' => self.erase_in_line(params.first().copied().unwrap_or(0)),
+            'm' => self.style.apply_sgr(params),
+            'r' => {
+                // DECSTBM: margins, the whole screen without parameters; the cursor goes home
+                let top = arg(0) - 1;
+                let bottom = params.get(1).copied().filter(|&n| n > 0).map_or(self.rows, |n| (n as usize).min(self.rows)) - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.cursor_home();
+                }
+            }
+            // SCOSC/SCORC, the same as ESC 7 and ESC 8
+            's' => self.esc('7'),
+            'u' => self.esc('8'),
+            'S' => self.scroll_up(self.scroll_top, arg(0), true),
+            'T' => self.scroll_down(self.scroll_top, arg(0)),
+            'L' => self.insert_lines(arg(0)),
+            'M' => self.delete_lines(arg(0)),
+            '@' => self.insert_chars(arg(0)),
+            'P' => self.delete_chars(arg(0)),
+            'X' => {
+                // ECH: blank characters from the cursor on, without moving it
+                let (row, col) = (self.cursor_row, self.cursor_col);
+                self.erase_cells(row, col, (col + arg(0)).min(self.cols));
+            }
+            _ => {}
+        }
+    }
+
+    // The top left corner, or of the margins in origin mode
+    fn cursor_home(&mut self) {
+        self.cursor_row = if self.origin_mode { self.scroll_top } else { 0 };
+        self.cursor_col = 0;
+        self.wrap_pending = false;
+    }
+
+    // Programs (vim, tmux, fish, anything using a terminfo-less library) ask what the
+    // terminal is and where the cursor is, and some wait for the answer before going on.
+    // The replies are xterm's.
+    fn answer_query(&mut self, private: Option<char>, params: &[u16], action: char) {
+        let param = params.first().copied().unwrap_or(0);
+        let origin = if self.origin_mode { self.scroll_top } else { 0 };
+        let (row, col) = (self.cursor_row.saturating_sub(origin) + 1, self.cursor_col + 1);
+        let reply = match (private, action, param) {
+            // DA1: a VT220-class terminal (62) with ANSI color (22)
+            (None, 'c', 0) => "\x1b[?62;22c".to_string(),
+            // DA2: terminal type, version, ROM cartridge (always 0)
+            (Some('>'), 'c', 0) => format!("\x1b[>1;{};0c", version_number()),
+            // DSR: status is fine, and the cursor position report
+            (None, 'n', 5) => "\x1b[0n".to_string(),
+            (None, 'n', 6) => format!("\x1b[{};{}R", row, col),
+            (Some('?'), 'n', 6) => format!("\x1b[?{};{}R", row, col),
+            // XTVERSION: name and version, as a DCS string
+            (Some('>'), 'q', 0) => format!("\x1bP>|sigmaterm({})\x1b\\", env!("CARGO_PKG_VERSION")),
+            _ => return,
+        };
+        self.responses.push_str(&reply);
+    }
+
+    // Kitty keyboard protocol: CSI > flags u pushes, CSI < count u pops, CSI = flags ; mode u
+    // changes the current flags (1 sets them, 2 adds to them, 3 takes them away), and
+    // CSI ? u asks for them. Only the flags keys.rs can report are kept, so the answer
+    // tells the program what it gets.
+    fn keyboard_protocol(&mut self, private: Option<char>, params: &[u16]) {
+        let flags = params.first().copied().unwrap_or(0) as u8 & KITTY_SUPPORTED;
+        match private {
+            Some('>') => {
+                if self.keyboard_stack.len() >= MAX_KEYBOARD_STACK {
+                    self.keyboard_stack.remove(0);
+                }
+                self.keyboard_stack.push(flags);
+            }
+            Some('<') => {
+                let count = params.first().copied().unwrap_or(1).max(1) as usize;
+                self.keyboard_stack.truncate(self.keyboard_stack.len().saturating_sub(count));
+            }
+            Some('=') => {
+                let current = self.keyboard_flags();
+                let flags = match params.get(1).copied().unwrap_or(1) {
+                    2 => current | flags,
+                    3 => current & !flags,
+                    _ => flags,
+                };
+                match self.keyboard_stack.last_mut() {
+                    Some(top) => *top = flags,
+                    None => self.keyboard_stack.push(flags),
+                }
+            }
+            Some('?') => self.responses.push_str(&format!("\x1b[?{}u", self.keyboard_flags())),
+            _ => {}
+        }
+    }
+
+    fn set_alt_screen(&mut self, enable: bool, save_cursor: bool) {
+        if enable && self.saved_screen.is_none() {
+            let blank = vec![vec![Cell::default(); self.cols]; self.rows];
+            self.saved_screen = Some(SavedScreen {
+                cells: std::mem::replace(&mut self.cells, blank),
+                cursor: save_cursor.then_some((self.cursor_row, self.cursor_col)),
+                keyboard_stack: std::mem::take(&mut self.keyboard_stack),
+                saved_cursor: self.saved_cursor.take(),
+            });
+        } else if !enable && let Some(saved) = self.saved_screen.take() {
+            self.cells = saved.cells;
+            self.keyboard_stack = saved.keyboard_stack;
+            self.saved_cursor = saved.saved_cursor;
+            if let Some((row, col)) = saved.cursor {
+                self.cursor_row = row;
+                self.cursor_col = col;
+            }
+        }
+        self.wrap_pending = false;
+    }
+
+    // ED: 0 erases from the cursor to the end of the screen, 1 from the start to the
+    // cursor, 2 all of it, and 3 the scrollback (what `clear` sends after 2)
+    fn erase_in_display(&mut self, mode: u16) {
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                for row in &mut self.cells[self.cursor_row + 1..] {
+                    row.fill(Cell::default());
+                }
+            }
+            1 => {
+                self.erase_in_line(1);
+                for row in &mut self.cells[..self.cursor_row] {
+                    row.fill(Cell::default());
+                }
+            }
+            2 => {
+                for row in &mut self.cells {
+                    row.fill(Cell::default());
+                }
+            }
+            3 => self.clear_scrollback(),
+            _ => {}
+        }
+    }
+
+    // EL: the same within the cursor's line
+    fn erase_in_line(&mut self, mode: u16) {
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        match mode {
+            0 => self.erase_cells(row, col, self.cols),
+            1 => self.erase_cells(row, 0, col + 1),
+            2 => self.erase_cells(row, 0, self.cols),
+            _ => {}
+        }
+    }
+}
+
+// X11 color specs: rgb:r/g/b with 1-4 hex digits a channel, or #rgb / #rrggbb
+fn parse_color_spec(spec: &str) -> Option<Rgb> {
+    let channels: Vec<u8> = if let Some(rgb) = spec.strip_prefix("rgb:") {
+        rgb.split('/')
+            .map(|channel| {
+                let value = u32::from_str_radix(channel, 16).ok().filter(|_| (1..=4).contains(&channel.len()))?;
+                // Scale to 8 bits whatever the number of digits
+                let max = (1u32 << (4 * channel.len())) - 1;
+                Some((value * 255 / max) as u8)
+            })
+            .collect::<Option<_>>()?
+    } else {
+        let hex = spec.strip_prefix('#')?;
+        let digits = match hex.len() {
+            3 => 1,
+            6 => 2,
+            _ => return None,
+        };
+        (0..3)
+            .map(|i| {
+                let value = u8::from_str_radix(hex.get(i * digits..(i + 1) * digits)?, 16).ok()?;
+                Some(if digits == 1 { value * 17 } else { value })
+            })
+            .collect::<Option<_>>()?
+    };
+    match channels[..] {
+        [r, g, b] => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+// The package version as one number the way DA2 reports it, 1.2.3 as 10203
+fn version_number() -> u32 {
+    env!("CARGO_PKG_VERSION").split('.')
+        .take(3)
+        .fold(0, |number, part| number * 100 + part.parse::<u32>().unwrap_or(0))
+}
+
+// Resize a screen to rows x cols, returning the rows taken off the top to keep
+// `cursor_row` on screen
+fn fit_screen(cells: &mut Vec<Vec<Cell>>, cols: usize, rows: usize, cursor_row: usize) -> Vec<Vec<Cell>> {
+    for row in cells.iter_mut() {
+        row.resize(cols, Cell::default());
+        // Narrowed through the middle of a wide character
+        if let Some(last) = row.last_mut()
+            && last.width == 2
+        {
+            *last = Cell::default();
+        }
+    }
+    let from_top = (cursor_row + 1).saturating_sub(rows).min(cells.len());
+    let scrolled_off: Vec<Vec<Cell>> = cells.drain(..from_top).collect();
+    cells.resize(rows, vec![Cell::default(); cols]);
+    scrolled_off
+}
@@ -1,6 +1,11 @@
 use eframe::egui;
+use std::path::PathBuf;
 
-use crate::{header, utils::{self, ColorSet, get_set_from_hue, window_button}};
+use crate::background::{BackgroundImage, BackgroundTexture, MAX_BLUR};
+use crate::export::ExportFormat;
+use crate::output_log::LogOptions;
+use crate::theme::{apply_edits, default_palette, schemes, ColorScheme, Palette, PaletteEdits, COLOR_NAMES};
+use crate::utils::{self, ColorSet, get_set_from_hue, window_button, DEFAULT_LIGHTNESS, DEFAULT_SATURATION};
 
 // Header action signals
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -8,45 +13,325 @@ pub enum HeaderAction {
     None,
     CloseTerminal,
     MaximizeTerminal,
-    MinimizeTerminal
+    MinimizeTerminal,
+    RestartShell,
+    StartLog,  // With the path and options from get_log_request
+    StopLog,
+    Export(ExportFormat),  // To the path from get_export_path
 }
 
 // Emoji Picker =======================================
+// Popup grid for choosing the icon shown before a terminal's title, filtered by
+// typing a word like "fire" or "server"
 
-pub struct EmojiPicker {
+// Each emoji with the words it's found by
+const EMOJIS: &[(&str, &str)] = &[
+    ("🖥", "computer desktop screen"),
+    ("💻", "laptop computer"),
+    ("⌨", "keyboard"),
+    ("🐚", "shell"),
+    ("🐧", "penguin linux"),
+    ("🐳", "whale docker container"),
+    ("🐍", "snake python"),
+    ("🦀", "crab rust"),
+    ("☕", "coffee java"),
+    ("💎", "gem ruby"),
+    ("🐹", "hamster go"),
+    ("🐘", "elephant php postgres"),
+    ("🐬", "dolphin mysql"),
+    ("🍃", "leaf mongo"),
+    ("🗄", "cabinet database db"),
+    ("🌐", "globe web network"),
+    ("☁", "cloud"),
+    ("📡", "antenna satellite remote ssh"),
+    ("🔌", "plug connection"),
+    ("🔒", "lock secure"),
+    ("🔑", "key auth"),
+    ("🛡", "shield security"),
+    ("⚙", "gear settings config"),
+    ("🔧", "wrench tool fix"),
+    ("🔨", "hammer build"),
+    ("🛠", "tools build"),
+    ("🏗", "construction build"),
+    ("📦", "package box"),
+    ("🚀", "rocket deploy launch"),
+    ("🧪", "test tube test"),
+    ("🔬", "microscope debug"),
+    ("🐛", "bug debug"),
+    ("📊", "chart stats monitor"),
+    ("📈", "chart up metrics"),
+    ("📉", "chart down"),
+    ("📜", "scroll log"),
+    ("📝", "memo note edit"),
+    ("📄", "page document file"),
+    ("📁", "folder directory"),
+    ("📂", "open folder"),
+    ("🗂", "dividers files"),
+    ("🔍", "search find"),
+    ("⏱", "stopwatch timer"),
+    ("⏰", "alarm clock"),
+    ("📅", "calendar"),
+    ("📬", "mailbox mail"),
+    ("💬", "speech chat"),
+    ("🔔", "bell notify"),
+    ("⚡", "lightning fast power"),
+    ("🔥", "fire hot"),
+    ("💧", "droplet water"),
+    ("🌱", "seedling new"),
+    ("🌲", "tree"),
+    ("🌙", "moon night"),
+    ("☀", "sun day"),
+    ("⭐", "star favorite"),
+    ("✨", "sparkles new"),
+    ("❤", "heart"),
+    ("✅", "check done ok"),
+    ("❌", "cross fail"),
+    ("⚠", "warning"),
+    ("❓", "question"),
+    ("🎯", "target goal"),
+    ("🎮", "game controller"),
+    ("🎵", "music note"),
+    ("🎨", "palette art design"),
+    ("🧩", "puzzle piece"),
+    ("🧠", "brain ai"),
+    ("🤖", "robot bot ai"),
+    ("👾", "alien monster"),
+    ("👻", "ghost"),
+    ("💀", "skull danger"),
+    ("🐱", "cat"),
+    ("🐶", "dog"),
+    ("🦊", "fox"),
+    ("🐢", "turtle slow"),
+    ("🦄", "unicorn"),
+    ("🍕", "pizza"),
+    ("🍺", "beer brew"),
+    ("🏠", "house home"),
+    ("🏢", "office work"),
+    ("🧹", "broom clean"),
+    ("🗑", "trash delete"),
+    ("💰", "money"),
+    ("🔴", "red circle"),
+    ("🟠", "orange circle"),
+    ("🟡", "yellow circle"),
+    ("🟢", "green circle"),
+    ("🔵", "blue circle"),
+    ("🟣", "purple circle"),
+];
 
+#[derive(Clone, Default)]
+pub struct EmojiPicker {
+    query: String,
 }
 
-impl Default for EmojiPicker {
-    fn default() -> Self {
-        Self {
+impl EmojiPicker {
+    // The grid and its filter. Some(Some(emoji)) once one is picked, Some(None) to remove the icon.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<Option<String>> {
+        let mut picked = None;
+        let search = ui.add(egui::TextEdit::singleline(&mut self.query).hint_text("Search").desired_width(f32::INFINITY));
+        if !search.has_focus() && self.query.is_empty() {
+            search.request_focus();
+        }
+        let query = self.query.trim().to_lowercase();
+        let matches: Vec<&str> = EMOJIS.iter()
+            .filter(|(_, words)| query.is_empty() || words.split(' ').any(|word| word.starts_with(&query)))
+            .map(|(emoji, _)| *emoji)
+            .collect();
 
+        ui.add_space(4.0);
+        egui::ScrollArea::vertical().max_height(180.0).show(ui, |ui| {
+            egui::Grid::new("emoji_grid").spacing([2.0, 2.0]).show(ui, |ui| {
+                for (index, emoji) in matches.iter().enumerate() {
+                    if ui.add(egui::Button::new(egui::RichText::new(*emoji).size(20.0)).frame(false).min_size(egui::vec2(28.0, 28.0))).clicked() {
+                        picked = Some(Some(emoji.to_string()));
+                    }
+                    if index % 8 == 7 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+        if matches.is_empty() {
+            ui.label(egui::RichText::new("No match").weak());
+        }
+        // Enter takes the first match
+        if search.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))
+            && let Some(first) = matches.first()
+        {
+            picked = Some(Some(first.to_string()));
+        }
+        ui.separator();
+        if ui.button("No icon").clicked() {
+            picked = Some(None);
+        }
+        if picked.is_some() {
+            self.query.clear();
         }
+        picked
     }
 }
+
 // Color Picker =======================================
+// Popup with preset palettes and the hues picked lately (in any pane), so a pane can
+// be recolored in one click instead of with the hue slider, saturation and lightness
+// for pastel or high-contrast panes, the color schemes, and the pane's 16 ANSI colors
+// to edit
+
+const MAX_RECENT_HUES: usize = 8;
 
+#[derive(Clone)]
 pub struct ColorPicker {
-    pub color_sets: Vec<ColorSet>,
-    pub selected_index: usize
+    pub presets: Vec<(f32, ColorSet)>,  // Hue and the palette made from it
 }
 
 impl Default for ColorPicker {
     fn default() -> Self {
         Self {
-            color_sets: vec![
-                get_set_from_hue(180.0),
-                get_set_from_hue(105.0),
-                get_set_from_hue(57.0),
-                get_set_from_hue(280.0)
-            ],
-            selected_index: 0
+            presets: [180.0, 105.0, 57.0, 280.0, 0.0, 30.0, 215.0, 330.0]
+                .into_iter()
+                .map(|hue| (hue, get_set_from_hue(hue)))
+                .collect(),
         }
     }
 }
 
+// What was chosen in the color picker
+pub enum ColorPick {
+    Hue(f32),  // Also goes back to following the hue
+    Shade(f32, f32),  // Saturation and lightness
+    Scheme(Option<String>),
+}
+
+impl ColorPicker {
+    // The swatches and the scheme list; what was clicked, if anything. ANSI colors
+    // picked go straight into `edits`, over `palette`.
+    pub fn show(&mut self, ui: &mut egui::Ui, current_hue: f32, current_shade: (f32, f32), current_scheme: Option<&str>, palette: &Palette, edits: &mut PaletteEdits) -> Option<ColorPick> {
+        let mut picked = None;
+        ui.label("Presets");
+        ui.horizontal_wrapped(|ui| {
+            for (hue, set) in &self.presets {
+                if swatch(ui, set, *hue == current_hue).on_hover_text(format!("Hue {:.0}", hue)).clicked() {
+                    picked = Some(ColorPick::Hue(*hue));
+                }
+            }
+        });
+
+        let recent = recent_hues(ui.ctx());
+        if !recent.is_empty() {
+            ui.add_space(4.0);
+            ui.label("Recent");
+            ui.horizontal_wrapped(|ui| {
+                for hue in recent {
+                    if swatch(ui, &get_set_from_hue(hue), hue == current_hue).on_hover_text(format!("Hue {:.0}", hue)).clicked() {
+                        picked = Some(ColorPick::Hue(hue));
+                    }
+                }
+            });
+        }
+
+        ui.add_space(4.0);
+        ui.label("Shade");
+        let (mut saturation, mut lightness) = current_shade;
+        let saturation_changed = ui.add(egui::Slider::new(&mut saturation, 0.0..=1.0).text("Saturation")).changed();
+        let lightness_changed = ui.add(egui::Slider::new(&mut lightness, 0.2..=0.9).text("Lightness")).changed();
+        if saturation_changed || lightness_changed {
+            picked = Some(ColorPick::Shade(saturation, lightness));
+        }
+        if current_shade != (DEFAULT_SATURATION, DEFAULT_LIGHTNESS) && ui.button("Default shade").clicked() {
+            picked = Some(ColorPick::Shade(DEFAULT_SATURATION, DEFAULT_LIGHTNESS));
+        }
+
+        ui.add_space(4.0);
+        ui.label("Scheme");
+        egui::ComboBox::from_id_salt("color_scheme")
+            .selected_text(current_scheme.unwrap_or("Follow hue"))
+            .width(150.0)
+            .show_ui(ui, |ui| {
+                if ui.selectable_label(current_scheme.is_none(), "Follow hue").clicked() {
+                    picked = Some(ColorPick::Scheme(None));
+                }
+                for scheme in schemes() {
+                    if ui.selectable_label(current_scheme == Some(scheme.name.as_str()), &scheme.name).clicked() {
+                        picked = Some(ColorPick::Scheme(Some(scheme.name.clone())));
+                    }
+                }
+            });
+
+        ui.add_space(4.0);
+        egui::CollapsingHeader::new("ANSI colors").show(ui, |ui| {
+            // Normal colors on the first row, bright ones under them
+            ui.spacing_mut().interact_size = egui::vec2(16.0, 16.0);
+            ui.spacing_mut().item_spacing.x = 4.0;
+            egui::Grid::new("ansi_colors").show(ui, |ui| {
+                for (index, name) in COLOR_NAMES.iter().enumerate() {
+                    let mut color = palette[index];
+                    let response = egui::color_picker::color_edit_button_srgba(ui, &mut color, egui::color_picker::Alpha::Opaque);
+                    if response.changed() {
+                        edits[index] = Some(color);
+                    }
+                    response.on_hover_text(*name);
+                    if index == 7 {
+                        ui.end_row();
+                    }
+                }
+            });
+            if edits.iter().any(Option::is_some) && ui.button("Reset").clicked() {
+                *edits = [None; 16];
+            }
+        });
+        picked
+    }
+}
+
+// A square of the palette's primary color over its dark background
+fn swatch(ui: &mut egui::Ui, set: &ColorSet, selected: bool) -> egui::Response {
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(28.0, 28.0), egui::Sense::click());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 4.0, set.dark);
+    painter.rect_filled(rect.shrink(5.0), 3.0, set.primary);
+    if selected || response.hovered() {
+        painter.rect_stroke(rect, 4.0, egui::Stroke::new(2.0, set.light), egui::StrokeKind::Inside);
+    }
+    response
+}
+
+// Hues picked lately, newest first, shared by all panes through egui's memory
+fn recent_hues(ctx: &egui::Context) -> Vec<f32> {
+    ctx.data(|d| d.get_temp::<Vec<f32>>(recent_hues_id())).unwrap_or_default()
+}
+
+fn remember_hue(ctx: &egui::Context, hue: f32) {
+    ctx.data_mut(|d| {
+        let recent = d.get_temp_mut_or_default::<Vec<f32>>(recent_hues_id());
+        recent.retain(|&h| h != hue);
+        recent.insert(0, hue);
+        recent.truncate(MAX_RECENT_HUES);
+    });
+}
+
+fn recent_hues_id() -> egui::Id {
+    egui::Id::new("recent_hues")
+}
 
 // Header =============================================
+// What happened in a pane while it wasn't being looked at, marked on its header and
+// tab; the later ones win when there's more than one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Badge {
+    Activity,  // New output
+    Silence,   // Output, then nothing for silence_seconds
+    Bell,
+}
+
+impl Badge {
+    pub fn icon(self) -> &'static str {
+        match self {
+            Badge::Activity => "•",
+            Badge::Silence => "💤",
+            Badge::Bell => "🔔",
+        }
+    }
+}
+
 #[derive(Clone, PartialEq)]
 pub enum ColorMode {
     Light, 
@@ -56,12 +341,36 @@ pub enum ColorMode {
 #[derive(Clone)]
 pub struct Header {
     emoji_picker_open: bool,
+    emoji_picker: EmojiPicker,
+    icon: Option<String>,  // Emoji shown before the title
     color_picker_open: bool,
+    color_picker: ColorPicker,
+    scheme: Option<ColorScheme>,  // Colors for the output instead of the hue's
+    default_palette: Palette,  // ANSI colors when following the hue
+    palette_edits: PaletteEdits,  // ANSI colors changed in this pane, over either
+    opacity: f32,  // Of the pane's background, with the desktop showing through
+    background_image: Option<BackgroundImage>,
+    background_texture: BackgroundTexture,
+    background_path: String,  // Being typed in the appearance popup
+    log_open: bool,
+    log_path: String,  // Being typed in the log popup
+    log_options: LogOptions,
+    logging: Option<PathBuf>,  // File the output is going to
+    log_error: Option<String>,  // Why the last file couldn't be opened
+    export_path: String,  // Being typed in the output popup
+    export_status: Option<String>,  // Where the last export went, or why it failed
     title: String,
     pub color_set: ColorSet,
     pub color_mode: ColorMode,
+    program_colors: [Option<egui::Color32>; 3],  // Foreground, background and cursor the running program set (OSC 10/11/12)
     is_editing_title: bool,
+    title_before_edit: String,
+    renamed: bool,  // The user named the pane, so titles set by programs are ignored
+    badge: Option<Badge>,  // Shown before the title
+    process: Option<String>,  // Program running in the pane, shown after the title
     hue: f32,  // Store current hue value
+    saturation: f32,
+    lightness: f32,
     is_maximized: bool
 }
 
@@ -70,11 +379,35 @@ impl Default for Header {
         Self{
             title: "Untitled Terminal".to_string(),
             emoji_picker_open: false,
+            emoji_picker: EmojiPicker::default(),
+            icon: None,
             color_picker_open: false,
+            color_picker: ColorPicker::default(),
+            scheme: None,
+            default_palette: default_palette(),
+            palette_edits: [None; 16],
+            opacity: 1.0,
+            background_image: None,
+            background_texture: BackgroundTexture::default(),
+            background_path: String::new(),
+            log_open: false,
+            log_path: String::new(),
+            log_options: LogOptions::default(),
+            logging: None,
+            log_error: None,
+            export_path: "~/sigmaterm-output".to_string(),
+            export_status: None,
             color_set: ColorSet::default(),
             color_mode: ColorMode::Dark,
+            program_colors: [None; 3],
             is_editing_title: false,
+            title_before_edit: String::new(),
+            renamed: false,
+            badge: None,
+            process: None,
             hue: 180.0,
+            saturation: DEFAULT_SATURATION,
+            lightness: DEFAULT_LIGHTNESS,
             is_maximized: false
         }
     }
@@ -85,12 +418,36 @@ impl Header {
         Self {
             title: "Untitled Terminal".to_string(),
             emoji_picker_open: false,
+            emoji_picker: EmojiPicker::default(),
+            icon: None,
             color_picker_open: false,
+            color_picker: ColorPicker::default(),
+            scheme: None,
+            default_palette: default_palette(),
+            palette_edits: [None; 16],
+            opacity: 1.0,
+            background_image: None,
+            background_texture: BackgroundTexture::default(),
+            background_path: String::new(),
+            log_open: false,
+            log_path: String::new(),
+            log_options: LogOptions::default(),
+            logging: None,
+            log_error: None,
+            export_path: "~/sigmaterm-output".to_string(),
+            export_status: None,
             color_set: utils::get_set_from_hue(hue),
             color_mode: ColorMode::Dark,
+            program_colors: [None; 3],
             is_editing_title: false,
+            title_before_edit: String::new(),
+            renamed: false,
+            badge: None,
+            process: None,
             hue,
-            is_maximized: is_maximized
+            saturation: DEFAULT_SATURATION,
+            lightness: DEFAULT_LIGHTNESS,
+            is_maximized
         }
     }
     pub fn set_dark_mode(&mut self, dark_mode: bool) {
@@ -103,26 +460,141 @@ impl Header {
     
     pub fn stop_editing_title(&mut self) {
         self.is_editing_title = false;
+        if self.title != self.title_before_edit {
+            self.renamed = true;
+        }
+    }
+
+    pub fn start_editing_title(&mut self) {
+        self.is_editing_title = true;
+        self.title_before_edit = self.title.clone();
     }
     
     pub fn toggle_emoji_picker(&mut self) {
         self.emoji_picker_open = !self.emoji_picker_open;
     }
 
+    // The emoji picker has the keyboard
+    pub fn is_picking_emoji(&self) -> bool {
+        self.emoji_picker_open
+    }
+
+    pub fn get_icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    pub fn set_icon(&mut self, icon: Option<String>) {
+        self.icon = icon;
+    }
+
     pub fn get_terminal_bg_color_imm(&self) -> egui::Color32 {
-        match self.color_mode {
-            ColorMode::Dark => self.color_set.dark,
-            ColorMode::Light => self.color_set.light,
-        }
+        self.program_colors[1].unwrap_or(self.pane_colors()[1])
     }
 
     pub fn get_terminal_text_color_imm(&self) -> egui::Color32 {
-        match self.color_mode {
-            ColorMode::Dark => self.color_set.on_dark,
-            ColorMode::Light => self.color_set.on_light,
+        self.program_colors[0].unwrap_or(self.pane_colors()[0])
+    }
+
+    pub fn get_cursor_color_imm(&self) -> egui::Color32 {
+        self.program_colors[2].unwrap_or(self.pane_colors()[2])
+    }
+
+    // Foreground, background and cursor colors from the scheme or the pane's hue,
+    // whatever the program asked for
+    pub fn pane_colors(&self) -> [egui::Color32; 3] {
+        let (foreground, background) = match (&self.scheme, &self.color_mode) {
+            (Some(scheme), _) => (scheme.foreground, scheme.background),
+            (None, ColorMode::Dark) => (self.color_set.on_dark, self.color_set.dark),
+            (None, ColorMode::Light) => (self.color_set.on_light, self.color_set.light),
+        };
+        [foreground, background, foreground]
+    }
+
+    pub fn set_program_colors(&mut self, colors: [Option<egui::Color32>; 3]) {
+        self.program_colors = colors;
+    }
+
+    // ANSI colors for the output
+    pub fn palette(&self) -> Palette {
+        let mut palette = match &self.scheme {
+            Some(scheme) => scheme.palette,
+            None => self.default_palette,
+        };
+        apply_edits(&mut palette, &self.palette_edits);
+        palette
+    }
+
+    pub fn set_default_palette(&mut self, palette: Palette) {
+        self.default_palette = palette;
+    }
+
+    pub fn get_palette_edits(&self) -> &PaletteEdits {
+        &self.palette_edits
+    }
+
+    pub fn set_palette_edits(&mut self, edits: PaletteEdits) {
+        self.palette_edits = edits;
+    }
+
+    // The file and options picked in the log popup
+    pub fn get_log_request(&self) -> (String, LogOptions) {
+        (self.log_path.trim().to_string(), self.log_options)
+    }
+
+    pub fn get_export_path(&self) -> &str {
+        self.export_path.trim()
+    }
+
+    pub fn set_export_status(&mut self, status: String) {
+        self.export_status = Some(status);
+    }
+
+    pub fn set_log_path(&mut self, path: &std::path::Path) {
+        self.log_path = path.display().to_string();
+    }
+
+    // Logging started (Ok with the file), stopped (Ok(None)) or failed to start
+    pub fn set_logging(&mut self, logging: Result<Option<PathBuf>, String>) {
+        match logging {
+            Ok(logging) => {
+                self.logging = logging;
+                self.log_error = None;
+            }
+            Err(error) => self.log_error = Some(error),
         }
     }
 
+    pub fn get_opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.2, 1.0);
+    }
+
+    pub fn get_background_image(&self) -> Option<&BackgroundImage> {
+        self.background_image.as_ref()
+    }
+
+    pub fn set_background_image(&mut self, image: Option<BackgroundImage>) {
+        self.background_path = image.as_ref().map(|image| image.path.clone()).unwrap_or_default();
+        self.background_image = image;
+    }
+
+    // The pane's background picture and the texture made from it, loading it if need be
+    pub fn background_texture(&mut self, ctx: &egui::Context) -> Option<(&BackgroundImage, &egui::TextureHandle)> {
+        let image = self.background_image.as_ref()?;
+        Some((image, self.background_texture.get(ctx, image)?))
+    }
+
+    pub fn get_scheme_name(&self) -> Option<&str> {
+        self.scheme.as_ref().map(|scheme| scheme.name.as_str())
+    }
+
+    pub fn set_scheme(&mut self, scheme: Option<ColorScheme>) {
+        self.scheme = scheme;
+    }
+
     pub fn get_primary_color(&mut self) -> egui::Color32 {
         self.color_set.primary
     }
@@ -135,8 +607,52 @@ impl Header {
         &self.title
     }
 
+    // The user named the pane rather than a program
+    pub fn is_renamed(&self) -> bool {
+        self.renamed
+    }
+
+    pub fn get_hue(&self) -> f32 {
+        self.hue
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        self.renamed = true;
+    }
+
+    // A title the running program asked for (OSC 0/2); a name the user gave wins
+    pub fn set_program_title(&mut self, title: &str) {
+        if !self.renamed && !self.is_editing_title && !title.trim().is_empty() {
+            self.title = title.to_string();
+        }
+    }
+
     pub fn set_color_set(&mut self, hue: f32) {
-        self.color_set = utils::get_set_from_hue(hue);
+        self.hue = hue;
+        self.update_color_set();
+    }
+
+    pub fn get_shade(&self) -> (f32, f32) {
+        (self.saturation, self.lightness)
+    }
+
+    pub fn set_shade(&mut self, saturation: f32, lightness: f32) {
+        self.saturation = saturation.clamp(0.0, 1.0);
+        self.lightness = lightness.clamp(0.2, 0.9);
+        self.update_color_set();
+    }
+
+    fn update_color_set(&mut self) {
+        self.color_set = utils::get_set_from_hsl(self.hue, self.saturation, self.lightness);
+    }
+
+    pub fn set_process(&mut self, process: Option<String>) {
+        self.process = process;
+    }
+
+    pub fn set_badge(&mut self, badge: Option<Badge>) {
+        self.badge = badge;
     }
 
     pub fn set_maximized(&mut self, is_maximized: bool) {
@@ -145,9 +661,9 @@ impl Header {
 
     pub fn render(&mut self, ui: &mut egui::Ui, is_active: bool) -> HeaderAction {
         let mut header_action: HeaderAction = HeaderAction::None;
-        let slider_width: f32 = 200.0;  // Increased to fit slider + buttons
+        let slider_width: f32 = 344.0;  // Increased to fit slider + buttons
         
-        egui::Frame::default()
+        let header_rect = egui::Frame::default()
             .fill(self.color_set.primary)
             .inner_margin(8.0)
             .show(ui, |ui| {
@@ -184,7 +700,7 @@ impl Header {
                         
                         // Stop editing on Enter or lost focus
                         if response.lost_focus() || enter_pressed || escape_pressed {
-                            self.is_editing_title = false;
+                            self.stop_editing_title();
                             
                             // Consume the Enter key event so terminal doesn't process it
                             if enter_pressed {
@@ -210,20 +726,35 @@ impl Header {
                         let response = ui.interact(text_rect, ui.id().with("title_label"), egui::Sense::click());
                         
                         // Draw the title text
-                        ui.painter().text(
+                        let title = [self.badge.map(Badge::icon), self.icon.as_deref(), Some(self.title.as_str())]
+                            .into_iter()
+                            .flatten()
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        let title_rect = ui.painter().text(
                             text_rect.left_center(),
                             egui::Align2::LEFT_CENTER,
-                            &self.title,
+                            title,
                             egui::FontId::proportional(20.0),
                             self.color_set.on_primary,
                         );
+                        if let Some(process) = &self.process {
+                            // Dimmer and cut off at the buttons, so it never pushes them away
+                            ui.painter().with_clip_rect(text_rect).text(
+                                title_rect.right_center() + egui::vec2(10.0, 0.0),
+                                egui::Align2::LEFT_CENTER,
+                                process,
+                                egui::FontId::monospace(14.0),
+                                self.color_set.on_primary.gamma_multiply(0.7),
+                            );
+                        }
                         
                         // Allocate space for the text
                         ui.allocate_space(egui::vec2(text_width, 20.0));
                         
                         // Start editing on click
                         if response.clicked() {
-                            self.is_editing_title = true;
+                            self.start_editing_title();
                         }
                     }
                     
@@ -262,6 +793,31 @@ impl Header {
 
                                         ui.add_space(10.0);
 
+                                        if window_button(ui, "⟳", self.color_set.light, self.color_set.on_primary) {
+                                            header_action = HeaderAction::RestartShell;
+                                        }
+
+                                        ui.add_space(10.0);
+
+                                        if window_button(ui, "☺", self.color_set.light, self.color_set.on_primary) {
+                                            self.toggle_emoji_picker();
+                                        }
+
+                                        ui.add_space(10.0);
+
+                                        if window_button(ui, "🎨", self.color_set.light, self.color_set.on_primary) {
+                                            self.color_picker_open = !self.color_picker_open;
+                                        }
+
+                                        ui.add_space(10.0);
+
+                                        let log_color = if self.logging.is_some() { self.color_set.warning } else { self.color_set.light };
+                                        if window_button(ui, "📝", log_color, self.color_set.on_primary) {
+                                            self.log_open = !self.log_open;
+                                        }
+
+                                        ui.add_space(10.0);
+
                                         // Add hue slider (leftmost in this group)
                                         let slider_response = ui.add(
                                             egui::Slider::new(&mut self.hue, 0.0..=360.0)
@@ -270,7 +826,10 @@ impl Header {
                                         
                                         // Update color set when hue changes
                                         if slider_response.changed() {
-                                            self.color_set = utils::get_set_from_hue(self.hue);
+                                            self.update_color_set();
+                                        }
+                                        if slider_response.drag_stopped() || (slider_response.changed() && !slider_response.dragged()) {
+                                            remember_hue(ui.ctx(), self.hue.round());
                                         }
                                         
                                         ui.add_space(10.0);
@@ -279,9 +838,156 @@ impl Header {
                         });
                     }
                 });
-            });
-            
+            }).response.rect;
+
+        if self.emoji_picker_open {
+            self.render_emoji_picker(ui, header_rect);
+        }
+        if self.color_picker_open {
+            self.render_color_picker(ui, header_rect);
+        }
+        if self.log_open && let Some(action) = self.render_log_settings(ui, header_rect) {
+            header_action = action;
+        }
         header_action
     }
+
+    fn render_color_picker(&mut self, ui: &egui::Ui, header_rect: egui::Rect) {
+        let mut open = true;
+        let mut picked = None;
+        egui::Window::new("Appearance")
+            .id(ui.id().with("color_picker"))
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .default_pos(header_rect.right_bottom() + egui::vec2(-260.0, 4.0))
+            .show(ui.ctx(), |ui| {
+                ui.set_max_width(160.0);
+                let palette = self.palette();
+                let scheme = self.scheme.as_ref().map(|scheme| scheme.name.as_str());
+                let shade = (self.saturation, self.lightness);
+                picked = self.color_picker.show(ui, self.hue, shade, scheme, &palette, &mut self.palette_edits);
+                ui.add_space(4.0);
+                self.render_background_settings(ui);
+            });
+        match picked {
+            Some(ColorPick::Hue(hue)) => {
+                self.set_color_set(hue);
+                self.scheme = None;
+                remember_hue(ui.ctx(), hue);
+            }
+            Some(ColorPick::Shade(saturation, lightness)) => self.set_shade(saturation, lightness),
+            Some(ColorPick::Scheme(name)) => {
+                self.scheme = name.and_then(|name| schemes().iter().find(|scheme| scheme.name == name).cloned());
+            }
+            None => {}
+        }
+        if !open || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.color_picker_open = false;
+        }
+    }
+
+    // Logging the output from now on, and saving what's there already; the action if
+    // logging was started or stopped or the scrollback exported
+    fn render_log_settings(&mut self, ui: &egui::Ui, header_rect: egui::Rect) -> Option<HeaderAction> {
+        let mut open = true;
+        let mut action = None;
+        egui::Window::new("Output")
+            .id(ui.id().with("log_settings"))
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .default_pos(header_rect.right_bottom() + egui::vec2(-300.0, 4.0))
+            .show(ui.ctx(), |ui| {
+                match &self.logging {
+                    Some(path) => {
+                        ui.label(format!("Logging to {}", path.display()));
+                        if ui.button("Stop logging").clicked() {
+                            action = Some(HeaderAction::StopLog);
+                        }
+                    }
+                    None => {
+                        ui.label("File");
+                        ui.add(egui::TextEdit::singleline(&mut self.log_path).desired_width(260.0));
+                        ui.checkbox(&mut self.log_options.timestamps, "Time at the start of each line");
+                        ui.checkbox(&mut self.log_options.strip_ansi, "Plain text (no colors or escape sequences)");
+                        if let Some(error) = &self.log_error {
+                            ui.colored_label(self.color_set.alert, error);
+                        }
+                        if ui.button("Start logging").clicked() && !self.log_path.trim().is_empty() {
+                            action = Some(HeaderAction::StartLog);
+                        }
+                    }
+                }
+
+                ui.separator();
+                ui.label("Export the scrollback to");
+                ui.add(egui::TextEdit::singleline(&mut self.export_path).desired_width(260.0));
+                ui.horizontal(|ui| {
+                    for (format, label) in [
+                        (ExportFormat::Text, "Plain text"),
+                        (ExportFormat::Ansi, "Text with colors (ANSI)"),
+                        (ExportFormat::Html, "HTML"),
+                    ] {
+                        if ui.button(label).clicked() && !self.export_path.trim().is_empty() {
+                            action = Some(HeaderAction::Export(format));
+                        }
+                    }
+                });
+                if let Some(status) = &self.export_status {
+                    ui.label(status);
+                }
+            });
+        if !open || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.log_open = false;
+        }
+        action
+    }
+
+    // Opacity and the background picture, in the appearance popup
+    fn render_background_settings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Background").show(ui, |ui| {
+            ui.add(egui::Slider::new(&mut self.opacity, 0.2..=1.0).text("Opacity"));
+            ui.label("Image");
+            ui.horizontal(|ui| {
+                let field = ui.add(egui::TextEdit::singleline(&mut self.background_path)
+                    .hint_text("~/Pictures/wall.png")
+                    .desired_width(120.0));
+                if field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    let path = self.background_path.trim();
+                    self.background_image = (!path.is_empty()).then(|| BackgroundImage::new(path));
+                }
+                if self.background_image.is_some() && ui.small_button("✖").on_hover_text("No image").clicked() {
+                    self.background_image = None;
+                    self.background_path.clear();
+                }
+            });
+            if let Some(image) = &mut self.background_image {
+                ui.add(egui::Slider::new(&mut image.dim, 0.0..=1.0).text("Dim"));
+                ui.add(egui::Slider::new(&mut image.blur, 0.0..=MAX_BLUR).text("Blur"));
+            }
+        });
+    }
+
+    fn render_emoji_picker(&mut self, ui: &egui::Ui, header_rect: egui::Rect) {
+        let mut open = true;
+        let mut picked = None;
+        egui::Window::new("Terminal icon")
+            .id(ui.id().with("emoji_picker"))
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .default_pos(header_rect.left_bottom() + egui::vec2(8.0, 4.0))
+            .show(ui.ctx(), |ui| {
+                picked = self.emoji_picker.show(ui);
+            });
+        if let Some(icon) = picked {
+            self.icon = icon;
+            open = false;
+        }
+        if !open || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.emoji_picker_open = false;
+        }
+    }
 }
 
@@ -0,0 +1,74 @@
+// Command history ====================================
+// Commands submitted at a pane's prompt, recalled into the command buffer with
+// ArrowUp/ArrowDown or found with Ctrl+R
+
+const MAX_HISTORY: usize = 1000;
+
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,  // Oldest first
+    position: Option<usize>,  // Entry being shown while stepping through with the arrows
+    draft: String,  // What was typed before stepping back, restored past the newest entry
+}
+
+impl History {
+    // Record a submitted command, skipping blanks and repeats of the last one
+    pub fn push(&mut self, command: &str) {
+        self.position = None;
+        let command = command.trim();
+        if command.is_empty() || self.entries.last().is_some_and(|last| last == command) {
+            return;
+        }
+        self.entries.push(command.to_string());
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+        }
+    }
+
+    // The entry before the one shown (or the newest), given what's in the buffer now
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        let index = match self.position {
+            Some(0) => return None,
+            Some(index) => index - 1,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len().checked_sub(1)?
+            }
+        };
+        self.position = Some(index);
+        Some(&self.entries[index])
+    }
+
+    // The entry after the one shown, then back to the draft
+    pub fn next(&mut self) -> Option<&str> {
+        let index = self.position?;
+        if index + 1 < self.entries.len() {
+            self.position = Some(index + 1);
+            Some(&self.entries[index + 1])
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+
+    // The `skip`th newest entry `query` fuzzy-matches
+    pub fn search(&self, query: &str, skip: usize) -> Option<&str> {
+        self.entries.iter().rev()
+            .filter(|entry| fuzzy_match(query, entry))
+            .nth(skip)
+            .map(String::as_str)
+    }
+}
+
+// Ctrl+R search state, shown above the output
+pub struct HistorySearch {
+    pub query: String,
+    pub skip: usize,  // Ctrl+R again steps to older matches
+    pub original: String,  // Command buffer to put back on Escape
+}
+
+// True if the query's characters appear in order in the candidate, ignoring case
+fn fuzzy_match(query: &str, candidate: &str) -> bool {
+    let mut chars = candidate.chars().flat_map(char::to_lowercase);
+    query.chars().flat_map(char::to_lowercase).all(|q| chars.any(|c| c == q))
+}
@@ -0,0 +1,64 @@
+use eframe::egui;
+use global_hotkey::hotkey::HotKey;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+use std::sync::mpsc::{self, Receiver};
+
+// Global hotkeys =====================================
+// System-wide shortcuts registered with the window system (X11 only on Linux;
+// under pure Wayland registration fails and the hotkeys are simply unavailable).
+// A press wakes the app up even while the window is hidden.
+pub struct GlobalHotkeys {
+    manager: GlobalHotKeyManager,  // Unregisters the hotkeys when dropped
+    events: Receiver<u32>,  // Ids of the hotkeys pressed
+    pressed: Vec<u32>,  // Since the last poll
+}
+
+impl GlobalHotkeys {
+    pub fn new(ctx: &egui::Context) -> Option<Self> {
+        let manager = match GlobalHotKeyManager::new() {
+            Ok(manager) => manager,
+            Err(e) => {
+                eprintln!("Warning: Global hotkeys are not available: {}", e);
+                return None;
+            }
+        };
+
+        let (sender, events) = mpsc::channel();
+        let ctx = ctx.clone();
+        GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
+            if event.state == HotKeyState::Pressed {
+                let _ = sender.send(event.id);
+                ctx.request_repaint();
+            }
+        }));
+
+        Some(Self { manager, events, pressed: Vec::new() })
+    }
+
+    // `spec` looks like "ctrl+alt+t" or "super+grave"; the hotkey's id if it worked
+    pub fn register(&self, spec: &str) -> Option<u32> {
+        let hotkey: HotKey = match spec.parse() {
+            Ok(hotkey) => hotkey,
+            Err(e) => {
+                eprintln!("Warning: Invalid global hotkey \"{}\": {}", spec, e);
+                return None;
+            }
+        };
+
+        if let Err(e) = self.manager.register(hotkey) {
+            eprintln!("Warning: Failed to register global hotkey \"{}\": {}", spec, e);
+            return None;
+        }
+        Some(hotkey.id())
+    }
+
+    // Collect the presses since the last frame, once per frame
+    pub fn poll(&mut self) {
+        self.pressed = self.events.try_iter().collect();
+    }
+
+    // True if the hotkey was pressed since the last poll
+    pub fn was_pressed(&self, id: Option<u32>) -> bool {
+        id.is_some_and(|id| self.pressed.contains(&id))
+    }
+}
@@ -0,0 +1,108 @@
+use crate::utils::{next_word_end, prev_word_start};
+
+// Input line =========================================
+// The command being typed in normal mode, edited locally and sent to the shell on
+// Enter. The cursor is a byte index, always on a char boundary.
+
+#[derive(Default)]
+pub struct InputLine {
+    text: String,
+    cursor: usize,
+}
+
+impl InputLine {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..self.cursor]
+    }
+
+    pub fn after_cursor(&self) -> &str {
+        &self.text[self.cursor..]
+    }
+
+    // Replace the whole line, with the cursor at the end
+    pub fn set(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(c) = self.before_cursor().chars().next_back() {
+            self.cursor -= c.len_utf8();
+            self.text.remove(self.cursor);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+        }
+    }
+
+    // Ctrl+K
+    pub fn delete_to_end(&mut self) {
+        self.text.truncate(self.cursor);
+    }
+
+    // Ctrl+U
+    pub fn delete_to_start(&mut self) {
+        self.text.replace_range(..self.cursor, "");
+        self.cursor = 0;
+    }
+
+    pub fn delete_word_backward(&mut self, is_word_char: impl Fn(char) -> bool) {
+        let start = prev_word_start(&self.text, self.cursor, is_word_char);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    pub fn move_left(&mut self) {
+        if let Some(c) = self.before_cursor().chars().next_back() {
+            self.cursor -= c.len_utf8();
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(c) = self.after_cursor().chars().next() {
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    pub fn move_word_left(&mut self, is_word_char: impl Fn(char) -> bool) {
+        self.cursor = prev_word_start(&self.text, self.cursor, is_word_char);
+    }
+
+    pub fn move_word_right(&mut self, is_word_char: impl Fn(char) -> bool) {
+        self.cursor = next_word_end(&self.text, self.cursor, is_word_char);
+    }
+}
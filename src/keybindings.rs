@@ -0,0 +1,167 @@
+use eframe::egui;
+use serde::Deserialize;
+use std::fmt;
+
+// Keybindings ========================================
+// Shortcuts handled by Sigmaterm itself rather than passed to the shell, e.g.:
+//
+//   [keybindings]
+//   search = "ctrl+shift+f"
+//   copy = "ctrl+shift+c"
+//   next_terminal = "ctrl+tab"
+//
+// A chord is modifiers (ctrl, shift, alt) and a key name joined by "+". Key names
+// are egui's: letters, digits, F1-F35, Enter, Tab, Space, PageUp, Insert, ...
+// focus_terminal is a list, one chord per pane in order; [] turns it off.
+// The pane focus chords (Alt+Arrow, Alt+1 to Alt+9 by default) aren't taken while
+// the active pane's program reads keys itself (vim, htop, a readline prompt).
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Keybindings {
+    pub copy: KeyChord,
+    pub paste: KeyChord,
+    pub search: KeyChord,
+    pub copy_mode: KeyChord,
+    pub rename: KeyChord,  // Not passed on in raw mode, where programs use the F-keys
+    pub new_terminal: KeyChord,
+    pub close_terminal: KeyChord,
+    pub restart_shell: KeyChord,
+    pub split_right: KeyChord,
+    pub split_down: KeyChord,
+    pub next_terminal: KeyChord,
+    pub previous_terminal: KeyChord,
+    pub focus_left: KeyChord,
+    pub focus_right: KeyChord,
+    pub focus_up: KeyChord,
+    pub focus_down: KeyChord,
+    pub focus_terminal: Vec<KeyChord>,
+    pub next_tab: KeyChord,
+    pub previous_tab: KeyChord,
+    pub fullscreen: KeyChord,  // Also not passed on in raw mode
+    pub previous_prompt: KeyChord,
+    pub next_prompt: KeyChord,
+    pub zoom_in: KeyChord,
+    pub zoom_out: KeyChord,
+    pub zoom_reset: KeyChord,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self {
+            copy: KeyChord::ctrl_shift(egui::Key::C),
+            paste: KeyChord::ctrl_shift(egui::Key::V),
+            search: KeyChord::ctrl_shift(egui::Key::F),
+            copy_mode: KeyChord::ctrl_shift(egui::Key::OpenBracket),
+            rename: KeyChord::plain(egui::Key::F2),
+            new_terminal: KeyChord::ctrl_shift(egui::Key::T),
+            close_terminal: KeyChord::ctrl_shift(egui::Key::W),
+            restart_shell: KeyChord::ctrl_shift(egui::Key::R),
+            split_right: KeyChord::ctrl_shift(egui::Key::E),
+            split_down: KeyChord::ctrl_shift(egui::Key::O),
+            next_terminal: KeyChord::ctrl(egui::Key::PageDown),
+            previous_terminal: KeyChord::ctrl(egui::Key::PageUp),
+            focus_left: KeyChord::alt(egui::Key::ArrowLeft),
+            focus_right: KeyChord::alt(egui::Key::ArrowRight),
+            focus_up: KeyChord::alt(egui::Key::ArrowUp),
+            focus_down: KeyChord::alt(egui::Key::ArrowDown),
+            focus_terminal: [
+                egui::Key::Num1, egui::Key::Num2, egui::Key::Num3, egui::Key::Num4, egui::Key::Num5,
+                egui::Key::Num6, egui::Key::Num7, egui::Key::Num8, egui::Key::Num9,
+            ].into_iter().map(KeyChord::alt).collect(),
+            next_tab: KeyChord::ctrl(egui::Key::Tab),
+            previous_tab: KeyChord::ctrl_shift(egui::Key::Tab),
+            fullscreen: KeyChord::plain(egui::Key::F11),
+            previous_prompt: KeyChord::ctrl_shift(egui::Key::ArrowUp),
+            next_prompt: KeyChord::ctrl_shift(egui::Key::ArrowDown),
+            zoom_in: KeyChord::ctrl(egui::Key::Equals),
+            zoom_out: KeyChord::ctrl(egui::Key::Minus),
+            zoom_reset: KeyChord::ctrl(egui::Key::Num0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyChord {
+    pub key: egui::Key,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl KeyChord {
+    pub fn plain(key: egui::Key) -> Self {
+        Self { key, ctrl: false, shift: false, alt: false }
+    }
+
+    pub fn alt(key: egui::Key) -> Self {
+        Self { key, ctrl: false, shift: false, alt: true }
+    }
+
+    pub fn ctrl(key: egui::Key) -> Self {
+        Self { key, ctrl: true, shift: false, alt: false }
+    }
+
+    pub fn ctrl_shift(key: egui::Key) -> Self {
+        Self { key, ctrl: true, shift: true, alt: false }
+    }
+
+    pub fn matches(&self, key: egui::Key, modifiers: egui::Modifiers) -> bool {
+        self.key == key && self.ctrl == modifiers.ctrl && self.shift == modifiers.shift && self.alt == modifiers.alt
+    }
+
+    // Take presses of this chord out of the input before the panes see them. True if there were any.
+    pub fn consume(&self, input: &mut egui::InputState) -> bool {
+        let count = input.events.len();
+        input.events.retain(|event| !matches!(event, egui::Event::Key { key, pressed: true, modifiers, .. } if self.matches(*key, *modifiers)));
+        input.events.len() != count
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = String;
+
+    fn try_from(chord: String) -> Result<Self, String> {
+        let mut parts: Vec<&str> = chord.split('+').map(str::trim).collect();
+        // "ctrl++" binds the plus key
+        if chord.ends_with("++") {
+            parts.pop();
+            *parts.last_mut().unwrap() = "+";
+        }
+        let Some(key_name) = parts.pop().filter(|name| !name.is_empty()) else {
+            return Err(format!("no key in \"{}\"", chord));
+        };
+
+        let mut result = Self::plain(find_key(key_name).ok_or_else(|| format!("unknown key \"{}\" in \"{}\"", key_name, chord))?);
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => result.ctrl = true,
+                "shift" => result.shift = true,
+                "alt" => result.alt = true,
+                _ => return Err(format!("unknown modifier \"{}\" in \"{}\"", modifier, chord)),
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+// Key names ignoring case, so "f" and "pageup" work as well as "F" and "PageUp"
+fn find_key(name: &str) -> Option<egui::Key> {
+    egui::Key::from_name(name)
+        .or_else(|| egui::Key::ALL.iter().copied().find(|key| key.name().eq_ignore_ascii_case(name)))
+}
@@ -0,0 +1,241 @@
+use eframe::egui;
+
+// Key encoding =======================================
+// What a key press sends to the program, for keys that don't arrive as text:
+// arrows and the other navigation keys, function keys, and Ctrl+<key> as control
+// characters, the way xterm sends them. Plain printable keys come through as text
+// events and aren't handled here. Alt works as Meta the way readline and emacs
+// expect, sending ESC first.
+
+// `app_cursor` is DECCKM, which programs like vim and less turn on to tell the
+// arrow keys apart from escape sequences typed by hand
+pub fn encode_key(key: egui::Key, modifiers: egui::Modifiers, app_cursor: bool) -> Option<String> {
+    // Keys that send a single character get the ESC in front with Alt, like text
+    let single = modifiers.ctrl.then(|| control_char(key)).flatten().or(match key {
+        egui::Key::Enter => Some(b'\r'),
+        egui::Key::Backspace => Some(0x7f),
+        egui::Key::Tab => Some(b'\t'),
+        egui::Key::Escape => Some(0x1b),
+        _ => None,
+    });
+    if let Some(byte) = single {
+        return Some(encode_text(&(byte as char).to_string(), modifiers));
+    }
+    // With modifiers these carry them, like ESC [ 1 ; 5 C for Ctrl+Right (word-wise
+    // movement in shells and editors), whatever the cursor key mode
+    let modifier = modifier_param(modifiers);
+    let cursor_key = |action: char| match modifier {
+        Some(modifier) => format!("\x1b[1;{}{}", modifier, action),
+        None if app_cursor => format!("\x1bO{}", action),
+        None => format!("\x1b[{}", action),
+    };
+    let sequence = match key {
+        egui::Key::ArrowUp => cursor_key('A'),
+        egui::Key::ArrowDown => cursor_key('B'),
+        egui::Key::ArrowRight => cursor_key('C'),
+        egui::Key::ArrowLeft => cursor_key('D'),
+        egui::Key::Home => cursor_key('H'),
+        egui::Key::End => cursor_key('F'),
+        egui::Key::Insert => tilde_key(2, modifier),
+        egui::Key::Delete => tilde_key(3, modifier),
+        egui::Key::PageUp => tilde_key(5, modifier),
+        egui::Key::PageDown => tilde_key(6, modifier),
+        _ => function_key(key, modifier)?,
+    };
+    Some(sequence)
+}
+
+// F1-F4 are SS3 P..S, the rest CSI <number> ~; with modifiers both become CSI
+// sequences carrying them, like ESC [ 1 ; 5 P for Ctrl+F1
+fn function_key(key: egui::Key, modifier: Option<u8>) -> Option<String> {
+    let ss3 = match key {
+        egui::Key::F1 => Some('P'),
+        egui::Key::F2 => Some('Q'),
+        egui::Key::F3 => Some('R'),
+        egui::Key::F4 => Some('S'),
+        _ => None,
+    };
+    if let Some(action) = ss3 {
+        return Some(match modifier {
+            Some(modifier) => format!("\x1b[1;{}{}", modifier, action),
+            None => format!("\x1bO{}", action),
+        });
+    }
+    let number = match key {
+        egui::Key::F5 => 15,
+        egui::Key::F6 => 17,
+        egui::Key::F7 => 18,
+        egui::Key::F8 => 19,
+        egui::Key::F9 => 20,
+        egui::Key::F10 => 21,
+        egui::Key::F11 => 23,
+        egui::Key::F12 => 24,
+        _ => return None,
+    };
+    Some(tilde_key(number, modifier))
+}
+
+// CSI <number> ~, or CSI <number> ; <modifier> ~
+fn tilde_key(number: u8, modifier: Option<u8>) -> String {
+    match modifier {
+        Some(modifier) => format!("\x1b[{};{}~", number, modifier),
+        None => format!("\x1b[{}~", number),
+    }
+}
+
+// xterm's modifier parameter: 1 plus Shift 1, Alt 2, Ctrl 4; None without modifiers
+fn modifier_param(modifiers: egui::Modifiers) -> Option<u8> {
+    let bits = modifiers.shift as u8 | (modifiers.alt as u8) << 1 | (modifiers.ctrl as u8) << 2;
+    (bits > 0).then_some(bits + 1)
+}
+
+// Typed text, ESC first while Alt is held (Alt+b, Alt+.)
+pub fn encode_text(text: &str, modifiers: egui::Modifiers) -> String {
+    if modifiers.alt {
+        format!("\x1b{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+// Ctrl+<key> the way xterm sends it: Ctrl+A..Z are 0x01..0x1a, and the keys around
+// them cover the rest of the C0 range (Ctrl+[ is ESC, Ctrl+Space NUL, Ctrl+/ US...)
+fn control_char(key: egui::Key) -> Option<u8> {
+    // Letter keys are named by their letter
+    let letter = key.name().as_bytes();
+    if letter.len() == 1 && letter[0].is_ascii_uppercase() {
+        return Some(letter[0] - b'A' + 1);
+    }
+    Some(match key {
+        egui::Key::Space | egui::Key::Num2 => 0x00,
+        egui::Key::OpenBracket | egui::Key::Num3 => 0x1b,
+        egui::Key::Backslash | egui::Key::Num4 => 0x1c,
+        egui::Key::CloseBracket | egui::Key::Num5 => 0x1d,
+        egui::Key::Num6 => 0x1e,
+        egui::Key::Slash | egui::Key::Minus | egui::Key::Num7 => 0x1f,
+        egui::Key::Backspace => 0x08,
+        egui::Key::Num8 => 0x7f,
+        _ => return None,
+    })
+}
+
+// Kitty keyboard protocol ============================
+// Programs that ask for it (CSI > flags u, see grid.rs) get keys in a form that can't
+// be mistaken for anything else: CSI <key code> ; <modifiers> u, so Tab and Ctrl+I,
+// Escape and the start of Alt+[, Ctrl+A and Ctrl+Shift+A all differ, and optionally
+// key releases too. https://sw.kovidgoyal.net/kitty/keyboard-protocol/
+
+pub const KITTY_DISAMBIGUATE: u8 = 1;
+pub const KITTY_EVENT_TYPES: u8 = 2;  // Report repeats and releases
+pub const KITTY_ALL_KEYS: u8 = 8;  // Typing too, not just keys with modifiers
+// Alternate keys (4) and associated text (16) aren't reported
+pub const KITTY_SUPPORTED: u8 = KITTY_DISAMBIGUATE | KITTY_EVENT_TYPES | KITTY_ALL_KEYS;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyEventType {
+    Press = 1,
+    Repeat = 2,
+    Release = 3,
+}
+
+// None for keys whose press goes as text (from the text event) or isn't reported
+pub fn encode_kitty_key(key: egui::Key, modifiers: egui::Modifiers, event: KeyEventType, flags: u8) -> Option<String> {
+    let all_keys = flags & KITTY_ALL_KEYS != 0;
+    let event = match event {
+        KeyEventType::Release if flags & KITTY_EVENT_TYPES == 0 => return None,
+        KeyEventType::Repeat if flags & KITTY_EVENT_TYPES == 0 => KeyEventType::Press,
+        event => event,
+    };
+    let mods = modifiers.shift as u8 | (modifiers.alt as u8) << 1 | (modifiers.ctrl as u8) << 2;
+
+    // Plain Enter, Tab and Backspace stay as they were unless every key is reported, so
+    // a shell left in this mode by a crashed program can still run `reset`
+    if !all_keys && mods == 0 && matches!(key, egui::Key::Enter | egui::Key::Tab | egui::Key::Backspace) {
+        return (event != KeyEventType::Release).then(|| encode_key(key, modifiers, false)).flatten();
+    }
+    if let Some(code) = text_key_code(key) {
+        // Typing (Shift at most) stays text
+        if !all_keys && mods & !1 == 0 && event != KeyEventType::Release {
+            return None;
+        }
+        return Some(kitty_sequence(code, mods, event, 'u'));
+    }
+    let (number, action) = match key {
+        egui::Key::Escape => (27, 'u'),
+        egui::Key::Enter => (13, 'u'),
+        egui::Key::Tab => (9, 'u'),
+        egui::Key::Backspace => (127, 'u'),
+        egui::Key::Insert => (2, '~'),
+        egui::Key::Delete => (3, '~'),
+        egui::Key::PageUp => (5, '~'),
+        egui::Key::PageDown => (6, '~'),
+        egui::Key::ArrowUp => (1, 'A'),
+        egui::Key::ArrowDown => (1, 'B'),
+        egui::Key::ArrowRight => (1, 'C'),
+        egui::Key::ArrowLeft => (1, 'D'),
+        egui::Key::Home => (1, 'H'),
+        egui::Key::End => (1, 'F'),
+        egui::Key::F1 => (1, 'P'),
+        egui::Key::F2 => (1, 'Q'),
+        egui::Key::F3 => (13, '~'),  // CSI R is a cursor position report
+        egui::Key::F4 => (1, 'S'),
+        egui::Key::F5 => (15, '~'),
+        egui::Key::F6 => (17, '~'),
+        egui::Key::F7 => (18, '~'),
+        egui::Key::F8 => (19, '~'),
+        egui::Key::F9 => (20, '~'),
+        egui::Key::F10 => (21, '~'),
+        egui::Key::F11 => (23, '~'),
+        egui::Key::F12 => (24, '~'),
+        _ => return None,
+    };
+    Some(kitty_sequence(number, mods, event, action))
+}
+
+// CSI <number> ; <modifiers>[:<event>] <action>, leaving out the defaults: a press,
+// no modifiers, and the 1 of CSI 1 A
+fn kitty_sequence(number: u32, mods: u8, event: KeyEventType, action: char) -> String {
+    let modifier_field = match event {
+        KeyEventType::Press if mods == 0 => String::new(),
+        KeyEventType::Press => (mods + 1).to_string(),
+        event => format!("{}:{}", mods + 1, event as u8),
+    };
+    match (number, modifier_field.is_empty()) {
+        (1, true) if action != 'u' => format!("\x1b[{}", action),
+        (_, true) => format!("\x1b[{}{}", number, action),
+        (_, false) => format!("\x1b[{};{}{}", number, modifier_field, action),
+    }
+}
+
+// The unshifted character a key types, which is its key code; shifted symbols egui
+// names on their own are taken back to their key on a US layout
+fn text_key_code(key: egui::Key) -> Option<u32> {
+    // Letters and digits are named by what they type
+    let name = key.name();
+    if name.len() == 1 {
+        return name.chars().next().map(|c| c.to_ascii_lowercase() as u32);
+    }
+    let c = match key {
+        egui::Key::Space => ' ',
+        egui::Key::Comma => ',',
+        egui::Key::Minus => '-',
+        egui::Key::Period => '.',
+        egui::Key::Equals => '=',
+        egui::Key::Semicolon => ';',
+        egui::Key::Backslash => '\\',
+        egui::Key::Slash => '/',
+        egui::Key::OpenBracket => '[',
+        egui::Key::CloseBracket => ']',
+        egui::Key::Backtick => '`',
+        egui::Key::Quote => '\'',
+        egui::Key::Colon => ';',
+        egui::Key::Plus => '=',
+        egui::Key::Pipe => '\\',
+        egui::Key::Questionmark => '/',
+        egui::Key::Exclamationmark => '1',
+        egui::Key::OpenCurlyBracket => '[',
+        egui::Key::CloseCurlyBracket => ']',
+        _ => return None,
+    };
+    Some(c as u32)
+}
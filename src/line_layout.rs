@@ -0,0 +1,159 @@
+use eframe::egui;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::actions::{find_token_actions, TokenRule};
+use crate::links::{find_links, LinkTarget};
+use crate::parser::TerminalOutput;
+
+// Laid-out lines =====================================
+// Output lines laid out once into a galley each and drawn from it frame after frame,
+// instead of rebuilding labels from their segments every time. Lines are looked up by
+// what's in them and how they're laid out (text size, width, scale), so a line that
+// changes is laid out again and the rest are left as they are. Links and token actions
+// are found along the way, since they only change with the text.
+
+pub struct LineLayout {
+    pub galley: Arc<egui::Galley>,
+    pub spans: Vec<LineSpan>,  // In order, not overlapping
+}
+
+// A stretch of the line that reacts to the mouse
+pub struct LineSpan {
+    pub chars: Range<usize>,  // Characters within the galley
+    pub text: String,
+    pub kind: SpanKind,
+}
+
+pub enum SpanKind {
+    Link(LinkTarget),
+    Token(usize),  // Index into the token action rules
+}
+
+impl LineLayout {
+    // The span over `pos`, relative to the galley's top left
+    pub fn span_at(&self, pos: egui::Vec2) -> Option<&LineSpan> {
+        // The cursor is the boundary nearest to pos; the character is on pos's side of it
+        let cursor = self.galley.cursor_from_pos(pos);
+        let index = if pos.x < self.galley.pos_from_cursor(cursor).left() {
+            cursor.index.checked_sub(1)?
+        } else {
+            cursor.index
+        };
+        self.spans.iter().find(|span| span.chars.contains(&index))
+    }
+}
+
+#[derive(Default)]
+pub struct LayoutCache {
+    lines: HashMap<u64, Rc<LineLayout>>,  // Used this frame
+    previous: HashMap<u64, Rc<LineLayout>>,  // Used last frame; let go unless used again
+    atlas_fill: f32,
+}
+
+impl LayoutCache {
+    // Once a frame, before the first line is laid out
+    pub fn begin_frame(&mut self, ctx: &egui::Context) {
+        self.previous = std::mem::take(&mut self.lines);
+        // egui starts the font atlas over when it fills up, and galleys from before then
+        // point at glyphs that are gone. The atlas only grows until that happens.
+        let fill = ctx.fonts(|f| f.font_atlas_fill_ratio());
+        if fill < self.atlas_fill {
+            self.previous.clear();
+        }
+        self.atlas_fill = fill;
+    }
+
+    // For when the token action rules change
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.previous.clear();
+    }
+
+    // `segments` laid out in the monospace font at `text_size`, wrapping anywhere at
+    // `wrap_width` (infinite to not wrap)
+    pub fn get(&mut self, ui: &egui::Ui, segments: &[TerminalOutput], text_size: f32, wrap_width: f32, rules: &[TokenRule]) -> Rc<LineLayout> {
+        let mut hasher = std::hash::DefaultHasher::new();
+        segments.hash(&mut hasher);
+        text_size.to_bits().hash(&mut hasher);
+        wrap_width.to_bits().hash(&mut hasher);
+        ui.ctx().pixels_per_point().to_bits().hash(&mut hasher);
+        let key = hasher.finish();
+
+        if let Some(layout) = self.lines.get(&key) {
+            return Rc::clone(layout);
+        }
+        let layout = self.previous.remove(&key)
+            .unwrap_or_else(|| Rc::new(lay_out(ui, segments, text_size, wrap_width, rules)));
+        self.lines.insert(key, Rc::clone(&layout));
+        layout
+    }
+}
+
+fn lay_out(ui: &egui::Ui, segments: &[TerminalOutput], text_size: f32, wrap_width: f32, rules: &[TokenRule]) -> LineLayout {
+    let font_id = egui::FontId::monospace(text_size);
+    let char_width = ui.fonts_mut(|f| f.glyph_width(&font_id, 'M'));
+    let mut job = egui::text::LayoutJob {
+        wrap: egui::text::TextWrapping { max_width: wrap_width, break_anywhere: true, ..Default::default() },
+        ..Default::default()
+    };
+    // Room still owed after the last wide character, put before the next piece
+    let mut pad = 0.0;
+    let append = |job: &mut egui::text::LayoutJob, text: egui::RichText, leading_space: f32| {
+        text.append_to(job, ui.style(), egui::FontSelection::Default, ui.text_valign());
+        if let Some(section) = job.sections.last_mut() {
+            section.leading_space = leading_space;
+        }
+    };
+
+    // A blank line still takes up a row
+    if segments.is_empty() {
+        append(&mut job, egui::RichText::new(" ").size(text_size).monospace(), 0.0);
+    }
+    let mut spans = Vec::new();
+    let mut chars = 0;
+    for seg in segments {
+        if seg.wide {
+            // Exactly two cells, whatever the glyph's width in the font, so the columns
+            // after it line up; the glyph goes in the middle
+            let glyph_width = seg.text.chars().next().map_or(char_width, |c| ui.fonts_mut(|f| f.glyph_width(&font_id, c)));
+            let half = (2.0 * char_width - glyph_width).max(0.0) / 2.0;
+            append(&mut job, seg.rich_text(&seg.text, text_size), std::mem::take(&mut pad) + half);
+            pad = half;
+            chars += seg.text.chars().count();
+            continue;
+        }
+
+        let mut found: Vec<(usize, usize, SpanKind)> = find_links(&seg.text).into_iter()
+            .map(|link| (link.start, link.end, SpanKind::Link(link.target)))
+            .collect();
+        found.extend(find_token_actions(&seg.text, rules).into_iter()
+            .filter_map(|(start, end, rule)| Some((start, end, SpanKind::Token(rules.iter().position(|r| std::ptr::eq(r, rule))?)))));
+        found.sort_by_key(|&(start, ..)| start);
+
+        let mut pos = 0;
+        for (start, end, kind) in found {
+            if start < pos {
+                continue;
+            }
+            if start > pos {
+                append(&mut job, seg.rich_text(&seg.text[pos..start], text_size), std::mem::take(&mut pad));
+                chars += seg.text[pos..start].chars().count();
+            }
+            let text = &seg.text[start..end];
+            append(&mut job, seg.rich_text(text, text_size).underline(), std::mem::take(&mut pad));
+            let count = text.chars().count();
+            spans.push(LineSpan { chars: chars..chars + count, text: text.to_string(), kind });
+            chars += count;
+            pos = end;
+        }
+        if pos < seg.text.len() {
+            append(&mut job, seg.rich_text(&seg.text[pos..], text_size), std::mem::take(&mut pad));
+            chars += seg.text[pos..].chars().count();
+        }
+    }
+    LineLayout { galley: ui.fonts_mut(|f| f.layout_job(job)), spans }
+}
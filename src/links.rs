@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Links ==============================================
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkTarget {
+    Url(String),
+    Path(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub start: usize,  // Byte range within the scanned text
+    pub end: usize,
+    pub target: LinkTarget,
+}
+
+const URL_PREFIXES: [&str; 3] = ["https://", "http://", "file://"];
+const PATH_PREFIXES: [&str; 4] = ["/", "./", "../", "~/"];
+
+// Find URLs and path-looking tokens in a piece of output text
+pub fn find_links(text: &str) -> Vec<Link> {
+    words(text)
+        .filter_map(|(start, word)| {
+            let target = if URL_PREFIXES.iter().any(|p| word.starts_with(p) && word.len() > p.len()) {
+                LinkTarget::Url(word.to_string())
+            } else if PATH_PREFIXES.iter().any(|p| word.starts_with(p)) && word.len() > 1 {
+                LinkTarget::Path(word.to_string())
+            } else {
+                return None;
+            };
+            Some(Link { start, end: start + word.len(), target })
+        })
+        .collect()
+}
+
+// Whitespace-separated words with their byte offsets, minus surrounding punctuation
+// that usually isn't part of the word, e.g. "(see https://example.com)." or 'file "/etc/hosts":'
+pub fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+    text.split_inclusive(char::is_whitespace).filter_map(move |token| {
+        let token_start = offset;
+        offset += token.len();
+
+        let leading = token.len() - token.trim_start_matches(['(', '[', '<', '"', '\'', '`']).len();
+        let trimmed = token[leading..]
+            .trim_end()
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '>', '"', '\'', '`']);
+        (!trimmed.is_empty()).then_some((token_start + leading, trimmed))
+    })
+}
+
+// Expand ~ and resolve relative paths against the shell's working directory
+pub fn resolve_path(path: &str, cwd: Option<&Path>) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/")
+        && let Ok(home) = std::env::var("HOME")
+    {
+        return PathBuf::from(home).join(rest);
+    }
+    match cwd {
+        Some(cwd) if !path.starts_with('/') => cwd.join(path),
+        _ => PathBuf::from(path),
+    }
+}
+
+// One-line summary of what's at a path, for hover tooltips
+pub fn describe_path(path: &Path) -> String {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return "Not found".to_string();
+    };
+
+    let kind = if metadata.is_dir() {
+        "Directory".to_string()
+    } else {
+        format!("File, {}", format_size(metadata.len()))
+    };
+
+    match metadata.modified().ok().and_then(|m| SystemTime::now().duration_since(m).ok()) {
+        Some(age) => format!("{}, modified {}", kind, format_age(age.as_secs())),
+        None => kind,
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
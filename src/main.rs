@@ -1,25 +1,108 @@
+use clap::Parser;
 use eframe::egui;
-use std::sync::Arc;
+use std::path::PathBuf;
 
+mod actions;
+mod background;
+mod config;
+#[cfg(test)]
+mod conformance;
+mod copy_mode;
+mod dropdown;
+mod export;
+#[cfg(test)]
+mod fuzz;
+mod fonts;
+mod grid;
 mod header;
+mod history;
+mod hotkey;
+mod input;
+mod keybindings;
+mod keys;
+mod line_layout;
+mod links;
 mod utils;
 mod terminal;
 mod manager;
+mod output_log;
 mod parser;
+mod playback;
+mod reader;
+mod schedule;
+mod scrollback;
+mod search;
+mod selection;
+mod session;
+mod settings;
+mod split;
+mod stats;
+mod system_theme;
+mod tabs;
+mod theme;
 mod window;
 
-use header::Header;
-use utils::ColorSet;
-use manager::TerminalManager;
-use window::WindowBar;
+use config::{Config, ConfigWatcher, Theme};
+use dropdown::Dropdown;
+use hotkey::GlobalHotkeys;
+use manager::{FocusDirection, TerminalManager};
+use playback::Playback;
+use split::SplitDirection;
+use session::Session;
+use stats::CommandStats;
+use system_theme::SystemTheme;
+use tabs::Tabs;
+use utils::confirm_close;
+use window::{WindowAction, WindowBar};
+
+// Command line ======================================
+#[derive(Parser)]
+#[command(version, about = "A terminal emulator with split panes")]
+struct Args {
+    /// Start the shells in this directory
+    #[arg(long, value_name = "DIR")]
+    working_dir: Option<PathBuf>,
+
+    /// Run this command in the first pane once its shell is up
+    #[arg(long, value_name = "COMMAND")]
+    command: Option<String>,
+
+    /// Use this profile from the config for new panes, instead of default_profile
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// How many panes to open at startup
+    #[arg(long, value_name = "N")]
+    terminals: Option<usize>,
+
+    /// Play back an asciinema recording (.cast) in a pane of its own
+    #[arg(long, value_name = "FILE")]
+    play: Option<PathBuf>,
+
+    /// Ignore the config file and start with a single plain bash pane, to recover
+    /// from a broken config
+    #[arg(long)]
+    safe_mode: bool,
+}
 
 fn main() -> eframe::Result {
+    let args = Args::parse();
+    let config = if args.safe_mode { Config::default() } else { Config::load() };
+
+    // Shells inherit our working directory
+    if let Some(dir) = &args.working_dir
+        && let Err(e) = std::env::set_current_dir(dir)
+    {
+        eprintln!("Warning: Can't use {} as the working directory: {}", dir.display(), e);
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_title("Sigmaterm")
             .with_inner_size([1000.0, 600.0])
             .with_resizable(true)
-            .with_decorations(false), // Disable native window decorations
+            .with_decorations(false) // Disable native window decorations
+            .with_transparent(true),  // For translucent panes
         ..Default::default()
     };
     
@@ -28,61 +111,385 @@ fn main() -> eframe::Result {
         options,
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
-            setup_fonts(&cc.egui_ctx);
-            Ok(Box::new(Sigmaterm::new()))
+            fonts::setup_fonts(&cc.egui_ctx, config.font.as_deref());
+            // Ctrl+=/-/0 zoom the pane under the keybindings, not the whole UI
+            cc.egui_ctx.options_mut(|options| options.zoom_with_keyboard = false);
+            Ok(Box::new(Sigmaterm::new(&cc.egui_ctx, args, config)))
         }),
     )
 }
 
-fn setup_fonts(ctx: &egui::Context){
-    let mut fonts = egui::FontDefinitions::default();
-    
-    // Load JetBrains Mono
-    fonts.font_data.insert("jetbrains".to_owned(), 
-        Arc::new(egui::FontData::from_static(include_bytes!("../assets/JetBrainsMono-2.304/fonts/ttf/JetBrainsMono-Regular.ttf")))
-    );
-
-    // For Monospace: JetBrains first, then egui's default fallbacks (which include some emoji support)
-    fonts.families.get_mut(&egui::FontFamily::Monospace).unwrap().insert(0, "jetbrains".to_owned());
-    
-    // For Proportional: JetBrains first, then egui's default fallbacks
-    fonts.families.get_mut(&egui::FontFamily::Proportional).unwrap().insert(0, "jetbrains".to_owned());
-
-    ctx.set_fonts(fonts);
-}
-
-#[derive(Default)]
 struct Sigmaterm {
-    text: String,
-    terminal_manager: TerminalManager,
+    tabs: Tabs,
     window_bar: WindowBar,
+    hotkeys: Option<GlobalHotkeys>,
+    summon_hotkey: Option<u32>,
+    dropdown: Option<Dropdown>,
+    stats: CommandStats,  // Commands from every tab
+    stats_open: bool,
+    scheduled_dark_mode: Option<bool>,
+    last_schedule_check: Option<std::time::Instant>,
+    system_theme: SystemTheme,
+    system_dark_mode: Option<bool>,  // What the desktop wanted last
+    config_watcher: Option<ConfigWatcher>,  // None in safe mode
+    safe_mode: bool,
+    translucent: bool,  // Some pane lets the desktop show through
+    quitting: Option<Vec<String>>,  // Programs still running, while asking whether to quit
+    quit_confirmed: bool,
 }
 
 impl Sigmaterm {
-    fn new() -> Self {
-        let mut app = Self::default();
-        app.terminal_manager.add_terminal(800.0, 600.0);
-        app.terminal_manager.add_terminal(800.0, 600.0);
+    fn new(ctx: &egui::Context, args: Args, mut config: Config) -> Self {
+        if let Some(name) = args.profile {
+            if config.profile(&name).is_some() {
+                config.default_profile = Some(name);
+            } else {
+                eprintln!("Warning: No profile named \"{}\" in the config", name);
+            }
+        }
+        let hotkeys = (config.summon_hotkey.is_some() || config.dropdown.is_some()).then(|| GlobalHotkeys::new(ctx)).flatten();
+        let register = |spec: &str| hotkeys.as_ref().and_then(|hotkeys| hotkeys.register(spec));
+        let summon_hotkey = config.summon_hotkey.as_deref().and_then(register);
+        let dropdown = config.dropdown.clone().map(|dropdown| {
+            let hotkey = register(&dropdown.hotkey);
+            Dropdown::new(dropdown, hotkey)
+        });
+        let default_terminals = if args.safe_mode { 1 } else { config.terminals };
+        // Following the desktop starts dark until it's been asked
+        let dark_mode = config.theme != Theme::Light;
+        // Asking for particular panes on the command line means a fresh start
+        let fresh_start = args.safe_mode || args.terminals.is_some() || args.command.is_some() || args.working_dir.is_some() || args.play.is_some();
+        let session = (config.restore_session && !fresh_start).then(Session::load).flatten();
+        let restored = session.is_some();
+        let window_state = session.as_ref().map_or((false, false), |session| (session.always_on_top, session.fullscreen));
+        let tabs = match session {
+            Some(session) => Tabs::restore(&config, session),
+            None => Tabs::new(TerminalManager::new(config)),
+        };
+        let mut app = Self {
+            tabs,
+            window_bar: WindowBar::default(),
+            hotkeys,
+            summon_hotkey,
+            dropdown,
+            stats: CommandStats::default(),
+            stats_open: false,
+            scheduled_dark_mode: None,
+            last_schedule_check: None,
+            system_theme: SystemTheme::new(ctx),
+            system_dark_mode: None,
+            config_watcher: (!args.safe_mode).then(ConfigWatcher::default),
+            safe_mode: args.safe_mode,
+            translucent: false,
+            quitting: None,
+            quit_confirmed: false,
+        };
+
+        app.window_bar.set_dark_mode(dark_mode);
+        let (always_on_top, fullscreen) = window_state;
+        if always_on_top {
+            app.window_bar.set_always_on_top(ctx, true);
+        }
+        if fullscreen {
+            app.window_bar.set_fullscreen(ctx, true);
+        }
+        app.window_bar.set_profiles(app.tabs.active().config().profiles.iter().map(|p| p.name.clone()).collect());
+        if restored {
+            return app;
+        }
+        let manager = app.tabs.active_mut();
+        // A recording on its own, unless shells are asked for too
+        let playback = args.play.as_deref().and_then(|path| Playback::open(path).map_err(|e| eprintln!("Warning: {}", e)).ok());
+        let shells = if playback.is_some() { args.terminals.unwrap_or(0) } else { args.terminals.unwrap_or(default_terminals).max(1) };
+        if let Some(playback) = playback {
+            manager.add_playback(playback, 800.0, 600.0);
+        }
+        for _ in 0..shells {
+            if manager.add_terminal(800.0, 600.0).is_none() {
+                eprintln!("Warning: max_terminals is {}, not opening more panes", manager.terminal_count());
+                break;
+            }
+        }
+        if let Some(command) = args.command {
+            manager.run_command(0, &command);
+        }
+        if args.safe_mode {
+            app.window_bar.set_title("Sigmaterm (safe mode)");
+        }
         app
     }
+
+    // Apply the config file to the running panes. If it doesn't parse (say, it's saved
+    // halfway through an edit), keep what's in use.
+    fn reload_config(&mut self, ctx: &egui::Context) {
+        let config = match Config::try_load() {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Warning: {}, keeping the current settings", e);
+                return;
+            }
+        };
+        let current = self.tabs.active().config();
+        if config.font != current.font {
+            fonts::setup_fonts(ctx, config.font.as_deref());
+        }
+        if config.theme != current.theme {
+            match config.theme {
+                // Picked up on the next frame
+                Theme::System => self.system_dark_mode = None,
+                theme => self.window_bar.set_dark_mode(theme == Theme::Dark),
+            }
+        }
+        self.window_bar.set_profiles(config.profiles.iter().map(|p| p.name.clone()).collect());
+        for manager in self.tabs.managers_mut() {
+            manager.set_config(config.clone());
+        }
+    }
+
+    // Recordings dragged onto the window are played back in new panes
+    fn open_dropped_recordings(&mut self, ctx: &egui::Context, width: f32, height: f32) {
+        let dropped: Vec<PathBuf> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
+        for path in dropped.iter().filter(|path| path.extension().is_some_and(|ext| ext == "cast")) {
+            match Playback::open(path) {
+                Ok(playback) => {
+                    let manager = self.tabs.active_mut();
+                    if let Some(id) = manager.add_playback(playback, width, height) {
+                        manager.focus_terminal(id);
+                    }
+                }
+                Err(e) => eprintln!("Warning: {}", e),
+            }
+        }
+    }
+
+    // Shortcuts for the active tab's panes, taken before the panes can see them
+    fn handle_pane_shortcuts(&mut self, ctx: &egui::Context, width: f32, height: f32) {
+        let bindings = self.tabs.active().config().keybindings.clone();
+        let manager = self.tabs.active_mut();
+        ctx.input_mut(|i| {
+            if bindings.new_terminal.consume(i) {
+                manager.new_terminal(width, height);
+            }
+            if bindings.close_terminal.consume(i) {
+                manager.close_active_terminal(width, height);
+            }
+            if bindings.split_right.consume(i) {
+                manager.split_terminal(SplitDirection::SideBySide, width, height);
+            }
+            if bindings.split_down.consume(i) {
+                manager.split_terminal(SplitDirection::Stacked, width, height);
+            }
+            if bindings.next_terminal.consume(i) {
+                manager.focus_next_terminal();
+            }
+            if bindings.previous_terminal.consume(i) {
+                manager.focus_previous_terminal();
+            }
+            // Programs that read keys themselves get the focus chords too
+            if manager.active_keys_to_program() {
+                return;
+            }
+            for (chord, direction) in [
+                (bindings.focus_left, FocusDirection::Left),
+                (bindings.focus_right, FocusDirection::Right),
+                (bindings.focus_up, FocusDirection::Up),
+                (bindings.focus_down, FocusDirection::Down),
+            ] {
+                if chord.consume(i) {
+                    manager.focus_neighbor(direction);
+                }
+            }
+            for (index, chord) in bindings.focus_terminal.iter().enumerate() {
+                if chord.consume(i) {
+                    manager.focus_terminal(index);
+                }
+            }
+        });
+    }
+
+    // Workspace and window shortcuts, taken before the panes can see them
+    fn handle_tab_shortcuts(&mut self, ctx: &egui::Context) {
+        let bindings = self.tabs.active().config().keybindings.clone();
+        // Like rename, left to fullscreen programs, which use the F-keys
+        let raw_mode = self.tabs.active().active_raw_mode();
+        let (next, previous, fullscreen) = ctx.input_mut(|i| {
+            (bindings.next_tab.consume(i), bindings.previous_tab.consume(i), !raw_mode && bindings.fullscreen.consume(i))
+        });
+        if next {
+            self.tabs.select_next();
+        }
+        if previous {
+            self.tabs.select_previous();
+        }
+        if fullscreen {
+            self.window_bar.set_fullscreen(ctx, !self.window_bar.is_fullscreen());
+        }
+    }
+
+    // Closing the window (its button, the window manager, Alt+F4) asks first if programs
+    // are still running in any pane
+    fn confirm_quit(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| i.viewport().close_requested()) && !self.quit_confirmed {
+            let running: Vec<String> = self.tabs.managers_mut().flat_map(|manager| manager.running_processes()).collect();
+            if !running.is_empty() {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                self.quitting = Some(running);
+            }
+        }
+        let Some(running) = &self.quitting else {
+            return;
+        };
+        match confirm_close(ctx, "Quit Sigmaterm?", running, "Quit") {
+            Some(true) => {
+                self.quitting = None;
+                self.quit_confirmed = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+            Some(false) => self.quitting = None,
+            None => {}
+        }
+    }
+
+    // Flip dark mode only when the scheduled state changes, so a manual toggle
+    // sticks until the next scheduled switch
+    fn follow_dark_mode_schedule(&mut self, ctx: &egui::Context) {
+        if self.last_schedule_check.is_some_and(|t| t.elapsed().as_secs() < 30) {
+            return;
+        }
+        self.last_schedule_check = Some(std::time::Instant::now());
+
+        let scheduled = self.tabs.active().config().dark_mode_schedule.as_ref()
+            .and_then(|schedule| schedule.is_dark_now());
+        if scheduled.is_some() {
+            // Come back for the next check even if nothing else happens
+            ctx.request_repaint_after(std::time::Duration::from_secs(30));
+        }
+        if let Some(dark_mode) = scheduled
+            && self.scheduled_dark_mode != scheduled
+        {
+            self.window_bar.set_dark_mode(dark_mode);
+        }
+        self.scheduled_dark_mode = scheduled;
+    }
+
+    // Like the schedule, follow the desktop's light/dark setting only when it changes,
+    // so the window bar's toggle overrides it until then
+    fn follow_system_theme(&mut self, ctx: &egui::Context) {
+        if self.tabs.active().config().theme != Theme::System {
+            return;
+        }
+        let system = self.system_theme.is_dark(ctx);
+        if let Some(dark_mode) = system
+            && self.system_dark_mode != system
+        {
+            self.window_bar.set_dark_mode(dark_mode);
+        }
+        self.system_dark_mode = system;
+    }
 }
 
 impl eframe::App for Sigmaterm {
+    // Only clear to transparent when a pane is translucent, so the gaps between panes
+    // stay solid otherwise
+    fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
+        if self.translucent {
+            [0.0; 4]
+        } else {
+            egui::Color32::from_gray(12).to_normalized_gamma_f32()
+        }
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if !self.safe_mode && self.tabs.active().config().restore_session {
+            let mut session = self.tabs.session();
+            session.always_on_top = self.window_bar.is_always_on_top();
+            session.fullscreen = self.window_bar.is_fullscreen();
+            session.save();
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.confirm_quit(ctx);
+        self.follow_system_theme(ctx);
+        self.follow_dark_mode_schedule(ctx);
+
         // Render the window bar at the top
-        let should_add_terminal = self.window_bar.render(ctx, frame);
+        self.window_bar.set_add_terminal_enabled(self.tabs.active().can_add_terminal());
+        let window_action = self.window_bar.render(ctx, frame);
         let dark_mode = self.window_bar.is_dark_mode();
+
+        let config_edited = self.config_watcher.as_mut().is_some_and(ConfigWatcher::changed);
+        if self.config_watcher.is_some() {
+            // The watcher polls, so an idle window still wakes up to look
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+        if window_action == WindowAction::ReloadConfig || config_edited {
+            self.reload_config(ctx);
+        }
+        if window_action == WindowAction::ToggleStats {
+            self.stats_open = !self.stats_open;
+        }
+        if self.stats_open {
+            self.stats.render(ctx, &mut self.stats_open);
+        }
+        if window_action == WindowAction::AddTab {
+            let mut manager = TerminalManager::new(self.tabs.active().config().clone());
+            manager.add_terminal(800.0, 600.0);
+            self.tabs.add(manager);
+        }
+        self.handle_tab_shortcuts(ctx);
+        self.tabs.render(ctx);
+
+        // Global hotkeys: bring the window forward and focus the last-active pane, or
+        // slide the dropdown window in or out
+        if let Some(hotkeys) = &mut self.hotkeys {
+            hotkeys.poll();
+        }
+        let pressed = |id: Option<u32>| self.hotkeys.as_ref().is_some_and(|hotkeys| hotkeys.was_pressed(id));
+        let mut summoned = pressed(self.summon_hotkey);
+        if summoned {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+            ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        }
+        if let Some(dropdown) = &mut self.dropdown {
+            if pressed(dropdown.hotkey) {
+                dropdown.toggle();
+                summoned = dropdown.is_shown();
+            }
+            dropdown.update(ctx);
+        }
         
         egui::CentralPanel::default()
             .frame(egui::Frame::default().inner_margin(0.0))
             .show(ctx, |ui| {
+            let (width, height) = (ui.available_width(), ui.available_height());
+            self.handle_pane_shortcuts(ui.ctx(), width, height);
             // Add new terminal if the button was clicked
-            if should_add_terminal {
-                self.terminal_manager.add_terminal(ui.available_width(), ui.available_height());
+            if window_action == WindowAction::AddTerminal {
+                self.tabs.active_mut().add_terminal(width, height);
+            }
+            if let WindowAction::AddProfileTerminal(index) = window_action {
+                let manager = self.tabs.active_mut();
+                let name = manager.config().profiles.get(index).map(|p| p.name.clone());
+                if let Some(id) = manager.add_profile_terminal(name.as_deref(), width, height) {
+                    manager.focus_terminal(id);
+                }
+            }
+            if summoned {
+                self.tabs.active_mut().summon(width, height);
+            }
+            self.open_dropped_recordings(ui.ctx(), width, height);
+            for manager in self.tabs.managers_mut() {
+                manager.set_dark_mode(dark_mode);
+                manager.update(ui, width, height);
+                for record in manager.take_finished_commands() {
+                    self.stats.record(record);
+                }
             }
-            self.terminal_manager.set_dark_mode(dark_mode);
-            self.terminal_manager.update(ui, ui.available_width(), ui.available_height());
-            self.terminal_manager.render(ui);
+            self.tabs.active_mut().render(ui);
         });
+        self.tabs.close_empty();
+        self.translucent = self.tabs.managers_mut().any(|manager| manager.is_translucent());
     }
 }   
\ No newline at end of file
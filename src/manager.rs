@@ -1,16 +1,34 @@
 use eframe::egui;
+use std::path::PathBuf;
 
+use crate::config::{Config, Layout};
+use crate::header::Badge;
+use crate::playback::Playback;
+use crate::session::TabSession;
+use crate::split::{SplitDirection, SplitNode};
+use crate::stats::CommandRecord;
 use crate::terminal::{Terminal, TerminalResponse};
+use crate::utils::{confirm_close, DEFAULT_LIGHTNESS, DEFAULT_SATURATION};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FocusDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
 
 pub struct TerminalManager {
     terminals: Vec<Terminal>,
     num_terminals: usize,
-    max_terminals: usize,
-    top_row_terminals: Vec<usize>,
-    bottom_row_terminals: Vec<usize>,
+    limit_notice_at: Option<std::time::Instant>,  // When opening a pane last ran into max_terminals
+    closing: Option<(usize, Vec<String>)>,  // Pane waiting on the user to confirm closing it, and what runs in it
+    layout: Option<SplitNode>,  // None until the first terminal
     show_all: bool,
     last_hue: f32,
     active_terminal_id: Option<usize>,  // Track active terminal
+    config: Config,
+    finished_commands: Vec<CommandRecord>,  // For the app-wide stats
 }
 
 impl Default for TerminalManager {
@@ -18,27 +36,107 @@ impl Default for TerminalManager {
         Self {
             terminals: Vec::new(),
             num_terminals: 0,
-            max_terminals: 6,
-            top_row_terminals: Vec::new(),
-            bottom_row_terminals: Vec::new(),
+            limit_notice_at: None,
+            closing: None,
+            layout: None,
             show_all: true,
             last_hue: 180.0,
             active_terminal_id: None,
+            config: Config::default(),
+            finished_commands: Vec::new(),
         }
     }
 }
 
 impl TerminalManager {
-    pub fn set_dark_mode(&mut self, dark_mode: bool) {
-        for &idx in &self.top_row_terminals {
-            if let Some(terminal) = self.terminals.get_mut(idx) {
-                terminal.set_dark_mode(dark_mode);
+    pub fn new(config: Config) -> Self {
+        Self {
+            last_hue: config.hue,
+            config,
+            ..Default::default()
+        }
+    }
+
+    // Reopen a tab saved at the end of the last session
+    pub fn restore(config: Config, session: TabSession) -> Self {
+        let mut manager = Self::new(config);
+        manager.show_all = !session.maximized;
+        for saved in session.terminals {
+            // A recording that's gone since gets a shell in its place
+            let playback = saved.recording.as_deref().and_then(|path| {
+                Playback::open(path).map_err(|e| eprintln!("Warning: {}", e)).ok()
+            });
+            let id = match playback {
+                Some(playback) => manager.open_playback(playback),
+                None => manager.open_terminal(saved.working_dir, saved.profile.as_deref()),
+            };
+            let terminal = &mut manager.terminals[id];
+            terminal.set_hue(saved.hue);
+            terminal.set_shade(saved.saturation.unwrap_or(DEFAULT_SATURATION), saved.lightness.unwrap_or(DEFAULT_LIGHTNESS));
+            if let Some(title) = saved.title {
+                terminal.set_title(&title);
             }
+            terminal.set_icon(saved.icon);
+            terminal.set_color_scheme(saved.color_scheme.as_deref());
+            terminal.set_palette_edits(&saved.palette);
+            terminal.set_background(saved.opacity.unwrap_or(1.0), saved.background_image);
+            manager.last_hue = saved.hue + 55.0;
         }
-        for &idx in &self.bottom_row_terminals {
-            if let Some(terminal) = self.terminals.get_mut(idx) {
-                terminal.set_dark_mode(dark_mode);
+        // A tab saved (or edited) with no panes still opens with a shell
+        if manager.num_terminals == 0 {
+            let id = manager.open_terminal(None, None);
+            manager.focus_terminal(id);
+        }
+        // A layout that doesn't match the panes (say, an edited file) gets columns instead
+        manager.layout = match session.layout {
+            Some(layout) if layout.has_panes(manager.num_terminals) => Some(layout),
+            _ => {
+                let mut layout = (manager.num_terminals > 0).then_some(SplitNode::Pane(0));
+                for id in 1..manager.num_terminals {
+                    layout = layout.map(|layout| layout.append(id, SplitDirection::SideBySide));
+                }
+                layout
             }
+        };
+        if let Some(id) = session.active_terminal {
+            manager.focus_terminal(id);
+        }
+        manager
+    }
+
+    pub fn session(&self, name: &str) -> TabSession {
+        TabSession {
+            name: name.to_string(),
+            terminals: self.terminals.iter().map(Terminal::session).collect(),
+            layout: self.layout.clone(),
+            active_terminal: self.active_terminal_id,
+            maximized: !self.show_all,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: Config) {
+        // A new base hue recolors the panes, undoing any slider tweaks
+        let recolor = config.hue != self.config.hue;
+        if recolor {
+            self.last_hue = config.hue;
+        }
+        for terminal in &mut self.terminals {
+            terminal.set_config(&config);
+            if recolor {
+                terminal.set_hue(self.last_hue);
+                self.last_hue += 55.0;
+            }
+        }
+        self.config = config;
+    }
+
+    pub fn set_dark_mode(&mut self, dark_mode: bool) {
+        for terminal in &mut self.terminals {
+            terminal.set_dark_mode(dark_mode);
         }
     }
 
@@ -56,77 +154,146 @@ impl TerminalManager {
     }
 
     pub fn resize_terminals(&mut self, available_width: f32, available_height: f32){
-        let border_width = 2.0;
-        
-        let top_count = self.top_row_terminals.len().max(1) as f32;
-        let top_terminal_width: f32 = (available_width - (border_width * top_count)) / top_count;
-        let top_terminal_height: f32 = if self.bottom_row_terminals.len() > 0 { 
-            available_height / 2.0
-        } else {
-            available_height
+        let Some(layout) = &self.layout else {
+            return;
         };
-        
-        let bottom_count = self.bottom_row_terminals.len().max(1) as f32;
-        let bottom_terminal_width: f32 = if self.bottom_row_terminals.len() > 0 {
-            (available_width - (border_width * bottom_count)) / bottom_count
-        } else {
-            available_width
-        };
-        let bottom_terminal_height: f32 = available_height / 2.0;
-
-        for &idx in &self.top_row_terminals {
+        let area = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(available_width, available_height));
+        for (idx, rect) in layout.rects(area) {
             if let Some(terminal) = self.terminals.get_mut(idx) {
-                terminal.set_width(top_terminal_width);
-                terminal.set_height(top_terminal_height);
+                terminal.set_width(rect.width());
+                terminal.set_height(rect.height());
             }
         }
-        for &idx in &self.bottom_row_terminals {
-            if let Some(terminal) = self.terminals.get_mut(idx) {
-                terminal.set_width(bottom_terminal_width);
-                terminal.set_height(bottom_terminal_height);
-            }
+    }
+
+    // Open another terminal where the layout setting puts it: a new column, or half of
+    // the active pane along its longer side
+    pub fn add_terminal(&mut self, available_width: f32, available_height: f32) -> Option<usize> {
+        self.add_profile_terminal(None, available_width, available_height)
+    }
+
+    // Like add_terminal, with a profile by name instead of the default one
+    pub fn add_profile_terminal(&mut self, profile: Option<&str>, available_width: f32, available_height: f32) -> Option<usize> {
+        if !self.can_add_terminal() {
+            self.limit_notice_at = Some(std::time::Instant::now());
+            return None;
         }
+        let id = self.open_terminal(None, profile);
+        self.place_terminal(id, available_width, available_height);
+        Some(id)
     }
-    
-    pub fn rearrange_terminals(&mut self) {
-        self.top_row_terminals.clear();
-        self.bottom_row_terminals.clear();
 
-        if self.num_terminals <= 2 {
-            self.top_row_terminals = (0..self.num_terminals).collect();
-        } else {
-            let mid = self.num_terminals / 2;
-            self.top_row_terminals = (0..mid).collect();
-            self.bottom_row_terminals = (mid..self.num_terminals).collect();
+    // A pane playing back a recording, placed like add_terminal's
+    pub fn add_playback(&mut self, playback: Playback, available_width: f32, available_height: f32) -> Option<usize> {
+        if !self.can_add_terminal() {
+            self.limit_notice_at = Some(std::time::Instant::now());
+            return None;
         }
+        let id = self.open_playback(playback);
+        self.place_terminal(id, available_width, available_height);
+        Some(id)
     }
 
-    pub fn add_terminal(&mut self, available_width: f32, available_height: f32) -> Option<usize> {
-        if self.num_terminals + 1 > 6 {
-            None
-        } else {
-            let id = self.num_terminals;
-            let mut terminal = Terminal::new(id, 100.0, 100.0, self.last_hue, !self.show_all);
-            
-            // Make first terminal active by default
-            if self.num_terminals == 0 {
-                terminal.set_active(true);
-                self.active_terminal_id = Some(id);
+    // Put a new terminal in the layout: a new column, or half of the active pane
+    fn place_terminal(&mut self, id: usize, available_width: f32, available_height: f32) {
+        self.layout = Some(match self.layout.take() {
+            None => SplitNode::Pane(id),
+            Some(layout) if self.config.layout == Layout::Columns => layout.append(id, SplitDirection::SideBySide),
+            Some(mut layout) => {
+                let active = self.active_terminal_id.and_then(|idx| self.terminals.get(idx).map(|t| (idx, t)));
+                match active {
+                    Some((idx, terminal)) => {
+                        let direction = if terminal.width >= terminal.height { SplitDirection::SideBySide } else { SplitDirection::Stacked };
+                        layout.split(idx, id, direction);
+                        layout
+                    }
+                    None => layout.append(id, SplitDirection::SideBySide),
+                }
             }
-            
-            self.terminals.push(terminal);
-            self.num_terminals += 1;
+        });
+        self.resize_terminals(available_width, available_height);
+    }
+
+    // Split the active pane, putting a new terminal to its right or below it, and focus that
+    pub fn split_terminal(&mut self, direction: SplitDirection, available_width: f32, available_height: f32) {
+        let Some(active) = self.active_terminal_id else {
+            self.add_terminal(available_width, available_height);
+            return;
+        };
+        if !self.can_add_terminal() {
+            self.limit_notice_at = Some(std::time::Instant::now());
+            return;
+        }
+        let id = self.open_terminal(None, None);
+        if let Some(layout) = &mut self.layout {
+            layout.split(active, id, direction);
+        }
+        self.set_active_terminal(id);
+        self.show_all = true;
+        self.resize_terminals(available_width, available_height);
+    }
+
+    pub fn can_add_terminal(&self) -> bool {
+        self.config.max_terminals.is_none_or(|max| self.num_terminals < max)
+    }
+
+    // A new terminal at the end of the list, not yet placed in the layout. Without a
+    // profile name it gets the default profile.
+    fn open_terminal(&mut self, working_dir: Option<PathBuf>, profile: Option<&str>) -> usize {
+        let id = self.num_terminals;
+        let profile = match profile {
+            Some(name) => self.config.profile(name),
+            None => self.config.default_profile(),
+        };
+        // A profile's own color doesn't use up a step around the wheel
+        let profile_hue = profile.and_then(|p| p.hue);
+        let terminal = Terminal::new(id, profile_hue.unwrap_or(self.last_hue), !self.show_all, &self.config, profile, working_dir);
+        self.push_terminal(terminal);
+        if profile_hue.is_none() {
             self.last_hue += 55.0;
-            self.rearrange_terminals();
-            self.resize_terminals(available_width, available_height);
-            Some(self.num_terminals - 1)
+        }
+        id
+    }
+
+    // Like open_terminal, playing back a recording instead of starting a shell
+    fn open_playback(&mut self, playback: Playback) -> usize {
+        let id = self.num_terminals;
+        let terminal = Terminal::playback(id, self.last_hue, !self.show_all, &self.config, playback);
+        self.push_terminal(terminal);
+        self.last_hue += 55.0;
+        id
+    }
+
+    fn push_terminal(&mut self, mut terminal: Terminal) {
+        // Make first terminal active by default
+        if self.num_terminals == 0 {
+            terminal.set_active(true);
+            self.active_terminal_id = Some(self.num_terminals);
+        }
+
+        self.terminals.push(terminal);
+        self.num_terminals += 1;
+    }
+
+    // Focus the last-active terminal, or open one if there are none
+    pub fn summon(&mut self, available_width: f32, available_height: f32) {
+        match self.active_terminal_id {
+            Some(id) => self.set_active_terminal(id),
+            None if self.terminals.is_empty() => {
+                self.add_terminal(available_width, available_height);
+            }
+            None => self.set_active_terminal(0),
         }
     }
 
     pub fn remove_terminal(&mut self, index: usize, available_width: f32, available_height: f32) -> Option<Terminal> {
+        // Pane numbers are about to change under the dialog
+        self.closing = None;
         if index < self.terminals.len() {
             self.num_terminals -= 1;
-            let removed = Some(self.terminals.remove(index));
+            let mut removed = self.terminals.remove(index);
+            // Kill the shell and close its PTY now, not whenever the caller drops it
+            removed.shutdown();
             
             // Update IDs of all remaining terminals to match their new indices
             for (new_id, terminal) in self.terminals.iter_mut().enumerate() {
@@ -146,59 +313,207 @@ impl TerminalManager {
                 }
             }
             
-            self.rearrange_terminals();
+            self.layout = self.layout.take().and_then(|layout| layout.remove(index));
             self.resize_terminals(available_width, available_height);
-            removed
+            Some(removed)
         } else {
             None
         }
     }
 
-    pub fn update(&mut self, _ui: &mut egui::Ui, available_width: f32, available_height: f32){
+    pub fn take_finished_commands(&mut self) -> Vec<CommandRecord> {
+        std::mem::take(&mut self.finished_commands)
+    }
+
+    pub fn terminal_count(&self) -> usize {
+        self.terminals.len()
+    }
+
+    // The strongest badge among the panes, for the tab
+    pub fn badge(&self) -> Option<Badge> {
+        self.terminals.iter().filter_map(Terminal::badge).max()
+    }
+
+    pub fn is_translucent(&self) -> bool {
+        self.terminals.iter().any(Terminal::is_translucent)
+    }
+
+    // Type a command into a terminal's shell, as if entered at the prompt
+    pub fn run_command(&mut self, id: usize, command: &str) {
+        if let Some(terminal) = self.terminals.get_mut(id) {
+            terminal.run_command(command);
+        }
+    }
+
+    // Rename a terminal by id, for IPC and scripting. Returns false if there's no such terminal.
+    #[allow(dead_code)]  // Nothing calls it from outside the UI yet
+    pub fn rename(&mut self, id: usize, title: &str) -> bool {
+        match self.terminals.get_mut(id) {
+            Some(terminal) => {
+                terminal.set_title(title);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Runs every frame, for tabs in the background too, so their panes keep up with output
+    pub fn update(&mut self, ui: &mut egui::Ui, available_width: f32, available_height: f32){
+        // Periodically reap exited shells so they don't stay around as zombies
+        for terminal in &mut self.terminals {
+            terminal.read_output(ui.ctx());
+            terminal.reap_child();
+            terminal.track_foreground_process();
+            self.finished_commands.extend(terminal.take_finished_commands());
+        }
+
+        // Deliver text from "send" token actions to the active pane
+        let outgoing: Vec<String> = self.terminals.iter_mut().filter_map(|t| t.take_outgoing_text()).collect();
+        if let Some(active) = self.active_terminal_id.and_then(|id| self.terminals.get_mut(id)) {
+            for text in outgoing {
+                active.send_text(&text);
+            }
+        }
         self.resize_terminals(available_width, available_height);
     }
 
+    // Pane shortcuts ================================
+    // For the app's keyboard shortcuts, so panes can be managed without the mouse
+
+    pub fn new_terminal(&mut self, available_width: f32, available_height: f32) {
+        if let Some(id) = self.add_terminal(available_width, available_height) {
+            self.set_active_terminal(id);
+        }
+    }
+
+    pub fn close_active_terminal(&mut self, available_width: f32, available_height: f32) {
+        if let Some(id) = self.active_terminal_id {
+            self.close_terminal(id, available_width, available_height);
+        }
+    }
+
+    // Close a pane, asking first if programs are still running in it
+    pub fn close_terminal(&mut self, id: usize, available_width: f32, available_height: f32) {
+        let processes = match self.terminals.get(id) {
+            Some(terminal) if self.config.confirm_close => terminal.running_processes(),
+            _ => Vec::new(),
+        };
+        if processes.is_empty() {
+            self.remove_terminal(id, available_width, available_height);
+        } else {
+            self.closing = Some((id, processes));
+        }
+    }
+
+    // What's running in each pane, as "title: command", for confirming a close
+    pub fn running_processes(&self) -> Vec<String> {
+        if !self.config.confirm_close {
+            return Vec::new();
+        }
+        self.terminals.iter()
+            .flat_map(|terminal| {
+                let title = terminal.get_title();
+                terminal.running_processes().into_iter().map(move |process| format!("{}: {}", title, process))
+            })
+            .collect()
+    }
+
+    // A fullscreen program is running in the active pane
+    pub fn active_raw_mode(&self) -> bool {
+        self.active_terminal_id
+            .and_then(|id| self.terminals.get(id))
+            .is_some_and(|terminal| terminal.is_raw_mode())
+    }
+
+    // The active pane's program reads keys itself (fullscreen, or line editing off)
+    pub fn active_keys_to_program(&self) -> bool {
+        self.active_terminal_id
+            .and_then(|id| self.terminals.get(id))
+            .is_some_and(|terminal| terminal.keys_to_program())
+    }
+
+    // Focus a pane by its position in the list (0 is the first)
+    pub fn focus_terminal(&mut self, index: usize) {
+        if index < self.terminals.len() {
+            self.set_active_terminal(index);
+        }
+    }
+
+    pub fn focus_next_terminal(&mut self) {
+        let count = self.terminals.len();
+        if count > 0 {
+            self.set_active_terminal((self.active_terminal_id.unwrap_or(0) + 1) % count);
+        }
+    }
+
+    pub fn focus_previous_terminal(&mut self) {
+        let count = self.terminals.len();
+        if count > 0 {
+            self.set_active_terminal((self.active_terminal_id.unwrap_or(0) + count - 1) % count);
+        }
+    }
+
+    // Focus the pane on the given side of the active one: the nearest pane that
+    // lines up with it, preferring the one level with its middle
+    pub fn focus_neighbor(&mut self, direction: FocusDirection) {
+        let (Some(layout), Some(active)) = (&self.layout, self.active_terminal_id) else {
+            return;
+        };
+        // Any area will do; only the arrangement matters
+        let rects = layout.rects(egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(1000.0, 1000.0)));
+        let Some(&(_, from)) = rects.iter().find(|(id, _)| *id == active) else {
+            return;
+        };
+        let neighbor = rects.iter()
+            .filter(|(id, _)| *id != active)
+            .filter_map(|&(id, rect)| {
+                let (distance, lined_up, offset) = match direction {
+                    FocusDirection::Left => (from.left() - rect.right(), overlaps(from.y_range(), rect.y_range()), (rect.center().y - from.center().y).abs()),
+                    FocusDirection::Right => (rect.left() - from.right(), overlaps(from.y_range(), rect.y_range()), (rect.center().y - from.center().y).abs()),
+                    FocusDirection::Up => (from.top() - rect.bottom(), overlaps(from.x_range(), rect.x_range()), (rect.center().x - from.center().x).abs()),
+                    FocusDirection::Down => (rect.top() - from.bottom(), overlaps(from.x_range(), rect.x_range()), (rect.center().x - from.center().x).abs()),
+                };
+                (distance >= 0.0 && lined_up).then_some((id, distance, offset))
+            })
+            .min_by(|a, b| (a.1, a.2).partial_cmp(&(b.1, b.2)).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(id, _, _)| id);
+        if let Some(id) = neighbor {
+            self.set_active_terminal(id);
+        }
+    }
+
     fn render_all(&mut self, ui: &mut egui::Ui) {
-        ui.vertical(|ui| {
-            ui.style_mut().spacing.item_spacing.y = 0.0;
-            ui.horizontal(|ui| {
-                ui.style_mut().spacing.item_spacing.x = 0.0;
-                for &idx in &self.top_row_terminals.clone() {
-                    if let Some(terminal) = self.terminals.get_mut(idx) {
-                        terminal.set_maximized(false);
-                        let terminal_response = terminal.render(ui);
-                        if terminal_response == TerminalResponse::WasClicked {
-                            self.set_active_terminal(idx);
-                        } else if terminal_response == TerminalResponse::CloseMe { 
-                            self.remove_terminal(idx, ui.available_width(), ui.available_height());
-                        } else if terminal_response == TerminalResponse::MaximizeMe {
-                            self.set_active_terminal(idx);
-                            self.show_all = false;
-                        }
-                    }
-                }
-            });
-            
-            if self.bottom_row_terminals.len() > 0 {
-                ui.style_mut().spacing.item_spacing.x = 0.0;
-                ui.horizontal(|ui| {
-                    for &idx in &self.bottom_row_terminals.clone() {
-                        if let Some(terminal) = self.terminals.get_mut(idx) {
-                            terminal.set_maximized(false);
-                            let terminal_response = terminal.render(ui);
-                            if terminal_response == TerminalResponse::WasClicked {
-                                self.set_active_terminal(idx);
-                            } else if terminal_response == TerminalResponse::CloseMe { 
-                                self.remove_terminal(idx, ui.available_width(), ui.available_height());
-                            } else if terminal_response == TerminalResponse::MaximizeMe {
-                                self.set_active_terminal(idx);
-                                self.show_all = false;
-                            }
-                        }
-                    }
-                });
+        let area = ui.available_rect_before_wrap();
+        let panes = self.layout.as_ref().map(|layout| layout.rects(area)).unwrap_or_default();
+        let mut responses = Vec::new();
+        for (idx, rect) in panes {
+            if let Some(terminal) = self.terminals.get_mut(idx) {
+                terminal.set_maximized(false);
+                let terminal_response = ui.scope_builder(egui::UiBuilder::new().max_rect(rect), |ui| terminal.render(ui)).inner;
+                responses.push((idx, terminal_response));
             }
-        });
+        }
+        if let Some(layout) = &mut self.layout {
+            layout.drag_dividers(ui, area, ui.id().with("split_dividers"));
+        }
+        ui.allocate_rect(area, egui::Sense::hover());
+
+        // Closing a pane renumbers the ones after it, so go from the last pane back. The
+        // layout lists panes in tree order, which isn't always by number.
+        responses.sort_by_key(|&(idx, _)| std::cmp::Reverse(idx));
+        for (idx, terminal_response) in responses {
+            if terminal_response == TerminalResponse::WasClicked {
+                self.set_active_terminal(idx);
+            } else if terminal_response == TerminalResponse::CloseMe {
+                self.close_terminal(idx, area.width(), area.height());
+            } else if terminal_response == TerminalResponse::MaximizeMe {
+                self.set_active_terminal(idx);
+                self.show_all = false;
+            } else if let TerminalResponse::SplitMe(direction) = terminal_response {
+                self.set_active_terminal(idx);
+                self.split_terminal(direction, area.width(), area.height());
+            }
+        }
     }
 
     fn render_single(&mut self, ui: &mut egui::Ui) {
@@ -211,19 +526,21 @@ impl TerminalManager {
             let terminal_height = ui.available_height() - tab_bar_height;
             
             // Render the active terminal
-            if let Some(active_id) = self.active_terminal_id {
-                if let Some(terminal) = self.terminals.get_mut(active_id) {
-                    // Set terminal to full width and available height
-                    terminal.set_width(ui.available_width());
-                    terminal.set_height(terminal_height);
-                    terminal.set_maximized(true);
-                    
-                    let terminal_response = terminal.render(ui);
-                    if terminal_response == TerminalResponse::CloseMe {
-                        self.remove_terminal(active_id, ui.available_width(), ui.available_height());
-                    } else if terminal_response == TerminalResponse::MinimizeMe {
-                        self.show_all = true;
-                    }
+            if let Some(active_id) = self.active_terminal_id
+                && let Some(terminal) = self.terminals.get_mut(active_id)
+            {
+                // Set terminal to full width and available height
+                terminal.set_width(ui.available_width());
+                terminal.set_height(terminal_height);
+                terminal.set_maximized(true);
+                
+                let terminal_response = terminal.render(ui);
+                if terminal_response == TerminalResponse::CloseMe {
+                    self.close_terminal(active_id, ui.available_width(), ui.available_height());
+                } else if terminal_response == TerminalResponse::MinimizeMe {
+                    self.show_all = true;
+                } else if let TerminalResponse::SplitMe(direction) = terminal_response {
+                    self.split_terminal(direction, ui.available_width(), ui.available_height());
                 }
             }
             
@@ -238,8 +555,12 @@ impl TerminalManager {
                 for (idx, terminal) in self.terminals.iter_mut().enumerate() {
                     let is_active = Some(idx) == self.active_terminal_id;
                     
+                    let label = match terminal.get_icon() {
+                        Some(icon) => format!("{} {}", icon, terminal.get_title()),
+                        None => terminal.get_title(),
+                    };
                     let button = egui::Button::new(
-                        egui::RichText::new(terminal.get_title())
+                        egui::RichText::new(label)
                             .size(14.0)
                             .color(terminal.get_text_color())
                     )
@@ -272,5 +593,47 @@ impl TerminalManager {
         } else {
             self.render_single(ui);
         }
+        self.render_limit_notice(ui);
+        self.render_close_confirmation(ui);
+    }
+
+    fn render_close_confirmation(&mut self, ui: &egui::Ui) {
+        let Some((id, processes)) = &self.closing else {
+            return;
+        };
+        let id = *id;
+        match confirm_close(ui.ctx(), "Close this pane?", processes, "Close") {
+            Some(true) => {
+                let area = ui.max_rect();
+                self.remove_terminal(id, area.width(), area.height());
+            }
+            Some(false) => self.closing = None,
+            None => {}
+        }
+    }
+
+    // Say why nothing happened when the add button or a shortcut hit max_terminals
+    fn render_limit_notice(&mut self, ui: &egui::Ui) {
+        let Some(shown_at) = self.limit_notice_at else {
+            return;
+        };
+        if shown_at.elapsed().as_secs() >= 3 {
+            self.limit_notice_at = None;
+            return;
+        }
+        ui.ctx().request_repaint_after(std::time::Duration::from_secs(3).saturating_sub(shown_at.elapsed()));
+        let max = self.config.max_terminals.unwrap_or(self.num_terminals);
+        egui::Area::new(ui.id().with("limit_notice"))
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -24.0))
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(format!("This tab already has {} panes, the max_terminals limit", max));
+                });
+            });
     }
-}
\ No newline at end of file
+}
+
+// True if two spans share more than a sliver
+fn overlaps(a: egui::Rangef, b: egui::Rangef) -> bool {
+    a.max.min(b.max) - a.min.max(b.min) > 1.0
+}
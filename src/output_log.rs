@@ -0,0 +1,99 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Output log =========================================
+// Everything a pane receives appended to a file, for auditing long sessions or
+// keeping a record of a build. Either raw, escape sequences and all (`cat` it back
+// into a terminal to replay the colors), or as plain text, and optionally with the
+// time at the start of each line.
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct LogOptions {
+    pub timestamps: bool,  // "[2026-03-14 09:26:53] " before each line
+    pub strip_ansi: bool,  // Drop escape sequences and carriage returns
+}
+
+pub struct OutputLog {
+    file: File,
+    path: PathBuf,
+    options: LogOptions,
+    at_line_start: bool,
+    escape: Escape,  // Where a stripped escape sequence left off at the end of a chunk
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Escape {
+    None,
+    Started,  // After ESC
+    Csi,      // ESC [ up to a final byte
+    String,   // OSC/DCS/APC, up to BEL or ST
+    StringEsc,  // ESC inside a string, maybe the start of ST
+}
+
+impl OutputLog {
+    pub fn open(path: &Path, options: LogOptions) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file, path: path.to_path_buf(), options, at_line_start: true, escape: Escape::None })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) {
+        if !self.options.strip_ansi && !self.options.timestamps {
+            let _ = self.file.write_all(chunk);
+            return;
+        }
+        let mut out = Vec::with_capacity(chunk.len());
+        for &byte in chunk {
+            if self.options.strip_ansi && !self.keep(byte) {
+                continue;
+            }
+            if self.options.timestamps && self.at_line_start {
+                out.extend_from_slice(format!("[{}] ", local_timestamp()).as_bytes());
+            }
+            out.push(byte);
+            self.at_line_start = byte == b'\n';
+        }
+        let _ = self.file.write_all(&out);
+    }
+
+    // Step through escape sequences; true for bytes that are text
+    fn keep(&mut self, byte: u8) -> bool {
+        self.escape = match (self.escape, byte) {
+            (Escape::None, 0x1b) => Escape::Started,
+            (Escape::None, b'\r') => return false,
+            (Escape::None, b'\n' | b'\t') => return true,
+            (Escape::None, byte) => return byte >= 0x20 && byte != 0x7f,
+            (Escape::Started, b'[') => Escape::Csi,
+            (Escape::Started, b']' | b'P' | b'_' | b'^' | b'X') => Escape::String,
+            // Two-byte sequences like ESC 7, and charset picks like ESC ( B
+            (Escape::Started, b'(' | b')' | b'*' | b'+' | b'#' | b' ' | b'%') => Escape::Started,
+            (Escape::Started, _) => Escape::None,
+            (Escape::Csi, 0x40..=0x7e) => Escape::None,
+            (Escape::Csi, _) => Escape::Csi,
+            (Escape::String | Escape::StringEsc, 0x07) => Escape::None,
+            (Escape::String | Escape::StringEsc, 0x1b) => Escape::StringEsc,
+            (Escape::StringEsc, b'\\') => Escape::None,
+            (Escape::String | Escape::StringEsc, _) => Escape::String,
+        };
+        false
+    }
+}
+
+// The local time as "2026-03-14 09:26:53"
+fn local_timestamp() -> String {
+    // SAFETY: localtime_r only writes into the tm struct we hand it
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
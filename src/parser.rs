@@ -1,102 +1,290 @@
 use eframe::egui;
-use crate::utils::ColorSet;
+use crate::theme::Palette;
 
+#[derive(Hash)]
 pub struct TerminalOutput {
     pub text: String,
     pub color: egui::Color32,
+    pub background: Option<egui::Color32>,  // None leaves the pane background showing
     pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub wide: bool,  // A single double-width character, drawn two cells wide
 }
 
-pub fn parse_ansi_output(output: &str, color_set: &ColorSet, default_color: egui::Color32) -> Vec<TerminalOutput> {
-    let mut segments = Vec::new();
-    let mut current_color = default_color;
-    let mut current_text = String::new();
-    let mut bold = false;
-    
-    let mut chars = output.chars().peekable();
-    while let Some(ch) = chars.next() {
-        if ch == '\x1b' {
-            // Save current segment before processing escape sequence
-            if !current_text.is_empty() {
-                segments.push(TerminalOutput {
-                    text: current_text.clone(),
-                    color: current_color,
-                    bold,
-                });
-                current_text.clear();
-            }
-            
-            // Check what type of escape sequence this is
-            match chars.peek() {
-                Some(&'[') => {
-                    // CSI (Control Sequence Introducer) - most common
-                    chars.next(); // consume '['
-                    let mut code = String::new();
-                    
-                    // Read until a letter (command character)
-                    while let Some(&ch) = chars.peek() {
-                        if ch.is_ascii_alphabetic() || ch == 'm' {
-                            chars.next(); // consume the command character
-                            break;
+impl TerminalOutput {
+    pub fn new(text: String, style: &CellStyle, palette: &Palette, default_color: egui::Color32, default_background: egui::Color32) -> Self {
+        let (color, background) = style.colors(palette, default_color, default_background);
+        Self {
+            text,
+            color,
+            background,
+            bold: style.bold,
+            italic: style.italic,
+            underline: style.underline,
+            strikethrough: style.strikethrough,
+            wide: false,
+        }
+    }
+
+    // Part of the segment's text, drawn in its style
+    pub fn rich_text(&self, text: &str, size: f32) -> egui::RichText {
+        let mut text = egui::RichText::new(text)
+            .size(size)
+            .color(self.color)
+            .monospace();
+        if let Some(background) = self.background {
+            text = text.background_color(background);
+        }
+        if self.bold {
+            text = text.strong();
+        }
+        if self.italic {
+            text = text.italics();
+        }
+        if self.underline {
+            text = text.underline();
+        }
+        if self.strikethrough {
+            text = text.strikethrough();
+        }
+        text
+    }
+}
+
+// Styles =============================================
+// Colors are kept logical (palette index) and only resolved against the pane's
+// palette when drawn, so changing the hue or scheme recolors existing output
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TermColor {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),  // Truecolor, shown as is
+}
+
+impl TermColor {
+    pub fn resolve(self, palette: &Palette, default_color: egui::Color32) -> egui::Color32 {
+        match self {
+            TermColor::Indexed(n @ 0..=15) => palette[n as usize],
+            TermColor::Indexed(n) => xterm_color(n),
+            TermColor::Rgb(r, g, b) => egui::Color32::from_rgb(r, g, b),
+            TermColor::Default => default_color,
+        }
+    }
+}
+
+// Fixed part of the xterm 256-color palette: a 6x6x6 color cube (16-231)
+// followed by a 24 step grayscale ramp (232-255)
+fn xterm_color(index: u8) -> egui::Color32 {
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    if index >= 232 {
+        let gray = 8 + (index - 232) * 10;
+        return egui::Color32::from_rgb(gray, gray, gray);
+    }
+    let cube = index.saturating_sub(16) as usize;
+    egui::Color32::from_rgb(CUBE_LEVELS[cube / 36], CUBE_LEVELS[cube / 6 % 6], CUBE_LEVELS[cube % 6])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CellStyle {
+    pub fg: TermColor,
+    pub bg: TermColor,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub inverse: bool,  // Swap text and background colors
+}
+
+impl CellStyle {
+    // Text color and background (None leaves the pane background showing)
+    pub fn colors(&self, palette: &Palette, default_color: egui::Color32, default_background: egui::Color32) -> (egui::Color32, Option<egui::Color32>) {
+        let fg = self.fg.resolve(palette, default_color);
+        let bg = match self.bg {
+            TermColor::Default => None,
+            bg => Some(bg.resolve(palette, default_color)),
+        };
+        let (fg, bg) = if self.inverse { (bg.unwrap_or(default_background), Some(fg)) } else { (fg, bg) };
+        let fg = if self.dim { fg.gamma_multiply(0.6) } else { fg };
+        (fg, bg)
+    }
+
+    // Apply SGR (Select Graphic Rendition) parameters, e.g. from "\x1b[1;31m"
+    pub fn apply_sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = CellStyle::default();
+        }
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = CellStyle::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.inverse = true,
+                9 => self.strikethrough = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                29 => self.strikethrough = false,
+                30..=37 => self.fg = TermColor::Indexed((param - 30) as u8),
+                90..=97 => self.fg = TermColor::Indexed((param - 90 + 8) as u8),
+                39 => self.fg = TermColor::Default,
+                40..=47 => self.bg = TermColor::Indexed((param - 40) as u8),
+                100..=107 => self.bg = TermColor::Indexed((param - 100 + 8) as u8),
+                49 => self.bg = TermColor::Default,
+                // Extended colors: 38;5;N / 48;5;N (256-color) or 38;2;R;G;B (truecolor). Their
+                // arguments are consumed even when incomplete so they aren't read as codes.
+                38 | 48 => match params.next() {
+                    Some(5) => {
+                        if let Some(index) = params.next() {
+                            let color = TermColor::Indexed(index.min(255) as u8);
+                            if param == 38 { self.fg = color } else { self.bg = color }
                         }
-                        code.push(chars.next().unwrap());
                     }
-                    
-                    // Only parse color codes (those ending with 'm' or in our code string)
-                    if code.chars().all(|c| c.is_ascii_digit() || c == ';') {
-                        // Parse SGR (Select Graphic Rendition) codes
-                        for part in code.split(';') {
-                            match part {
-                                "0" | "00" => {
-                                    current_color = default_color;
-                                    bold = false;
-                                }
-                                "1" | "01" => bold = true,
-                                "31" => current_color = color_set.alert,       // Red -> alert
-                                "32" => current_color = color_set.primary,     // Green -> primary
-                                "33" => current_color = color_set.warning,     // Yellow -> warning
-                                "34" => current_color = color_set.alternate_1,     // Blue -> alternate_1
-                                "35" => current_color = color_set.alternate_2,       // Magenta -> alternate_2
-                                "36" => current_color = color_set.alternate_3,     // Cyan -> alternate_3
-                                _ => {} // Ignore unknown codes
-                            }
+                    Some(2) => {
+                        let mut channel = || params.next().map(|n| n.min(255) as u8);
+                        if let (Some(r), Some(g), Some(b)) = (channel(), channel(), channel()) {
+                            let color = TermColor::Rgb(r, g, b);
+                            if param == 38 { self.fg = color } else { self.bg = color }
                         }
                     }
-                    // All other CSI sequences are ignored (cursor movement, etc.)
+                    _ => {}
+                },
+                _ => {} // Ignore unknown codes
+            }
+        }
+    }
+}
+
+// UTF-8 decoding =====================================
+// PTY reads can end in the middle of a multibyte character (emoji, CJK), so an
+// incomplete sequence at the end of a chunk is held back until the next one
+#[derive(Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    // Invalid bytes become U+FFFD, like String::from_utf8_lossy
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut text = String::new();
+        let mut input = &self.pending[..];
+        loop {
+            match std::str::from_utf8(input) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    input = &[];
+                    break;
                 }
-                Some(&']') => {
-                    // OSC (Operating System Command) - like window title
-                    chars.next(); // consume ']'
-                    
-                    // Read until BEL (\x07) or ST (ESC \)
-                    while let Some(ch) = chars.next() {
-                        if ch == '\x07' {
-                            break;
+                Err(e) => {
+                    let (valid, rest) = input.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            input = &rest[len..];
                         }
-                        if ch == '\x1b' && chars.peek() == Some(&'\\') {
-                            chars.next(); // consume '\'
+                        // Incomplete sequence at the end: wait for the rest
+                        None => {
+                            input = rest;
                             break;
                         }
                     }
                 }
-                _ => {
-                    // Other escape sequences - consume next character
-                    chars.next();
-                }
             }
-        } else {
-            current_text.push(ch);
         }
+        self.pending = input.to_vec();
+        text
+    }
+}
+
+// Tokenizer ==========================================
+// Splits output into printable characters, control characters, and escape sequences,
+// keeping partial sequences around between feeds. The state machine is vte's (the DEC
+// parser described at vt100.net), which gets the corners right: DCS, SOS, PM and APC
+// strings are skipped whole, CAN and SUB cancel a sequence, controls inside one still
+// take effect, and malformed sequences end where a real terminal would end them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Print(char),
+    Control(char),  // C0 controls: \n, \r, \x08, \t, ...
+    Csi { private: Option<char>, params: Vec<u16>, intermediate: Option<char>, action: char },
+    Osc(String),
+    Esc { intermediate: Option<char>, action: char },
+}
+
+// OSC strings longer than this are cut off rather than growing forever
+const MAX_SEQUENCE_LEN: usize = 4096;
+
+#[derive(Default)]
+pub struct Tokenizer {
+    parser: vte::Parser<MAX_SEQUENCE_LEN>,
+}
+
+impl Tokenizer {
+    pub fn feed(&mut self, text: &str, emit: impl FnMut(Token)) {
+        let mut performer = Performer(emit);
+        // 8-bit controls arrive UTF-8 encoded (U+009B for CSI, U+009C for ST...), which is
+        // how xterm reads them in UTF-8 mode. vte only knows their 7-bit forms, so each
+        // goes in as ESC and the matching letter.
+        let mut rest = text;
+        while let Some(at) = rest.find(|c| ('\u{80}'..='\u{9f}').contains(&c)) {
+            self.parser.advance(&mut performer, &rest.as_bytes()[..at]);
+            // Encoded as C2 80..C2 9F, so the second byte is the control itself
+            self.parser.advance(&mut performer, &[0x1b, rest.as_bytes()[at + 1] - 0x40]);
+            rest = &rest[at + 2..];
+        }
+        self.parser.advance(&mut performer, rest.as_bytes());
+    }
+}
+
+// Hands what vte parses on as tokens
+struct Performer<F: FnMut(Token)>(F);
+
+impl<F: FnMut(Token)> vte::Perform for Performer<F> {
+    fn print(&mut self, ch: char) {
+        (self.0)(Token::Print(ch));
     }
-    
-    // Add final segment
-    if !current_text.is_empty() {
-        segments.push(TerminalOutput {
-            text: current_text,
-            color: current_color,
-            bold,
-        });
+
+    fn execute(&mut self, byte: u8) {
+        (self.0)(Token::Control(byte as char));
     }
-    
-    segments
-}
\ No newline at end of file
+
+    fn csi_dispatch(&mut self, params: &vte::Params, intermediates: &[u8], ignore: bool, action: char) {
+        // Too many parameters or intermediates to be anything we know
+        if ignore {
+            return;
+        }
+        // vte collects a leading ? > = or < with the intermediates
+        let (private, intermediates) = match intermediates.split_first() {
+            Some((&first, rest)) if (b'<'..=b'?').contains(&first) => (Some(first as char), rest),
+            _ => (None, intermediates),
+        };
+        // Sub-parameters (38:2:R:G:B) are taken like the rest
+        let params = params.iter().flatten().copied().collect();
+        (self.0)(Token::Csi { private, params, intermediate: intermediates.first().map(|&b| b as char), action });
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        // The \ of ST (ESC \): vte ends the string at the ESC, leaving nothing to do
+        let string_terminator = byte == b'\\' && intermediates.is_empty();
+        if !ignore && !string_terminator {
+            (self.0)(Token::Esc { intermediate: intermediates.first().map(|&b| b as char), action: byte as char });
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        let fields: Vec<_> = params.iter().map(|field| String::from_utf8_lossy(field)).collect();
+        (self.0)(Token::Osc(fields.join(";")));
+    }
+}
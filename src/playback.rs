@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// Cast playback ======================================
+// An asciinema recording (.cast, format 2 or 3) played back in a pane, through the
+// same grid and renderer as a live shell, with pause, seeking and speed controls.
+//
+// A recording is a JSON header line, then one event per line:
+//   {"version": 2, "width": 80, "height": 24, "idle_time_limit": 2.0}
+//   [0.248, "o", "$ "]           # output, at seconds since the start
+//   [1.001, "r", "100x30"]       # the terminal was resized
+// Format 3 puts the size under "term" and times events since the one before.
+
+pub const SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 1.5, 2.0, 4.0];
+const SEEK_STEP: f64 = 5.0;  // Seconds, for the arrow keys
+const MAX_SIZE: usize = 1000;  // Columns or rows; anything bigger is a broken or hostile file
+
+pub enum CastEvent {
+    Output(String),
+    Resize(usize, usize),
+}
+
+type TimedEvent = (f64, CastEvent);  // At seconds since the start
+
+pub struct Playback {
+    path: PathBuf,
+    pub size: (usize, usize),  // Columns and rows it was recorded at
+    events: Vec<TimedEvent>,  // In order
+    next_event: usize,
+    position: f64,  // Seconds into the recording
+    played_at: Option<(Instant, f64)>,  // Since when, and from where, while playing
+    speed: f32,
+    rewound: bool,  // Seeked backward; the screen has to be replayed from the start
+}
+
+impl Playback {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+        let (size, events) = parse_cast(&contents).map_err(|e| format!("{} isn't an asciinema recording: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size,
+            events,
+            next_event: 0,
+            position: 0.0,
+            played_at: Some((Instant::now(), 0.0)),
+            speed: 1.0,
+            rewound: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |(time, _)| *time)
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.played_at.is_some()
+    }
+
+    // Playing from the start again once it's over
+    pub fn toggle(&mut self) {
+        if self.is_playing() {
+            self.played_at = None;
+        } else {
+            if self.position >= self.duration() {
+                self.seek(0.0);
+            }
+            self.played_at = Some((Instant::now(), self.position));
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+        if self.is_playing() {
+            self.played_at = Some((Instant::now(), self.position));
+        }
+    }
+
+    pub fn seek(&mut self, position: f64) {
+        let position = position.clamp(0.0, self.duration());
+        if position < self.position {
+            self.rewound = true;
+            self.next_event = 0;
+        }
+        self.position = position;
+        if self.is_playing() {
+            self.played_at = Some((Instant::now(), position));
+        }
+    }
+
+    // Back or forward by a few seconds
+    pub fn step(&mut self, forward: bool) {
+        self.seek(self.position + if forward { SEEK_STEP } else { -SEEK_STEP });
+    }
+
+    // Move along to the current time. True if the screen has to be cleared before
+    // the events, which are the ones due since the last call.
+    pub fn advance(&mut self) -> (bool, &[TimedEvent]) {
+        if let Some((since, from)) = self.played_at {
+            self.position = (from + since.elapsed().as_secs_f64() * self.speed as f64).min(self.duration());
+            if self.position >= self.duration() {
+                self.played_at = None;
+            }
+        }
+        let start = self.next_event;
+        while self.events.get(self.next_event).is_some_and(|(time, _)| *time <= self.position) {
+            self.next_event += 1;
+        }
+        (std::mem::take(&mut self.rewound), &self.events[start..self.next_event])
+    }
+}
+
+// Seconds as "1:05"
+pub fn format_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn parse_cast(contents: &str) -> Result<((usize, usize), Vec<TimedEvent>), String> {
+    let mut lines = contents.lines();
+    let header: serde_json::Value = lines.next()
+        .and_then(|line| serde_json::from_str(line).ok())
+        .ok_or("no header line")?;
+    let version = header["version"].as_u64().ok_or("no version in the header")?;
+    let (width, height) = match version {
+        2 => (&header["width"], &header["height"]),
+        3 => (&header["term"]["cols"], &header["term"]["rows"]),
+        _ => return Err(format!("version {} isn't supported", version)),
+    };
+    let size = (width.as_u64().unwrap_or(80) as usize, height.as_u64().unwrap_or(24) as usize);
+    if !size_fits(size) {
+        return Err(format!("a {}x{} terminal is too big", size.0, size.1));
+    }
+    // Long pauses, like the recording sitting at a prompt, are cut down to this
+    let idle_limit = header["idle_time_limit"].as_f64();
+
+    let mut events = Vec::new();
+    let (mut recorded, mut time) = (0.0, 0.0);
+    for (number, line) in lines.enumerate() {
+        // Format 3 allows comments
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let event: (f64, String, String) = serde_json::from_str(line)
+            .map_err(|e| format!("line {}: {}", number + 2, e))?;
+        let (at, code, data) = event;
+        let gap = if version == 3 { at } else { at - recorded };
+        recorded = at;
+        time += idle_limit.map_or(gap, |limit| gap.min(limit)).max(0.0);
+        match code.as_str() {
+            "o" => events.push((time, CastEvent::Output(data))),
+            "r" => {
+                if let Some((cols, rows)) = data.split_once('x')
+                    && let (Ok(cols), Ok(rows)) = (cols.parse(), rows.parse())
+                    && size_fits((cols, rows))
+                {
+                    events.push((time, CastEvent::Resize(cols, rows)));
+                }
+            }
+            // Input, markers and exit codes don't show
+            _ => {}
+        }
+    }
+    Ok((size, events))
+}
+
+fn size_fits((cols, rows): (usize, usize)) -> bool {
+    (1..=MAX_SIZE).contains(&cols) && (1..=MAX_SIZE).contains(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_recordings_are_rejected() {
+        assert!(parse_cast("{\"version\": 2, \"width\": 100000, \"height\": 100000}\n").is_err());
+        assert!(parse_cast("{\"version\": 3, \"term\": {\"cols\": 0, \"rows\": 24}}\n").is_err());
+
+        // Bad resizes are dropped, the rest of the recording plays
+        let (size, events) = parse_cast(concat!(
+            "{\"version\": 2, \"width\": 80, \"height\": 24}\n",
+            "[0.1, \"r\", \"100000x100000\"]\n",
+            "[0.2, \"r\", \"100x30\"]\n",
+            "[0.3, \"o\", \"$ \"]\n",
+        )).unwrap();
+        assert_eq!(size, (80, 24));
+        assert!(matches!(events.as_slice(), [(_, CastEvent::Resize(100, 30)), (_, CastEvent::Output(_))]));
+    }
+}
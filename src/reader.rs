@@ -0,0 +1,59 @@
+use eframe::egui;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::sync::mpsc::{self, Receiver};
+
+// PTY reader =========================================
+// Reads a pane's PTY on its own thread, so output arrives as fast as the program
+// writes it rather than one read per frame. Chunks go to the UI over a channel.
+
+// A burst of output (a build log, `cat` of a big file) is read until the PTY has
+// nothing more to give, up to this much, and sent as one chunk with one repaint
+const MAX_BATCH: usize = 64 * 1024;
+
+pub struct PtyReader {
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl PtyReader {
+    // `stream` is the PTY master (a dup of it). The thread stops at EOF, on a read
+    // error (EIO once the shell is gone), or when the PtyReader is dropped.
+    pub fn spawn(mut stream: File, ctx: egui::Context) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            loop {
+                let mut batch = Vec::new();
+                // Block for the first read, then keep going while more is ready
+                while batch.is_empty() || (batch.len() < MAX_BATCH && has_pending_input(&stream)) {
+                    match stream.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(n) => batch.extend_from_slice(&buffer[..n]),
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(_) => break,
+                    }
+                }
+                if batch.is_empty() || sender.send(batch).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+            // The shell is likely gone; a frame reaps it
+            ctx.request_repaint();
+        });
+        Self { receiver }
+    }
+
+    // Everything read since the last call
+    pub fn drain(&self) -> Vec<Vec<u8>> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+// True if a read wouldn't block
+fn has_pending_input(stream: &File) -> bool {
+    let mut pollfd = libc::pollfd { fd: stream.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    // SAFETY: poll only touches the one pollfd we pass
+    unsafe { libc::poll(&mut pollfd, 1, 0) > 0 && pollfd.revents & libc::POLLIN != 0 }
+}
@@ -0,0 +1,118 @@
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// Dark mode schedule =================================
+// Either fixed times:
+//
+//   [dark_mode_schedule]
+//   dark_from = "19:30"
+//   light_from = "07:00"
+//
+// or follow the sun at a location:
+//
+//   [dark_mode_schedule]
+//   latitude = 52.52
+//   longitude = 13.40
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DarkModeSchedule {
+    pub dark_from: Option<String>,
+    pub light_from: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl DarkModeSchedule {
+    // Whether the schedule says it should be dark right now, or None if it isn't usable
+    pub fn is_dark_now(&self) -> Option<bool> {
+        let now = LocalTime::now();
+
+        if let (Some(dark_from), Some(light_from)) = (&self.dark_from, &self.light_from) {
+            let dark_from = parse_clock(dark_from)?;
+            let light_from = parse_clock(light_from)?;
+            return Some(is_between(now.minutes, dark_from, light_from));
+        }
+
+        let (latitude, longitude) = (self.latitude?, self.longitude?);
+        Some(match sun_times(now.day_of_year, latitude, longitude) {
+            SunTimes::Rises { sunrise, sunset } => {
+                let offset = now.utc_offset_minutes;
+                let sunrise = (sunrise + offset).rem_euclid(1440.0);
+                let sunset = (sunset + offset).rem_euclid(1440.0);
+                is_between(now.minutes, sunset, sunrise)
+            }
+            SunTimes::PolarNight => true,
+            SunTimes::PolarDay => false,
+        })
+    }
+}
+
+// True if `minutes` falls in [from, until), wrapping past midnight
+fn is_between(minutes: f64, from: f64, until: f64) -> bool {
+    if from <= until {
+        minutes >= from && minutes < until
+    } else {
+        minutes >= from || minutes < until
+    }
+}
+
+// "HH:MM" -> minutes since midnight
+fn parse_clock(clock: &str) -> Option<f64> {
+    let (hours, minutes) = clock.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then(|| (hours * 60 + minutes) as f64)
+}
+
+struct LocalTime {
+    minutes: f64,  // Since local midnight
+    day_of_year: f64,
+    utc_offset_minutes: f64,
+}
+
+impl LocalTime {
+    fn now() -> Self {
+        // SAFETY: localtime_r only writes into the tm struct we hand it
+        let tm = unsafe {
+            let now = libc::time(std::ptr::null_mut());
+            let mut tm: libc::tm = std::mem::zeroed();
+            libc::localtime_r(&now, &mut tm);
+            tm
+        };
+        Self {
+            minutes: (tm.tm_hour * 60 + tm.tm_min) as f64,
+            day_of_year: (tm.tm_yday + 1) as f64,
+            utc_offset_minutes: tm.tm_gmtoff as f64 / 60.0,
+        }
+    }
+}
+
+enum SunTimes {
+    Rises { sunrise: f64, sunset: f64 },  // Minutes since UTC midnight
+    PolarNight,
+    PolarDay,
+}
+
+// NOAA's approximate solar equations
+fn sun_times(day_of_year: f64, latitude: f64, longitude: f64) -> SunTimes {
+    let gamma = 2.0 * PI / 365.0 * (day_of_year - 1.0);
+    let eqtime = 229.18 * (0.000075 + 0.001868 * gamma.cos() - 0.032077 * gamma.sin()
+        - 0.014615 * (2.0 * gamma).cos() - 0.040849 * (2.0 * gamma).sin());
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos() + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos() + 0.00148 * (3.0 * gamma).sin();
+
+    let lat = latitude.to_radians();
+    let cos_hour_angle = 90.833_f64.to_radians().cos() / (lat.cos() * decl.cos()) - lat.tan() * decl.tan();
+    if cos_hour_angle > 1.0 {
+        return SunTimes::PolarNight;
+    }
+    if cos_hour_angle < -1.0 {
+        return SunTimes::PolarDay;
+    }
+
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    SunTimes::Rises {
+        sunrise: 720.0 - 4.0 * (longitude + hour_angle) - eqtime,
+        sunset: 720.0 - 4.0 * (longitude - hour_angle) - eqtime,
+    }
+}
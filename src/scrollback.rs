@@ -0,0 +1,366 @@
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::fs::FileExt;
+use std::rc::Rc;
+
+use crate::grid::Cell;
+use crate::parser::{CellStyle, TermColor};
+
+// Scrollback =========================================
+// Lines that scrolled off the top of the main screen, oldest first. The newest are
+// kept as they are; older ones are packed into compressed chunks, unpacked again
+// when they're scrolled to or searched, so a long scrollback takes a fraction of
+// the memory. Dropping the oldest line once the limit is reached doesn't shift the rest.
+//
+// With no limit (scrollback_lines = "unlimited") the oldest lines spill to a
+// temporary file past what's kept in memory, read back when they're looked at. The
+// view keeps just a width and an error mark per line, and styles the lines in view.
+
+// Unlimited scrollback keeps this many of the newest lines in memory, mostly compressed
+const MEMORY_LINES: usize = 100_000;
+const HOT_LINES: usize = 1000;  // Newest lines left uncompressed
+const CHUNK_LINES: usize = 256;  // Lines compressed together
+
+// How much output a pane keeps, set in lines or "unlimited"
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "LimitSetting")]
+pub enum ScrollbackLimit {
+    Lines(usize),
+    Unlimited,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LimitSetting {
+    Lines(usize),
+    Word(String),
+}
+
+impl TryFrom<LimitSetting> for ScrollbackLimit {
+    type Error = String;
+
+    fn try_from(setting: LimitSetting) -> Result<Self, String> {
+        match setting {
+            LimitSetting::Lines(lines) => Ok(ScrollbackLimit::Lines(lines)),
+            LimitSetting::Word(word) if word == "unlimited" => Ok(ScrollbackLimit::Unlimited),
+            LimitSetting::Word(word) => Err(format!("\"{}\" isn't a number of lines or \"unlimited\"", word)),
+        }
+    }
+}
+
+type ChunkLines = Rc<Vec<Vec<Cell>>>;
+
+pub struct Scrollback {
+    spill: Option<Spill>,  // The oldest, on disk
+    chunks: VecDeque<Vec<u8>>,  // Then CHUNK_LINES lines each, compressed
+    skipped: usize,  // Lines of the first chunk that have been dropped
+    lines: VecDeque<Vec<Cell>>,  // The newest, as they are
+    unpacked: RefCell<Option<(usize, ChunkLines)>>,  // The chunk last read, by position
+    limit: ScrollbackLimit,
+    dropped: usize,  // Lines discarded since the last take_dropped()
+}
+
+impl Scrollback {
+    pub fn new(limit: ScrollbackLimit) -> Self {
+        Self {
+            spill: None,
+            chunks: VecDeque::new(),
+            skipped: 0,
+            lines: VecDeque::new(),
+            unpacked: RefCell::new(None),
+            limit,
+            dropped: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spilled() + self.packed() + self.lines.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Cow<'_, [Cell]>> {
+        let Some(index) = index.checked_sub(self.spilled()) else {
+            return self.spill.as_ref()?.read(index).map(Cow::Owned);
+        };
+        match index.checked_sub(self.packed()) {
+            Some(index) => self.lines.get(index).map(|line| Cow::Borrowed(line.as_slice())),
+            None => {
+                let index = index + self.skipped;
+                self.chunk(index / CHUNK_LINES)?.get(index % CHUNK_LINES).cloned().map(Cow::Owned)
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cow<'_, [Cell]>> {
+        self.iter_from(0)
+    }
+
+    // Lines from `start` on
+    pub fn iter_from(&self, start: usize) -> impl Iterator<Item = Cow<'_, [Cell]>> {
+        (start..self.len()).map_while(|index| self.get(index))
+    }
+
+    pub fn push(&mut self, mut line: Vec<Cell>) {
+        // Trailing blanks are never drawn, so don't keep them around
+        let used = line.iter().rposition(|cell| *cell != Cell::default()).map_or(0, |i| i + 1);
+        line.truncate(used);
+        line.shrink_to_fit();
+
+        self.lines.push_back(line);
+        if self.lines.len() >= HOT_LINES + CHUNK_LINES {
+            let chunk: Vec<Vec<Cell>> = self.lines.drain(..CHUNK_LINES).collect();
+            self.chunks.push_back(pack(&chunk));
+        }
+        self.trim();
+    }
+
+    // Everything goes, counted as dropped
+    pub fn clear(&mut self) {
+        self.dropped += self.len();
+        self.lines.clear();
+        self.chunks.clear();
+        self.skipped = 0;
+        self.unpacked.replace(None);
+        if let Some(spill) = &mut self.spill {
+            spill.clear();
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: ScrollbackLimit) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    // Lines dropped that take_dropped() hasn't handed out yet
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    // How many lines fell off the top since the last call, so line numbers kept
+    // elsewhere can be shifted to match
+    pub fn take_dropped(&mut self) -> usize {
+        std::mem::take(&mut self.dropped)
+    }
+
+    fn spilled(&self) -> usize {
+        self.spill.as_ref().map_or(0, Spill::len)
+    }
+
+    fn packed(&self) -> usize {
+        self.chunks.len() * CHUNK_LINES - self.skipped
+    }
+
+    // The lines of a chunk, unpacking it unless it was the last one read
+    fn chunk(&self, position: usize) -> Option<ChunkLines> {
+        if let Some((unpacked_at, lines)) = &*self.unpacked.borrow()
+            && *unpacked_at == position
+        {
+            return Some(lines.clone());
+        }
+        let lines = Rc::new(unpack(self.chunks.get(position)?));
+        self.unpacked.replace(Some((position, lines.clone())));
+        Some(lines)
+    }
+
+    fn pop_chunk(&mut self) -> Option<Vec<u8>> {
+        self.skipped = 0;
+        self.unpacked.replace(None);
+        self.chunks.pop_front()
+    }
+
+    fn trim(&mut self) {
+        match self.limit {
+            ScrollbackLimit::Lines(limit) => {
+                // Back from disk as many as fit, since a limited scrollback is all in memory
+                if let Some(spill) = self.spill.take() {
+                    let wanted = limit.saturating_sub(self.packed() + self.lines.len()).min(spill.len());
+                    self.dropped += spill.len() - wanted;
+                    // Packed into whole chunks, padded at the front with lines counted as skipped
+                    let padding = (CHUNK_LINES - wanted % CHUNK_LINES) % CHUNK_LINES;
+                    let restored: Vec<Vec<Cell>> = std::iter::repeat_n(Vec::new(), padding)
+                        .chain((spill.len() - wanted..spill.len()).map(|index| spill.read(index).unwrap_or_default()))
+                        .collect();
+                    for chunk in restored.chunks(CHUNK_LINES).rev() {
+                        self.chunks.push_front(pack(chunk));
+                    }
+                    self.skipped = padding;
+                    self.unpacked.replace(None);
+                }
+                let mut excess = self.len().saturating_sub(limit);
+                self.dropped += excess;
+                while excess > 0 && !self.chunks.is_empty() {
+                    let dropping = excess.min(CHUNK_LINES - self.skipped);
+                    self.skipped += dropping;
+                    excess -= dropping;
+                    if self.skipped == CHUNK_LINES {
+                        self.pop_chunk();
+                    }
+                }
+                self.lines.drain(..excess.min(self.lines.len()));
+            }
+            ScrollbackLimit::Unlimited => {
+                while self.packed() + self.lines.len() > MEMORY_LINES && !self.chunks.is_empty() {
+                    if self.spill.is_none() {
+                        self.spill = Spill::create();
+                    }
+                    let chunk = self.chunk(0).unwrap_or_default();
+                    let skipped = self.skipped;
+                    let written = self.spill.as_mut().is_some_and(|spill| chunk[skipped..].iter().all(|line| spill.push(line)));
+                    if !written {
+                        // Nowhere to put it; keep what fits in memory instead
+                        self.limit = ScrollbackLimit::Lines(MEMORY_LINES);
+                        self.trim();
+                        return;
+                    }
+                    self.pop_chunk();
+                }
+            }
+        }
+    }
+}
+
+// The lines of a chunk one after another, each as its length and then its cells,
+// deflated
+fn pack(lines: &[Vec<Cell>]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for line in lines {
+        bytes.extend_from_slice(&(line.len() as u32).to_le_bytes());
+        for cell in line {
+            encode_cell(cell, &mut bytes);
+        }
+    }
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    // Writing to a Vec can't fail
+    let _ = encoder.write_all(&bytes);
+    encoder.finish().unwrap_or_default()
+}
+
+fn unpack(chunk: &[u8]) -> Vec<Vec<Cell>> {
+    let mut bytes = Vec::new();
+    if let Err(e) = DeflateDecoder::new(chunk).read_to_end(&mut bytes) {
+        eprintln!("Warning: Can't unpack scrollback: {}", e);
+    }
+    let mut lines = Vec::with_capacity(CHUNK_LINES);
+    let mut rest = bytes.as_slice();
+    while let Some((length, after)) = rest.split_first_chunk::<4>() {
+        let length = (u32::from_le_bytes(*length) as usize * CELL_BYTES).min(after.len());
+        lines.push(after[..length].chunks_exact(CELL_BYTES).map(decode_cell).collect());
+        rest = &after[length..];
+    }
+    lines
+}
+
+// Spill file =========================================
+// Lines written one after another in a temporary file, a fixed number of bytes per
+// cell, found by where each one starts. The file is deleted as soon as it's opened,
+// so it's gone with the pane however Sigmaterm exits.
+
+const CELL_BYTES: usize = 26;
+
+struct Spill {
+    file: File,
+    starts: Vec<u64>,  // Offset of each line, then the end of the file
+}
+
+impl Spill {
+    fn create() -> Option<Self> {
+        static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let number = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("sigmaterm-scrollback-{}-{}", std::process::id(), number));
+        let file = std::fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path);
+        match file {
+            Ok(file) => {
+                let _ = std::fs::remove_file(&path);
+                Some(Self { file, starts: vec![0] })
+            }
+            Err(e) => {
+                eprintln!("Warning: Can't keep scrollback in {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    // False if it couldn't be written
+    fn push(&mut self, line: &[Cell]) -> bool {
+        let mut bytes = Vec::with_capacity(line.len() * CELL_BYTES);
+        for cell in line {
+            encode_cell(cell, &mut bytes);
+        }
+        let end = self.starts[self.starts.len() - 1];
+        if let Err(e) = self.file.write_all_at(&bytes, end) {
+            eprintln!("Warning: Can't write scrollback to disk: {}", e);
+            return false;
+        }
+        self.starts.push(end + bytes.len() as u64);
+        true
+    }
+
+    fn read(&self, index: usize) -> Option<Vec<Cell>> {
+        let (start, end) = (*self.starts.get(index)?, *self.starts.get(index + 1)?);
+        let mut bytes = vec![0; (end - start) as usize];
+        if let Err(e) = self.file.read_exact_at(&mut bytes, start) {
+            eprintln!("Warning: Can't read scrollback from disk: {}", e);
+            return None;
+        }
+        Some(bytes.chunks_exact(CELL_BYTES).map(decode_cell).collect())
+    }
+
+    fn clear(&mut self) {
+        let _ = self.file.set_len(0);
+        self.starts = vec![0];
+    }
+}
+
+// ch, the combining marks, width, colors as a kind then r g b (or the index), and
+// the attributes as bits
+fn encode_cell(cell: &Cell, out: &mut Vec<u8>) {
+    for ch in [cell.ch].iter().chain(&cell.combining) {
+        out.extend_from_slice(&(*ch as u32).to_le_bytes());
+    }
+    out.push(cell.width);
+    for color in [cell.style.fg, cell.style.bg] {
+        out.extend_from_slice(&match color {
+            TermColor::Default => [0, 0, 0, 0],
+            TermColor::Indexed(n) => [1, n, 0, 0],
+            TermColor::Rgb(r, g, b) => [2, r, g, b],
+        });
+    }
+    let style = &cell.style;
+    let flags = [style.bold, style.dim, style.italic, style.underline, style.strikethrough, style.inverse];
+    out.push(flags.iter().enumerate().fold(0, |bits, (i, &on)| bits | (on as u8) << i));
+}
+
+fn decode_cell(bytes: &[u8]) -> Cell {
+    let ch = |i: usize| char::from_u32(u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])).unwrap_or('\0');
+    let color = |i: usize| match bytes[i] {
+        1 => TermColor::Indexed(bytes[i + 1]),
+        2 => TermColor::Rgb(bytes[i + 1], bytes[i + 2], bytes[i + 3]),
+        _ => TermColor::Default,
+    };
+    let flag = |bit: u8| bytes[25] & (1 << bit) != 0;
+    Cell {
+        ch: ch(0),
+        combining: [ch(4), ch(8), ch(12)],
+        width: bytes[16],
+        style: CellStyle {
+            fg: color(17),
+            bg: color(21),
+            bold: flag(0),
+            dim: flag(1),
+            italic: flag(2),
+            underline: flag(3),
+            strikethrough: flag(4),
+            inverse: flag(5),
+        },
+    }
+}
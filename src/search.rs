@@ -0,0 +1,149 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::grid::Cell;
+
+// Search =============================================
+// Search through a pane's output. Matches are found per line, so they never span
+// a line break, and are kept as cell columns for highlighting.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,  // Exclusive
+}
+
+#[derive(Default)]
+pub struct Search {
+    pub open: bool,
+    pub query: String,
+    pub case_sensitive: bool,
+    pub regex: bool,
+    pub matches: Vec<SearchMatch>,
+    pub current: Option<usize>,  // Index into matches
+    pub error: Option<String>,   // The query isn't a valid regex
+    pub focused: bool,           // The query field has keyboard focus
+    pub take_focus: bool,        // Focus the query field on the next frame
+    searched: Option<(String, bool, bool)>,  // Query and modes the matches are for
+    stale: bool,                 // Output changed since the last search
+}
+
+impl Search {
+    pub fn open(&mut self) {
+        self.open = true;
+        self.take_focus = true;
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.focused = false;
+        self.matches.clear();
+        self.current = None;
+        self.searched = None;
+    }
+
+    // New output arrived; search again on the next update
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    pub fn current_match(&self) -> Option<SearchMatch> {
+        self.matches.get(self.current?).copied()
+    }
+
+    // Re-run the search if the query, the modes or the output changed. Returns true if
+    // the current match moved to a different place.
+    pub fn update(&mut self, lines: impl Iterator<Item = impl AsRef<[Cell]>>) -> bool {
+        let key = (self.query.clone(), self.case_sensitive, self.regex);
+        if !self.open || (!self.stale && self.searched.as_ref() == Some(&key)) {
+            return false;
+        }
+        let query_changed = self.searched.as_ref() != Some(&key);
+        self.searched = Some(key);
+        self.stale = false;
+
+        let previous = self.current_match();
+        self.matches.clear();
+        self.error = None;
+        if self.query.is_empty() {
+            self.current = None;
+            return false;
+        }
+        let pattern = match self.compile() {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                self.current = None;
+                return false;
+            }
+        };
+
+        for (line, cells) in lines.enumerate() {
+            let cells = cells.as_ref();
+            // The column each byte of the text came from
+            let mut text = String::new();
+            let mut columns = Vec::new();
+            for (col, cell) in cells.iter().enumerate() {
+                cell.push_text(&mut text);
+                columns.resize(text.len(), col);
+            }
+            for found in pattern.find_iter(&text) {
+                if found.is_empty() {
+                    continue;
+                }
+                let start = columns[found.start()];
+                let last = columns[found.end() - 1];
+                let end = last + (cells[last].width as usize).max(1);
+                self.matches.push(SearchMatch { line, start, end });
+            }
+        }
+
+        // Stay on the same match while output streams in; a new query starts from the
+        // bottom, the most recent output
+        self.current = match previous {
+            Some(previous) if !query_changed => self.matches.iter().position(|m| *m == previous),
+            _ => None,
+        }
+        .or(self.matches.len().checked_sub(1));
+        self.current_match() != previous
+    }
+
+    // Towards older output, wrapping around at the top
+    pub fn previous(&mut self) -> Option<SearchMatch> {
+        let count = self.matches.len();
+        if count == 0 {
+            return None;
+        }
+        self.current = Some(self.current.map_or(count - 1, |i| (i + count - 1) % count));
+        self.current_match()
+    }
+
+    // Towards newer output, wrapping around at the bottom
+    pub fn next(&mut self) -> Option<SearchMatch> {
+        let count = self.matches.len();
+        if count == 0 {
+            return None;
+        }
+        self.current = Some(self.current.map_or(0, |i| (i + 1) % count));
+        self.current_match()
+    }
+
+    // Lines fell off the top of the scrollback
+    pub fn shift(&mut self, removed_lines: usize) {
+        let previous = self.current_match();
+        self.matches.retain(|m| m.line >= removed_lines);
+        for m in &mut self.matches {
+            m.line -= removed_lines;
+        }
+        self.current = previous.and_then(|previous| {
+            self.matches.iter().position(|m| m.line + removed_lines == previous.line && m.start == previous.start)
+        });
+    }
+
+    fn compile(&self) -> Result<Regex, regex::Error> {
+        let pattern = if self.regex { self.query.clone() } else { regex::escape(&self.query) };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+    }
+}
@@ -0,0 +1,116 @@
+use std::borrow::Cow;
+
+use crate::grid::{cells_text, Cell};
+
+// Selection ==========================================
+// A stretch of output between two (line, column) points, in the order the mouse
+// went; lines count from the top of the scrollback (or the screen in raw mode).
+// A block selection (Alt+drag) is the rectangle between them instead, for taking a
+// column out of a table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Selection {
+    pub anchor: (usize, usize),  // Where the drag started
+    pub head: (usize, usize),    // Where the pointer is now
+    pub block: bool,
+}
+
+impl Selection {
+    pub fn new(at: (usize, usize)) -> Self {
+        Self { anchor: at, head: at, block: false }
+    }
+
+    pub fn block(at: (usize, usize)) -> Self {
+        Self { block: true, ..Self::new(at) }
+    }
+
+    // The word around a column of `line`, for a double click: a run of word characters,
+    // or else of the character clicked on
+    pub fn word(line: usize, cells: &[Cell], col: usize, is_word_char: impl Fn(char) -> bool) -> Self {
+        // The right half of a wide character is that character
+        let ch = |col: usize| cells.get(col).map(|cell| if cell.width == 0 && col > 0 { cells[col - 1].ch } else { cell.ch });
+        let Some(clicked) = ch(col) else {
+            return Self::new((line, col));
+        };
+        let same = |c: char| if is_word_char(clicked) { is_word_char(c) } else { c == clicked };
+        let mut start = col;
+        while start > 0 && ch(start - 1).is_some_and(same) {
+            start -= 1;
+        }
+        let mut end = col + 1;
+        while ch(end).is_some_and(same) {
+            end += 1;
+        }
+        Self { anchor: (line, start), head: (line, end), block: false }
+    }
+
+    // All of `line`, for a triple click
+    pub fn line(line: usize) -> Self {
+        Self { anchor: (line, 0), head: (line, usize::MAX), block: false }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        if self.block {
+            self.anchor.1 == self.head.1
+        } else {
+            self.anchor == self.head
+        }
+    }
+
+    // Start and end in reading order; the end column is exclusive
+    pub fn ordered(&self) -> ((usize, usize), (usize, usize)) {
+        if self.anchor <= self.head {
+            (self.anchor, self.head)
+        } else {
+            (self.head, self.anchor)
+        }
+    }
+
+    // Selected columns of `line`, or None if the line isn't part of the selection.
+    // Lines in the middle are selected to the end (usize::MAX), unless it's a block.
+    pub fn columns(&self, line: usize) -> Option<(usize, usize)> {
+        let (start, end) = self.ordered();
+        if line < start.0 || line > end.0 {
+            return None;
+        }
+        if self.block {
+            return Some((self.anchor.1.min(self.head.1), self.anchor.1.max(self.head.1)));
+        }
+        let from = if line == start.0 { start.1 } else { 0 };
+        let to = if line == end.0 { end.1 } else { usize::MAX };
+        Some((from, to))
+    }
+
+    // Shift by lines dropped from the top; None if the selection went with them
+    pub fn shifted(self, removed_lines: usize) -> Option<Self> {
+        if self.ordered().0.0 < removed_lines {
+            return None;
+        }
+        Some(Self {
+            anchor: (self.anchor.0 - removed_lines, self.anchor.1),
+            head: (self.head.0 - removed_lines, self.head.1),
+            ..self
+        })
+    }
+
+    // The selected cells of each line, for copying with colors
+    pub fn cells<'a>(&self, line: impl Fn(usize) -> Option<Cow<'a, [Cell]>>) -> Vec<Cow<'a, [Cell]>> {
+        let (start, end) = self.ordered();
+        (start.0..=end.0).filter_map(|index| {
+            let (from, to) = self.columns(index)?;
+            let cells = line(index).unwrap_or_default();
+            // Starting on the right half of a wide character takes all of it
+            let from = if cells.get(from).is_some_and(|cell| cell.width == 0) { from.saturating_sub(1) } else { from };
+            let range = from..to.min(cells.len());
+            Some(match cells {
+                Cow::Borrowed(cells) => Cow::Borrowed(cells.get(range).unwrap_or_default()),
+                Cow::Owned(cells) => Cow::Owned(cells.get(range).unwrap_or_default().to_vec()),
+            })
+        }).collect()
+    }
+
+    // The selected text, one line per line with trailing blanks trimmed
+    pub fn text<'a>(&self, line: impl Fn(usize) -> Option<Cow<'a, [Cell]>>) -> String {
+        let lines: Vec<String> = self.cells(line).into_iter().map(|cells| cells_text(&cells).trim_end().to_string()).collect();
+        lines.join("\n")
+    }
+}
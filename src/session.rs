@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::background::BackgroundImage;
+use crate::config::data_dir;
+use crate::split::SplitNode;
+
+// Session ============================================
+// The tabs and panes open at exit (layout, titles, colors, directories), saved to
+// ~/.local/share/sigmaterm/session.toml and reopened on the next launch
+
+const SESSION_FORMAT: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub format: u32,
+    pub active_tab: usize,
+    pub tabs: Vec<TabSession>,
+    #[serde(default)]
+    pub always_on_top: bool,
+    #[serde(default)]
+    pub fullscreen: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TabSession {
+    pub name: String,
+    pub terminals: Vec<TerminalSession>,
+    pub layout: Option<SplitNode>,
+    pub active_terminal: Option<usize>,
+    pub maximized: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TerminalSession {
+    pub title: Option<String>,  // Only a name the user gave; programs set their own again
+    pub icon: Option<String>,
+    pub color_scheme: Option<String>,
+    #[serde(default)]
+    pub palette: BTreeMap<String, String>,  // ANSI colors edited in the pane
+    pub profile: Option<String>,
+    pub hue: f32,
+    pub saturation: Option<f32>,  // Only if changed from the default
+    pub lightness: Option<f32>,
+    pub opacity: Option<f32>,  // Only if the pane is translucent
+    pub background_image: Option<BackgroundImage>,
+    pub working_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub recording: Option<PathBuf>,  // A .cast file the pane was playing back
+}
+
+impl Session {
+    pub fn new(active_tab: usize, tabs: Vec<TabSession>) -> Self {
+        Self { format: SESSION_FORMAT, active_tab, tabs, always_on_top: false, fullscreen: false }
+    }
+
+    // The last saved session, if there is one this version can read
+    pub fn load() -> Option<Self> {
+        let path = session_path()?;
+        let contents = std::fs::read_to_string(&path).ok()?;
+        match toml::from_str::<Session>(&contents) {
+            Ok(session) if session.format == SESSION_FORMAT && !session.tabs.is_empty() => Some(session),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("Warning: Failed to parse {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = session_path() else {
+            return;
+        };
+        let result = toml::to_string(self)
+            .map_err(std::io::Error::other)
+            .and_then(|contents| {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&path, contents)
+            });
+        if let Err(e) = result {
+            eprintln!("Warning: Failed to save the session to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn session_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("session.toml"))
+}
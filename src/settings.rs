@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::config_dir;
+
+// Settings archive ===================================
+// Everything under ~/.config/sigmaterm (config, profiles, themes, layouts, ...) bundled
+// into one TOML file keyed by relative path, so a setup can be copied to another machine.
+
+const ARCHIVE_FORMAT: u32 = 1;
+
+// Never export anything that looks like a credential
+const SECRET_MARKERS: [&str; 5] = ["secret", "password", "token", ".key", ".pem"];
+
+#[derive(Serialize, Deserialize)]
+struct SettingsArchive {
+    format: u32,
+    files: BTreeMap<String, String>,
+}
+
+pub fn default_archive_path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    PathBuf::from(home).join("sigmaterm-settings.toml")
+}
+
+// Write all settings files to `archive_path`, returning how many were included
+pub fn export_settings(archive_path: &Path) -> io::Result<usize> {
+    let dir = config_dir().ok_or_else(|| io::Error::other("no config directory"))?;
+
+    let mut files = BTreeMap::new();
+    collect_files(&dir, &dir, &mut files)?;
+
+    let count = files.len();
+    let archive = SettingsArchive { format: ARCHIVE_FORMAT, files };
+    let contents = toml::to_string(&archive).map_err(io::Error::other)?;
+    std::fs::write(archive_path, contents)?;
+    Ok(count)
+}
+
+// Unpack an archive into the config directory, returning how many files were written
+pub fn import_settings(archive_path: &Path) -> io::Result<usize> {
+    let dir = config_dir().ok_or_else(|| io::Error::other("no config directory"))?;
+
+    let contents = std::fs::read_to_string(archive_path)?;
+    let archive: SettingsArchive = toml::from_str(&contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if archive.format > ARCHIVE_FORMAT {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "archive is from a newer version of Sigmaterm"));
+    }
+
+    let mut written = 0;
+    for (relative, contents) in &archive.files {
+        // Only plain relative paths, so an archive can't write outside the config directory
+        let relative = Path::new(relative);
+        if !relative.components().all(|c| matches!(c, Component::Normal(_))) || is_secret(relative) {
+            continue;
+        }
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Keep the file being replaced next to it, as name.bak
+        if path.is_file() {
+            if std::fs::read_to_string(&path).is_ok_and(|current| current == *contents) {
+                continue;
+            }
+            std::fs::copy(&path, backup_path(&path))?;
+        }
+        std::fs::write(path, contents)?;
+        written += 1;
+    }
+
+    Ok(written)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut BTreeMap<String, String>) -> io::Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root)
+            && !is_secret(relative)
+            // Backups left by an import
+            && path.extension().is_none_or(|ext| ext != "bak")
+            && let Ok(contents) = std::fs::read_to_string(&path)
+        {
+            files.insert(relative.to_string_lossy().into_owned(), contents);
+        }
+    }
+    Ok(())
+}
+
+fn is_secret(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    SECRET_MARKERS.iter().any(|marker| name.contains(marker))
+}
@@ -0,0 +1,151 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+// Split tree =========================================
+// How the panes share the window. Each split divides its space between two
+// subtrees, side by side or one above the other; the leaves are terminal ids
+// (indices into the manager's terminals).
+
+// Space between two panes, where the divider can be dragged
+const GAP: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SplitDirection {
+    SideBySide,  // New pane to the right
+    Stacked,     // New pane below
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SplitNode {
+    Pane(usize),
+    Split {
+        direction: SplitDirection,
+        ratio: f32,  // Share of the space the first subtree gets
+        first: Box<SplitNode>,
+        second: Box<SplitNode>,
+    },
+}
+
+impl SplitNode {
+    // Put pane `new_id` next to pane `id`, halving its space. False if there's no such pane.
+    pub fn split(&mut self, id: usize, new_id: usize, direction: SplitDirection) -> bool {
+        match self {
+            SplitNode::Pane(pane) if *pane == id => {
+                *self = SplitNode::Split {
+                    direction,
+                    ratio: 0.5,
+                    first: Box::new(SplitNode::Pane(id)),
+                    second: Box::new(SplitNode::Pane(new_id)),
+                };
+                true
+            }
+            SplitNode::Pane(_) => false,
+            SplitNode::Split { first, second, .. } => first.split(id, new_id, direction) || second.split(id, new_id, direction),
+        }
+    }
+
+    // Add pane `new_id` after everything else, sized so a row (or column) of panes
+    // added this way share the space equally
+    pub fn append(self, new_id: usize, direction: SplitDirection) -> Self {
+        let count = self.run_length(direction) as f32;
+        SplitNode::Split {
+            direction,
+            ratio: count / (count + 1.0),
+            first: Box::new(self),
+            second: Box::new(SplitNode::Pane(new_id)),
+        }
+    }
+
+    // Panes in a chain of appends in `direction`, counting a nested split as one
+    fn run_length(&self, direction: SplitDirection) -> usize {
+        match self {
+            SplitNode::Split { direction: d, first, .. } if *d == direction => first.run_length(direction) + 1,
+            _ => 1,
+        }
+    }
+
+    // Take pane `id` out, giving its space to its sibling. Ids above it move down by
+    // one, as they do in the manager's list. None if it was the last pane.
+    pub fn remove(self, id: usize) -> Option<Self> {
+        match self {
+            SplitNode::Pane(pane) if pane == id => None,
+            SplitNode::Pane(pane) => Some(SplitNode::Pane(if pane > id { pane - 1 } else { pane })),
+            SplitNode::Split { direction, ratio, first, second } => match (first.remove(id), second.remove(id)) {
+                (Some(first), Some(second)) => Some(SplitNode::Split { direction, ratio, first: Box::new(first), second: Box::new(second) }),
+                (Some(only), None) | (None, Some(only)) => Some(only),
+                (None, None) => None,
+            },
+        }
+    }
+
+    // True if every pane 0..count is in the tree exactly once, as when it was saved
+    pub fn has_panes(&self, count: usize) -> bool {
+        let mut ids: Vec<usize> = self.rects(egui::Rect::ZERO).into_iter().map(|(id, _)| id).collect();
+        ids.sort_unstable();
+        ids == (0..count).collect::<Vec<_>>()
+    }
+
+    // Where each pane goes within `rect`
+    pub fn rects(&self, rect: egui::Rect) -> Vec<(usize, egui::Rect)> {
+        let mut rects = Vec::new();
+        self.collect_rects(rect, &mut rects);
+        rects
+    }
+
+    fn collect_rects(&self, rect: egui::Rect, rects: &mut Vec<(usize, egui::Rect)>) {
+        match self {
+            SplitNode::Pane(id) => rects.push((*id, rect)),
+            SplitNode::Split { direction, ratio, first, second } => {
+                let (first_rect, _, second_rect) = divide(rect, *direction, *ratio);
+                first.collect_rects(first_rect, rects);
+                second.collect_rects(second_rect, rects);
+            }
+        }
+    }
+
+    // Let the gaps between panes be dragged to resize them
+    pub fn drag_dividers(&mut self, ui: &egui::Ui, rect: egui::Rect, id: egui::Id) {
+        let SplitNode::Split { direction, ratio, first, second } = self else {
+            return;
+        };
+        let (first_rect, divider, second_rect) = divide(rect, *direction, *ratio);
+        let response = ui.interact(divider, id, egui::Sense::drag());
+        if response.hovered() || response.dragged() {
+            ui.ctx().set_cursor_icon(match direction {
+                SplitDirection::SideBySide => egui::CursorIcon::ResizeHorizontal,
+                SplitDirection::Stacked => egui::CursorIcon::ResizeVertical,
+            });
+        }
+        if response.dragged() {
+            let delta = response.drag_delta();
+            *ratio = match direction {
+                SplitDirection::SideBySide => *ratio + delta.x / rect.width(),
+                SplitDirection::Stacked => *ratio + delta.y / rect.height(),
+            }.clamp(0.1, 0.9);
+        }
+        first.drag_dividers(ui, first_rect, id.with(0));
+        second.drag_dividers(ui, second_rect, id.with(1));
+    }
+}
+
+// The first subtree's rect, the gap, and the second subtree's rect
+fn divide(rect: egui::Rect, direction: SplitDirection, ratio: f32) -> (egui::Rect, egui::Rect, egui::Rect) {
+    match direction {
+        SplitDirection::SideBySide => {
+            let x = rect.left() + (rect.width() - GAP) * ratio;
+            (
+                egui::Rect::from_x_y_ranges(rect.left()..=x, rect.y_range()),
+                egui::Rect::from_x_y_ranges(x..=x + GAP, rect.y_range()),
+                egui::Rect::from_x_y_ranges(x + GAP..=rect.right(), rect.y_range()),
+            )
+        }
+        SplitDirection::Stacked => {
+            let y = rect.top() + (rect.height() - GAP) * ratio;
+            (
+                egui::Rect::from_x_y_ranges(rect.x_range(), rect.top()..=y),
+                egui::Rect::from_x_y_ranges(rect.x_range(), y..=y + GAP),
+                egui::Rect::from_x_y_ranges(rect.x_range(), y + GAP..=rect.bottom()),
+            )
+        }
+    }
+}
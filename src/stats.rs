@@ -0,0 +1,121 @@
+use eframe::egui;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::utils::format_duration;
+
+// Command stats ======================================
+// Timing of commands run in any pane, shown in a small dashboard. The times come from
+// shell integration (OSC 133); shells without it don't show up here.
+
+const MAX_RECORDS: usize = 1000;
+const BUILD_COMMANDS: [&str; 13] = [
+    "cargo", "make", "cmake", "ninja", "npm", "yarn", "pnpm", "gradle", "mvn", "go", "bazel", "meson", "dotnet",
+];
+
+#[derive(Clone)]
+pub struct CommandRecord {
+    pub command: String,
+    pub pane: String,  // Title of the pane it ran in
+    pub started_at: SystemTime,
+    pub duration: Duration,
+}
+
+impl CommandRecord {
+    fn is_build(&self) -> bool {
+        self.command.split_whitespace().next().is_some_and(|program| BUILD_COMMANDS.contains(&program))
+    }
+}
+
+#[derive(Default)]
+pub struct CommandStats {
+    records: Vec<CommandRecord>,
+}
+
+impl CommandStats {
+    pub fn record(&mut self, record: CommandRecord) {
+        self.records.push(record);
+        if self.records.len() > MAX_RECORDS {
+            self.records.remove(0);
+        }
+    }
+
+    fn slowest(&self, count: usize) -> Vec<&CommandRecord> {
+        let mut sorted: Vec<&CommandRecord> = self.records.iter().collect();
+        sorted.sort_by_key(|r| std::cmp::Reverse(r.duration));
+        sorted.truncate(count);
+        sorted
+    }
+
+    // (pane, command count, total time), busiest first
+    fn busiest_panes(&self) -> Vec<(&str, usize, Duration)> {
+        let mut panes: HashMap<&str, (usize, Duration)> = HashMap::new();
+        for record in &self.records {
+            let entry = panes.entry(&record.pane).or_default();
+            entry.0 += 1;
+            entry.1 += record.duration;
+        }
+        let mut panes: Vec<(&str, usize, Duration)> = panes.into_iter().map(|(pane, (n, total))| (pane, n, total)).collect();
+        panes.sort_by_key(|&(_, _, total)| std::cmp::Reverse(total));
+        panes
+    }
+
+    fn build_time_today(&self) -> Duration {
+        let midnight = local_midnight();
+        self.records.iter()
+            .filter(|r| r.started_at >= midnight && r.is_build())
+            .map(|r| r.duration)
+            .sum()
+    }
+
+    pub fn render(&self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new("Command stats")
+            .open(open)
+            .collapsible(false)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                if self.records.is_empty() {
+                    ui.label("No commands timed yet. Timing needs shell integration (OSC 133) in the shell.");
+                    return;
+                }
+
+                ui.label(format!("Time in builds today: {}", format_duration(self.build_time_today())));
+                ui.separator();
+
+                ui.strong("Slowest commands");
+                egui::Grid::new("slowest_commands").striped(true).show(ui, |ui| {
+                    for record in self.slowest(10) {
+                        ui.label(egui::RichText::new(&record.command).monospace());
+                        ui.label(&record.pane);
+                        ui.label(format_duration(record.duration));
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+
+                ui.strong("Busiest panes");
+                egui::Grid::new("busiest_panes").striped(true).show(ui, |ui| {
+                    for (pane, count, total) in self.busiest_panes() {
+                        ui.label(pane);
+                        ui.label(format!("{} commands", count));
+                        ui.label(format_duration(total));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
+
+fn local_midnight() -> SystemTime {
+    // SAFETY: localtime_r/mktime only touch the tm struct we hand them
+    let midnight = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm.tm_hour = 0;
+        tm.tm_min = 0;
+        tm.tm_sec = 0;
+        libc::mktime(&mut tm)
+    };
+    UNIX_EPOCH + Duration::from_secs(midnight.max(0) as u64)
+}
@@ -0,0 +1,76 @@
+use eframe::egui;
+use std::process::Command;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// System theme =======================================
+// The desktop's light/dark preference, from the XDG desktop portal (GNOME, KDE and
+// most others), polled in the background since a D-Bus call can take a moment. Where
+// there's no portal, whatever the window system tells egui.
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// What the portal said last
+const UNKNOWN: u8 = 0;
+const DARK: u8 = 1;
+const LIGHT: u8 = 2;
+
+pub struct SystemTheme {
+    portal: Arc<AtomicU8>,
+}
+
+impl SystemTheme {
+    pub fn new(ctx: &egui::Context) -> Self {
+        let portal = Arc::new(AtomicU8::new(UNKNOWN));
+        let shared = Arc::clone(&portal);
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            // No portal (or no dbus-send) won't come back later, so stop there
+            while let Some(preference) = read_portal() {
+                // The window may be sitting idle; wake it to switch
+                if shared.swap(preference, Ordering::Relaxed) != preference {
+                    ctx.request_repaint();
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        });
+        Self { portal }
+    }
+
+    // Whether the desktop wants dark mode, or None if it doesn't say
+    pub fn is_dark(&self, ctx: &egui::Context) -> Option<bool> {
+        match self.portal.load(Ordering::Relaxed) {
+            DARK => Some(true),
+            LIGHT => Some(false),
+            _ => ctx.system_theme().map(|theme| theme == egui::Theme::Dark),
+        }
+    }
+}
+
+// org.freedesktop.appearance color-scheme: 0 is no preference, 1 dark, 2 light.
+// The reply ends with e.g. "variant       variant          uint32 1".
+fn read_portal() -> Option<u8> {
+    let output = Command::new("dbus-send")
+        .args([
+            "--session",
+            "--print-reply=literal",
+            "--reply-timeout=1000",
+            "--dest=org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.Settings.Read",
+            "string:org.freedesktop.appearance",
+            "string:color-scheme",
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let reply = String::from_utf8_lossy(&output.stdout);
+    match reply.split_whitespace().last()? {
+        "1" => Some(DARK),
+        "2" => Some(LIGHT),
+        _ => Some(UNKNOWN),
+    }
+}
@@ -0,0 +1,168 @@
+use eframe::egui;
+
+use crate::config::Config;
+use crate::manager::TerminalManager;
+use crate::session::Session;
+use crate::utils::confirm_close;
+
+// Tabs ===============================================
+// Workspaces, each with its own set of panes and layout, e.g. "build" and "servers".
+// The strip only shows once there's more than one.
+
+pub struct Tab {
+    pub name: String,
+    pub manager: TerminalManager,
+}
+
+pub struct Tabs {
+    tabs: Vec<Tab>,
+    active: usize,
+    renaming: Option<usize>,  // Tab whose name is being edited
+    next_number: usize,  // For "Tab N" names, so closing one doesn't reuse a name
+    closing: Option<(usize, Vec<String>)>,  // Tab waiting on the user to confirm closing it, and what runs in it
+}
+
+impl Tabs {
+    pub fn new(manager: TerminalManager) -> Self {
+        Self {
+            tabs: vec![Tab { name: "Tab 1".to_string(), manager }],
+            active: 0,
+            renaming: None,
+            next_number: 2,
+            closing: None,
+        }
+    }
+
+    // Reopen the tabs from the last session
+    pub fn restore(config: &Config, session: Session) -> Self {
+        let active = session.active_tab.min(session.tabs.len() - 1);
+        let tabs: Vec<Tab> = session.tabs.into_iter()
+            .map(|tab| Tab { name: tab.name.clone(), manager: TerminalManager::restore(config.clone(), tab) })
+            .collect();
+        Self {
+            next_number: tabs.len() + 1,
+            tabs,
+            active,
+            renaming: None,
+            closing: None,
+        }
+    }
+
+    pub fn session(&self) -> Session {
+        Session::new(self.active, self.tabs.iter().map(|tab| tab.manager.session(&tab.name)).collect())
+    }
+
+    pub fn active(&self) -> &TerminalManager {
+        &self.tabs[self.active].manager
+    }
+
+    pub fn active_mut(&mut self) -> &mut TerminalManager {
+        &mut self.tabs[self.active].manager
+    }
+
+    pub fn managers_mut(&mut self) -> impl Iterator<Item = &mut TerminalManager> {
+        self.tabs.iter_mut().map(|tab| &mut tab.manager)
+    }
+
+    // Add a tab after the others and switch to it
+    pub fn add(&mut self, manager: TerminalManager) {
+        self.tabs.push(Tab { name: format!("Tab {}", self.next_number), manager });
+        self.next_number += 1;
+        self.active = self.tabs.len() - 1;
+    }
+
+    // Close a tab and its panes; the last tab stays
+    pub fn close(&mut self, index: usize) {
+        if self.tabs.len() > 1 && index < self.tabs.len() {
+            self.closing = None;
+            self.tabs.remove(index);
+            if self.active > index || self.active == self.tabs.len() {
+                self.active -= 1;
+            }
+            self.renaming = None;
+        }
+    }
+
+    // A tab whose last pane was closed goes too, unless it's the only one
+    pub fn close_empty(&mut self) {
+        if let Some(index) = self.tabs.iter().position(|tab| tab.manager.terminal_count() == 0) {
+            self.close(index);
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        self.active = (self.active + 1) % self.tabs.len();
+    }
+
+    pub fn select_previous(&mut self) {
+        self.active = (self.active + self.tabs.len() - 1) % self.tabs.len();
+    }
+
+    // Double-click a tab to rename it
+    pub fn render(&mut self, ctx: &egui::Context) {
+        self.render_close_confirmation(ctx);
+        if self.tabs.len() < 2 {
+            return;
+        }
+        let mut close = None;
+
+        egui::TopBottomPanel::top("tab_strip")
+            .frame(egui::Frame::default()
+                .fill(egui::Color32::from_gray(40))
+                .inner_margin(egui::Margin::symmetric(8, 4)))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for (index, tab) in self.tabs.iter_mut().enumerate() {
+                        if self.renaming == Some(index) {
+                            let response = ui.add(egui::TextEdit::singleline(&mut tab.name).desired_width(120.0));
+                            response.request_focus();
+                            if response.lost_focus() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                                self.renaming = None;
+                            }
+                            continue;
+                        }
+
+                        let label = match tab.manager.badge() {
+                            Some(badge) => format!("{} {}", badge.icon(), tab.name),
+                            None => tab.name.clone(),
+                        };
+                        let response = ui.selectable_label(index == self.active, label);
+                        if response.clicked() {
+                            self.active = index;
+                        }
+                        if response.double_clicked() {
+                            self.renaming = Some(index);
+                        }
+                        if response.middle_clicked() || ui.small_button("×").on_hover_text("Close tab").clicked() {
+                            close = Some(index);
+                        }
+                        ui.add_space(8.0);
+                    }
+                });
+            });
+
+        if let Some(index) = close {
+            let processes = self.tabs[index].manager.running_processes();
+            if processes.is_empty() {
+                self.close(index);
+            } else {
+                self.closing = Some((index, processes));
+            }
+        }
+    }
+
+    fn render_close_confirmation(&mut self, ctx: &egui::Context) {
+        let Some((index, processes)) = &self.closing else {
+            return;
+        };
+        let index = *index;
+        match confirm_close(ctx, "Close this tab?", processes, "Close") {
+            Some(true) => {
+                self.closing = None;
+                self.close(index);
+            }
+            Some(false) => self.closing = None,
+            None => {}
+        }
+    }
+}
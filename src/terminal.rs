@@ -1,12 +1,95 @@
 use eframe::egui;
 use egui::scroll_area::ScrollBarVisibility;
-use ptyprocess::PtyProcess;
+use ptyprocess::{PtyProcess, WaitStatus};
 use std::process::Command;
-use std::io::{Write, Read};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::io::Write;
 use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
 
-use crate::header::{Header, HeaderAction};
-use crate::parser::{parse_ansi_output, TerminalOutput};
+use crate::actions::{find_token_actions, open_url, TokenAction, TokenActionKind, TokenRule};
+use crate::background::{self, BackgroundImage};
+use crate::config::{data_dir, Bell, Config, OnExit, Profile, Theme};
+use crate::copy_mode::{CopyMode, CopyModeAction};
+use crate::grid::{cells_text, Cell, Grid, MouseTracking, ShellMark};
+use crate::header::{Badge, Header, HeaderAction};
+use crate::history::{History, HistorySearch};
+use crate::input::InputLine;
+use crate::export::{export, ExportColors, ExportFormat};
+use crate::keybindings::KeyChord;
+use crate::keys::{encode_key, encode_kitty_key, encode_text, KeyEventType, KITTY_ALL_KEYS};
+use crate::line_layout::{LayoutCache, LineLayout, SpanKind};
+use crate::links::{describe_path, find_links, resolve_path, LinkTarget};
+use crate::output_log::{LogOptions, OutputLog};
+use crate::playback::{format_time, CastEvent, Playback, SPEEDS};
+use crate::parser::{TerminalOutput, Utf8Decoder};
+use crate::reader::PtyReader;
+use crate::scrollback::{Scrollback, ScrollbackLimit};
+use crate::search::Search;
+use crate::selection::Selection;
+use crate::session::TerminalSession;
+use crate::split::SplitDirection;
+use crate::stats::CommandRecord;
+use crate::theme::{apply_edits, default_palette, find_scheme, palette_table, parse_palette, Palette};
+use crate::utils::{format_duration, DEFAULT_LIGHTNESS, DEFAULT_SATURATION};
+
+// What the view needs to know about each scrollback line before it's in view. Lines
+// don't change once they've scrolled off the screen, so only those new since the last
+// frame get looked at; the lines in view are styled when they're drawn.
+#[derive(Default)]
+struct LineInfoCache {
+    lines: VecDeque<LineInfo>,
+}
+
+// Small, since with unlimited scrollback there's one for every line ever printed
+struct LineInfo {
+    columns: u32,  // How wide it is, for its height before it's laid out
+    error: bool,  // Marked on the minimap
+}
+
+impl LineInfoCache {
+    fn update(&mut self, scrollback: &Scrollback) {
+        for cells in scrollback.iter_from(self.lines.len()) {
+            let error = is_error_text(&cells_text(&cells));
+            self.lines.push_back(LineInfo { columns: cells.len() as u32, error });
+        }
+    }
+
+    fn drop_front(&mut self, count: usize) {
+        self.lines.drain(..count.min(self.lines.len()));
+    }
+}
+
+// A stretch of output text that reacts to the mouse
+enum Span<'a> {
+    Link(LinkTarget),
+    Token(&'a TokenRule),
+}
+
+// A command the shell marked the end of (OSC 133 D), annotated at the end of its output
+struct CommandBlock {
+    last_line: usize,
+    exit_code: Option<i32>,
+    duration: std::time::Duration,
+}
+
+// Which way a search bar button or Enter moves through the matches
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SearchJump {
+    Previous,
+    Next,
+}
+
+// Zoom limits for text_size
+const MIN_TEXT_SIZE: f32 = 6.0;
+const MAX_TEXT_SIZE: f32 = 72.0;
+
+const CURSOR_BLINK: std::time::Duration = std::time::Duration::from_millis(500);
+
+// Words on the cursor line that mean the program is reading a password
+const PASSWORD_PROMPTS: [&str; 2] = ["password", "passphrase"];
 
 // Terminal ===========================================
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -15,7 +98,20 @@ pub enum TerminalResponse {
     WasClicked,
     CloseMe,
     MaximizeMe,
-    MinimizeMe
+    MinimizeMe,
+    SplitMe(SplitDirection),  // Put a new pane next to this one
+}
+
+// What was picked from the output's right-click menu
+#[derive(Clone, Copy)]
+enum MenuAction {
+    Copy(ExportFormat),
+    Paste,
+    SelectAll,
+    Search,
+    ClearScrollback,
+    Export(ExportFormat),
+    Split(SplitDirection),
 }
 
 pub struct Terminal {
@@ -25,43 +121,361 @@ pub struct Terminal {
     pub width: f32,
     pub height: f32,
     pty: Option<PtyProcess>,
-    output_buffer: String,
     text_size: f32,
-    command_buffer: String,
+    command_buffer: InputLine,  // Typed in normal mode, sent on Enter
+    history: History,  // Commands submitted at the prompt
+    history_search: Option<HistorySearch>,  // Ctrl+R in progress
     cursor_visible: bool,
     last_cursor_toggle: std::time::Instant,
-    raw_mode: bool,  // True when in interactive program (SSH, vim, etc.)
-    is_maximized: bool
+    raw_mode: bool,  // A fullscreen program (vim, htop, less) is on the alternate screen; the grid shows as is
+    handed_over: Option<(usize, usize)>,  // Where the line starts once Tab has given it to the shell to complete
+    is_maximized: bool,
+    exit_code: Option<i32>,  // Set once the shell has exited and been reaped
+    config: Config,
+    bell_override: Option<Bell>,  // From the profile, instead of the config's bell
+    bell_at: Option<std::time::Instant>,  // When the bell last rang, for the border flash
+    bell_pending: bool,  // Rang since the pane was last shown active; marks its header and tab
+    unseen_output: bool,  // Output arrived since the pane was last shown active
+    last_output_at: Option<std::time::Instant>,
+    last_process_check: std::time::Instant,  // The foreground process is looked up twice a second
+    silence_override: Option<u64>,  // From the profile, instead of the config's silence_seconds
+    log: Option<OutputLog>,
+    running_command: Option<String>,  // Sent from the prompt, timed once the shell marks its end (OSC 133 D)
+    finished_commands: Vec<CommandRecord>,
+    prompt_lines: Vec<usize>,  // Output lines where prompts are, for the minimap, separators and prompt jumps
+    shell_integration: bool,  // The shell marks its prompts (OSC 133), so Enter doesn't have to
+    first_visible_line: usize,  // Where prompt jumps go from
+    output_started: Option<std::time::Instant>,  // OSC 133 C seen, waiting for D
+    command_blocks: Vec<CommandBlock>,
+    scroll_to_line: Option<(usize, Option<egui::Align>)>,  // No alignment scrolls just enough to show it
+    top_line: Option<usize>,  // First visible line, or None while following the bottom
+    anchor_line: Option<usize>,  // Put back at the top at once, after what's above it changed
+    lines_when_scrolled_up: Option<usize>,  // Line count when the view left the bottom; lines since are new
+    scroll_to_end: bool,  // Follow the bottom again, from the new lines pill
+    scroll_layout: Option<(egui::Id, u32)>,  // Scroll area and width the lines were laid out in
+    outgoing_text: Option<String>,  // From a "send" token action, for the manager to deliver
+    grid: Grid,  // Screen state and scrollback
+    scrollback_override: Option<ScrollbackLimit>,  // From the profile, instead of the config's scrollback_lines
+    line_info: LineInfoCache,
+    layout_cache: LayoutCache,  // Galleys of the lines drawn last frame
+    reader: Option<PtyReader>,  // Started on the first frame, once there's a Context to wake
+    utf8_decoder: Utf8Decoder,
+    shell: String,
+    spawn_error: Option<String>,  // Why the shell couldn't be started, shown instead of output
+    shell_choice: String,  // Shell typed into the error panel
+    selection: Option<Selection>,  // Lines are scrollback + screen in normal mode, grid rows in raw mode
+    selecting: bool,  // A drag is extending the selection
+    copy_mode: Option<CopyMode>,
+    mouse_button: Option<u8>,  // Button held down while the program tracks the mouse
+    mouse_cell: Option<(usize, usize)>,  // Last cell reported, so motion is only sent on a change
+    wheel_delta: f32,  // Scrolling not yet reported, in rows
+    search: Search,
+    paste_requested: bool,  // Waiting for the clipboard after a paste chord egui doesn't paste on, or a middle-click
+    window_size: (u16, u16),  // Columns and rows the PTY was last given
+    env: BTreeMap<String, String>,  // From the profile, for the shell on top of the standard variables
+    working_dir: Option<PathBuf>,  // Where a restarted shell starts; None for our own directory
+    profile_name: Option<String>,
+    shell_args: Vec<String>,  // From the profile
+    theme_override: Option<Theme>,  // From the profile, instead of the window's dark mode
+    font_size_override: Option<f32>,  // From the profile, instead of the config's font_size
+    playback: Option<Playback>,  // A recording shown instead of a shell
+    ime_preedit: String,  // Text being composed with an input method (CJK, dead keys), not yet typed
+    ime_cursor: Option<egui::Rect>,  // Where the input method's candidate window goes
 }
 
 impl Terminal {
-    pub fn new(id: usize, width: f32, height: f32, hue: f32, is_maximized:bool) -> Self {
-        let mut pty = PtyProcess::spawn(Command::new("bash")).ok();
-        
-        // Set initial PTY size (80 cols x 24 rows is a common default)
-        if let Some(ref mut p) = pty {
-            let _ = p.set_window_size(80, 24);
+    // The size comes from the layout once the pane is placed
+    pub fn new(id: usize, hue: f32, is_maximized:bool, config: &Config, profile: Option<&Profile>, working_dir: Option<PathBuf>) -> Self {
+        let mut terminal = Self::without_shell(id, hue, is_maximized, config, working_dir);
+        // Before the shell starts, so it gets the profile's environment and arguments
+        if let Some(profile) = profile {
+            terminal.apply_profile(profile);
         }
-        
-        Self {
+        let shell = profile.and_then(|p| p.shell.as_deref()).or(config.shell.as_deref()).unwrap_or("bash");
+        terminal.start_shell(shell);
+        if let Some(command) = profile.and_then(|p| p.command.as_deref()) {
+            terminal.run_command(command);
+        }
+        terminal
+    }
+
+    // A pane playing back a recording, at the size it was recorded at
+    pub fn playback(id: usize, hue: f32, is_maximized: bool, config: &Config, playback: Playback) -> Self {
+        let mut terminal = Self::without_shell(id, hue, is_maximized, config, None);
+        let name = playback.path().file_name().map_or_else(|| "recording".into(), |name| name.to_string_lossy());
+        terminal.header.set_program_title(&format!("▶ {}", name));
+        terminal.grid.resize(playback.size.0, playback.size.1);
+        terminal.playback = Some(playback);
+        terminal
+    }
+
+    fn without_shell(id: usize, hue: f32, is_maximized: bool, config: &Config, working_dir: Option<PathBuf>) -> Self {
+        let mut terminal = Self {
             id,
             is_active: false,
             header: Header::new(hue, is_maximized),
-            width,
-            height,
-            pty,
-            output_buffer: String::new(),
-            text_size: 18.0,
-            command_buffer: String::new(),
+            width: 100.0,
+            height: 100.0,
+            pty: None,
+            text_size: config.font_size,
+            command_buffer: InputLine::default(),
+            history: History::default(),
+            history_search: None,
             cursor_visible: true,
             last_cursor_toggle: std::time::Instant::now(),
             raw_mode: false,
-            is_maximized: is_maximized
+            handed_over: None,
+            is_maximized,
+            exit_code: None,
+            config: config.clone(),
+            bell_override: None,
+            bell_at: None,
+            bell_pending: false,
+            unseen_output: false,
+            last_output_at: None,
+            last_process_check: std::time::Instant::now(),
+            silence_override: None,
+            log: None,
+            running_command: None,
+            finished_commands: Vec::new(),
+            prompt_lines: Vec::new(),
+            shell_integration: false,
+            first_visible_line: 0,
+            output_started: None,
+            command_blocks: Vec::new(),
+            scroll_to_line: None,
+            top_line: None,
+            anchor_line: None,
+            lines_when_scrolled_up: None,
+            scroll_to_end: false,
+            scroll_layout: None,
+            outgoing_text: None,
+            grid: Grid::new(80, 24),
+            scrollback_override: None,
+            line_info: LineInfoCache::default(),
+            layout_cache: LayoutCache::default(),
+            reader: None,
+            utf8_decoder: Utf8Decoder::default(),
+            shell: String::new(),
+            spawn_error: None,
+            shell_choice: String::new(),
+            selection: None,
+            selecting: false,
+            copy_mode: None,
+            mouse_button: None,
+            mouse_cell: None,
+            wheel_delta: 0.0,
+            search: Search::default(),
+            paste_requested: false,
+            window_size: (80, 24),
+            env: BTreeMap::new(),
+            working_dir,
+            profile_name: None,
+            shell_args: Vec::new(),
+            theme_override: None,
+            font_size_override: None,
+            playback: None,
+            ime_preedit: String::new(),
+            ime_cursor: None,
+        };
+        terminal.grid.set_scrollback_limit(config.scrollback_lines);
+        terminal.set_color_scheme(config.color_scheme.as_deref());
+        terminal.header.set_default_palette(configured_palette(config));
+        if let Some(path) = terminal.default_log_path() {
+            terminal.header.set_log_path(&path);
+        }
+        terminal
+    }
+
+    // Spawn `shell` on a new PTY, keeping the error for the in-pane panel if that fails
+    fn start_shell(&mut self, shell: &str) {
+        self.shell = shell.to_string();
+        self.shell_choice = shell.to_string();
+        let mut command = Command::new(shell);
+        command.args(&self.shell_args);
+        // Tell programs what they're talking to, so they pick the right escape sequences
+        command.env("TERM", "xterm-256color")
+            .env("COLORTERM", "truecolor")
+            .env("TERM_PROGRAM", "sigmaterm")
+            .envs(&self.env);
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+        match PtyProcess::spawn(command) {
+            Ok(mut pty) => {
+                // Whatever fits the pane so far; sync_window_size keeps it up to date
+                let _ = pty.set_window_size(self.window_size.0, self.window_size.1);
+                self.pty = Some(pty);
+                self.reader = None;
+                self.utf8_decoder = Utf8Decoder::default();
+                self.spawn_error = None;
+                self.exit_code = None;
+            }
+            Err(e) => {
+                eprintln!("Warning: Failed to start {}: {}", shell, e);
+                self.spawn_error = Some(format!("{}: {}", shell, e));
+            }
+        }
+    }
+
+    // Apply a profile's settings to this pane. The environment and shell arguments only
+    // reach shells started after this.
+    pub fn apply_profile(&mut self, profile: &Profile) {
+        self.profile_name = Some(profile.name.clone());
+        self.shell_args = profile.args.clone();
+        self.theme_override = profile.theme;
+        if profile.color_scheme.is_some() {
+            self.set_color_scheme(profile.color_scheme.as_deref());
+        }
+        if !profile.palette.is_empty() {
+            self.header.set_palette_edits(parse_palette(&profile.palette));
+        }
+        self.font_size_override = profile.font_size;
+        if let Some(size) = profile.font_size {
+            self.set_text_size(size);
+        }
+        self.scrollback_override = profile.scrollback_lines;
+        self.grid.set_scrollback_limit(profile.scrollback_lines.unwrap_or(self.config.scrollback_lines));
+        self.bell_override = if profile.mute_bell { Some(Bell::Off) } else { profile.bell };
+        self.silence_override = profile.silence_seconds;
+        self.env = profile.env.clone();
+        if let Some(path) = self.default_log_path() {
+            self.header.set_log_path(&path);
+            if profile.log_output && self.log.is_none() {
+                self.start_log(&path, LogOptions::default());
+            }
+        }
+    }
+
+    // ~/.local/share/sigmaterm/logs/<profile>-<time>-<pane>.log
+    fn default_log_path(&self) -> Option<PathBuf> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        // Profile names are free text; keep only what's safe in a file name
+        let name: String = self.profile_name.as_deref().unwrap_or("pane").chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        Some(data_dir()?.join("logs").join(format!("{}-{}-{}.log", name, timestamp, self.id)))
+    }
+
+    // Append the output from now on to a file, as picked in the header's log popup
+    fn start_log(&mut self, path: &std::path::Path, options: LogOptions) {
+        match OutputLog::open(path, options) {
+            Ok(log) => {
+                self.header.set_logging(Ok(Some(log.path().to_path_buf())));
+                self.log = Some(log);
+            }
+            Err(e) => {
+                eprintln!("Warning: Failed to open log file {}: {}", path.display(), e);
+                self.header.set_logging(Err(format!("Can't open it: {}", e)));
+            }
+        }
+    }
+
+    fn stop_log(&mut self) {
+        self.log = None;
+        self.header.set_logging(Ok(None));
+    }
+
+    // Save the scrollback and what's on the main screen, as picked in the header's
+    // output popup; files without an extension get the format's
+    fn export_scrollback(&mut self, format: ExportFormat) {
+        let mut path = resolve_path(self.header.get_export_path(), self.shell_cwd().as_deref());
+        if path.extension().is_none() {
+            path.set_extension(format.extension());
+        }
+        let palette = self.header.palette();
+        let colors = ExportColors {
+            palette: &palette,
+            foreground: self.header.get_terminal_text_color_imm(),
+            background: self.header.get_terminal_bg_color_imm(),
+        };
+        let lines = self.grid.main_lines();
+        let status = match create_new_file(&mut path).and_then(|mut file| file.write_all(export(lines, format, &colors).as_bytes())) {
+            Ok(()) => format!("Saved to {}", path.display()),
+            Err(e) => {
+                eprintln!("Warning: Failed to export to {}: {}", path.display(), e);
+                format!("Can't save to {}: {}", path.display(), e)
+            }
+        };
+        self.header.set_export_status(status);
+    }
+
+    pub fn set_config(&mut self, config: &Config) {
+        // Keep the pane's zoom unless the configured size itself changed
+        if config.font_size != self.config.font_size && self.font_size_override.is_none() {
+            self.set_text_size(config.font_size);
+        }
+        // Panes still on the configured scheme move to the new one
+        let same_scheme = |a: Option<&str>, b: Option<&str>| a.map(str::to_lowercase) == b.map(str::to_lowercase);
+        if !same_scheme(config.color_scheme.as_deref(), self.config.color_scheme.as_deref())
+            && same_scheme(self.header.get_scheme_name(), self.config.color_scheme.as_deref())
+        {
+            self.set_color_scheme(config.color_scheme.as_deref());
+        }
+        if config.palette != self.config.palette {
+            self.header.set_default_palette(configured_palette(config));
+        }
+        self.config = config.clone();
+        // Token actions are found when lines are laid out
+        self.layout_cache.clear();
+        self.grid.set_scrollback_limit(self.scrollback_override.unwrap_or(config.scrollback_lines));
+    }
+
+    // Zoom the pane's text; the PTY is resized to match on the next frame
+    fn set_text_size(&mut self, size: f32) {
+        self.text_size = size.clamp(MIN_TEXT_SIZE, MAX_TEXT_SIZE);
+    }
+
+    // Give the PTY and the grid as many columns and rows as fit in the output area.
+    // Runs every frame, so splits, closed panes, maximizing, window resizes and zoom
+    // all reach the program (as SIGWINCH) once the layout settles.
+    fn sync_window_size(&mut self, ui: &egui::Ui, area: egui::Vec2) {
+        // Skip the odd frame where the pane hasn't been laid out yet; recordings keep
+        // the size they were made at
+        if area.x < 40.0 || area.y < 20.0 || self.playback.is_some() {
+            return;
+        }
+        let font_id = egui::FontId::monospace(self.text_size);
+        let (char_width, row_height) = ui.fonts_mut(|f| (f.glyph_width(&font_id, 'M'), f.row_height(&font_id)));
+        // Less the left padding and the minimap strip; lines are spaced apart by item_spacing
+        let cols = ((area.x - 24.0) / char_width).floor().max(2.0) as u16;
+        let rows = (area.y / (row_height + ui.spacing().item_spacing.y)).floor().max(1.0) as u16;
+        if (cols, rows) == self.window_size {
+            return;
+        }
+        self.window_size = (cols, rows);
+        self.grid.resize(cols as usize, rows as usize);
+        if let Some(pty) = &mut self.pty {
+            let _ = pty.set_window_size(cols, rows);
         }
     }
 
+    pub fn set_hue(&mut self, hue: f32) {
+        self.header.set_color_set(hue);
+    }
+
+    pub fn set_shade(&mut self, saturation: f32, lightness: f32) {
+        self.header.set_shade(saturation, lightness);
+    }
+
+    pub fn set_background(&mut self, opacity: f32, image: Option<BackgroundImage>) {
+        self.header.set_opacity(opacity);
+        self.header.set_background_image(image);
+    }
+
+    // The desktop shows through the pane
+    pub fn is_translucent(&self) -> bool {
+        self.header.get_opacity() < 1.0
+    }
+
     pub fn set_dark_mode(&mut self, dark_mode: bool) {
-        self.header.set_dark_mode(dark_mode);
+        self.header.set_dark_mode(match self.theme_override {
+            Some(Theme::Dark) => true,
+            Some(Theme::Light) => false,
+            Some(Theme::System) | None => dark_mode,
+        });
     }
 
     pub fn set_active(&mut self, active: bool) {
@@ -93,6 +507,28 @@ impl Terminal {
         self.header.get_title().to_string()
     }
 
+    pub fn set_title(&mut self, title: &str) {
+        self.header.set_title(title);
+    }
+
+    // None follows the hue
+    pub fn set_color_scheme(&mut self, name: Option<&str>) {
+        self.header.set_scheme(name.and_then(find_scheme).cloned());
+    }
+
+    // ANSI colors changed in this pane, as saved in the session
+    pub fn set_palette_edits(&mut self, palette: &BTreeMap<String, String>) {
+        self.header.set_palette_edits(parse_palette(palette));
+    }
+
+    pub fn get_icon(&self) -> Option<&str> {
+        self.header.get_icon()
+    }
+
+    pub fn set_icon(&mut self, icon: Option<String>) {
+        self.header.set_icon(icon);
+    }
+
     pub fn get_primary_color(&self) -> egui::Color32 {
         self.header.get_primary_color_imm()
     }
@@ -101,49 +537,452 @@ impl Terminal {
         self.header.get_terminal_text_color_imm()
     }
 
-    pub fn read_output(&mut self) {
-        if let Some(pty) = &mut self.pty {
-            if let Ok(mut stream) = pty.get_raw_handle() {
-                let fd = stream.as_raw_fd();
-                unsafe {
-                    let flags = libc::fcntl(fd, libc::F_GETFL, 0);
-                    libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
-                }
-                
-                let mut buffer = [0u8; 4096];
-                match stream.read(&mut buffer) {
-                    Ok(n) if n > 0 => {
-                        let new_output = String::from_utf8_lossy(&buffer[..n]);
-                        
-                        // Detect raw mode: if output contains certain escape sequences
-                        // that indicate screen manipulation (alternate screen buffer, cursor positioning, etc.)
-                        // NOTE: Disabled for now - vim/fullscreen apps need a proper terminal grid
-                        // which is complex to implement. For now, only SSH works reasonably.
-                        if false && (new_output.contains("\x1b[?1049h") || // Alternate screen buffer
-                           new_output.contains("\x1b[?25l") ||   // Hide cursor (vim, ssh)
-                           new_output.contains("\x1b[2J") ||     // Clear screen
-                           new_output.contains("\x1b[H\x1b[2J")) { // Home + clear
-                            self.raw_mode = true;
-                        }
-                        
-                        // Exit raw mode when we see the alternate screen buffer exit
-                        if new_output.contains("\x1b[?1049l") {
-                            self.raw_mode = false;
-                            self.output_buffer.clear(); // Clear buffer when exiting raw mode
-                        }
-                        
-                        self.output_buffer.push_str(&new_output);
-                        
-                        // Keep buffer size reasonable (last 50KB of output)
-                        if self.output_buffer.len() > 50000 {
-                            let keep_from = self.output_buffer.len() - 50000;
-                            self.output_buffer = self.output_buffer[keep_from..].to_string();
+    // Non-blocking waitpid on the shell so an exited child doesn't linger as a zombie
+    pub fn reap_child(&mut self) {
+        if self.exit_code.is_some() {
+            return;
+        }
+        if let Some(pty) = &self.pty {
+            self.exit_code = match pty.status() {
+                Ok(WaitStatus::Exited(_, code)) => Some(code),
+                Ok(WaitStatus::Signaled(_, signal, _)) => Some(128 + signal as i32),
+                Ok(_) => None,
+                // ECHILD: someone else already collected it
+                Err(_) => Some(-1),
+            };
+        }
+    }
+
+    // Start the shell again in this pane, after the old one's output and in the directory
+    // it was in. Whatever state it left the screen in (alternate screen, colors, modes) is
+    // reset first; the title and colors stay.
+    pub fn restart_shell(&mut self) {
+        // A shell that already exited has no directory left to look up; use the last one
+        self.working_dir = self.shell_cwd().or(self.working_dir.take());
+        if let Some(mut pty) = self.pty.take()
+            && self.exit_code.is_none()
+        {
+            let _ = pty.exit(true);
+        }
+        self.grid.feed("\x1b[?1049l\x1b[0m\x1b[?2004l\x1b[?25h\x1b[<99u\x1b[r\x1b[?6l\x1b[?7h\x1b]110\x07\x1b]111\x07\x1b]112\x07");
+        self.header.set_program_colors([None; 3]);
+        if self.grid.cursor().1 > 0 {
+            self.grid.feed("\r\n");
+        }
+        self.raw_mode = false;
+        self.handed_over = None;
+        self.selection = None;
+        self.command_buffer.clear();
+        self.running_command = None;
+        let shell = self.shell.clone();
+        self.start_shell(&shell);
+    }
+
+    // What to reopen next launch
+    pub fn session(&self) -> TerminalSession {
+        let (saturation, lightness) = self.header.get_shade();
+        TerminalSession {
+            title: self.header.is_renamed().then(|| self.get_title()),
+            icon: self.get_icon().map(str::to_string),
+            color_scheme: self.header.get_scheme_name().map(str::to_string),
+            palette: palette_table(self.header.get_palette_edits()),
+            profile: self.profile_name.clone(),
+            hue: self.header.get_hue(),
+            saturation: (saturation != DEFAULT_SATURATION).then_some(saturation),
+            lightness: (lightness != DEFAULT_LIGHTNESS).then_some(lightness),
+            opacity: self.is_translucent().then(|| self.header.get_opacity()),
+            background_image: self.header.get_background_image().cloned(),
+            working_dir: self.shell_cwd().or(self.working_dir.clone()),
+            recording: self.playback.as_ref().map(|playback| playback.path().to_path_buf()),
+        }
+    }
+
+    // Kill the shell (if still running) and close the PTY fd right away,
+    // rather than whenever the Terminal happens to be dropped
+    pub fn shutdown(&mut self) {
+        if let Some(mut pty) = self.pty.take()
+            && self.exit_code.is_none()
+            && let Err(e) = pty.exit(true)
+        {
+            eprintln!("Warning: Failed to cleanly exit PTY process: {}", e);
+        }
+        self.reader = None;
+        self.command_buffer.clear();
+        self.grid = Grid::new(80, 24);
+        self.window_size = (80, 24);
+        self.line_info = LineInfoCache::default();
+    }
+
+    // Programs the shell started that are still running (its children), by command line
+    pub fn running_processes(&self) -> Vec<String> {
+        match &self.pty {
+            Some(pty) if self.exit_code.is_none() => child_processes(pty.pid().as_raw())
+                .into_iter()
+                .filter_map(process_command_line)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Process group currently in the foreground of the PTY, and the shell's own pid
+    fn foreground_pgrp(&self) -> Option<(i32, i32)> {
+        let pty = self.pty.as_ref()?;
+        let stream = pty.get_raw_handle().ok()?;
+        let pgrp = unsafe { libc::tcgetpgrp(stream.as_raw_fd()) };
+        (pgrp > 0).then(|| (pgrp, pty.pid().as_raw()))
+    }
+
+    // The PTY's local modes (termios c_lflag), as whatever is reading it last set them
+    fn line_discipline(&self) -> Option<libc::tcflag_t> {
+        let stream = self.pty.as_ref()?.get_raw_handle().ok()?;
+        // SAFETY: tcgetattr only writes into the termios struct we hand it
+        let termios = unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            (libc::tcgetattr(stream.as_raw_fd(), &mut termios) == 0).then_some(termios)
+        };
+        termios.map(|termios| termios.c_lflag)
+    }
+
+    // A program reading a password (sudo, ssh) reads a whole line after a prompt that
+    // asks for one. The ECHO flag can't tell: ptyprocess turns echo off in the child at
+    // spawn, since the pane echoes what's typed itself, so it's off for `cat` and `read`
+    // too. Line editors (readline) turn line reading off and echo for themselves.
+    fn input_hidden(&self) -> bool {
+        if self.line_discipline().is_none_or(|flags| flags & libc::ICANON == 0) {
+            return false;
+        }
+        let (_, col) = self.grid.cursor();
+        let prompt = self.grid.main_line(self.grid.cursor_line())
+            .map(|cells| cells_text(&cells[..col.min(cells.len())]).to_lowercase())
+            .unwrap_or_default();
+        PASSWORD_PROMPTS.iter().any(|word| prompt.contains(word))
+    }
+
+    pub fn is_raw_mode(&self) -> bool {
+        self.raw_mode
+    }
+
+    // Whether keys go straight to the program rather than into the line typed here:
+    // fullscreen programs, and ones that read key by key (python, psql, anything with
+    // readline) and so turned off the line discipline's line editing. The shell's own
+    // prompt does that too but is typed here all the same, up to a Tab.
+    pub fn keys_to_program(&self) -> bool {
+        self.raw_mode
+            || self.handed_over.is_some()
+            || (self.line_discipline().is_some_and(|flags| flags & libc::ICANON == 0) && !self.shell_at_prompt())
+    }
+
+    // The shell itself (not a program it launched) is reading input
+    fn shell_at_prompt(&self) -> bool {
+        self.foreground_pgrp().is_some_and(|(pgrp, shell)| pgrp == shell)
+    }
+
+    // Show what's running in the pane (`vim`, `cargo build`) next to its title
+    pub fn track_foreground_process(&mut self) {
+        if self.last_process_check.elapsed().as_millis() < 500 {
+            return;
+        }
+        self.last_process_check = std::time::Instant::now();
+        let process = self.foreground_pgrp()
+            .filter(|(pgrp, shell)| pgrp != shell)
+            .and_then(|(pgrp, _)| process_command_line(pgrp));
+        self.header.set_process(process);
+    }
+
+    // A command sent from the prompt on `line`: it goes in the history, and gets timed
+    // if the shell marks where its output starts and ends.
+    // Only ones typed at the prompt count, not input fed to a running program.
+    fn track_command(&mut self, command: &str, line: usize) {
+        if self.shell_at_prompt() && !self.input_hidden() {
+            if !self.shell_integration {
+                self.prompt_lines.push(line);
+            }
+            self.running_command = Some(command.trim().to_string()).filter(|command| !command.is_empty());
+            self.history.push(command);
+        }
+    }
+
+    // Tab completion is the shell's: the line typed so far goes to it with the Tab,
+    // and the shell edits the line from then on, echoing it, until Enter
+    fn hand_over_line(&mut self, modifiers: egui::Modifiers) {
+        let (_, col) = self.grid.cursor();
+        self.handed_over = Some((self.grid.cursor_line(), col));
+        let line = self.command_buffer.take();
+        self.write_to_pty(&line);
+        self.send_key(egui::Key::Tab, modifiers, KeyEventType::Press);
+    }
+
+    // Enter on a line the shell has been editing: the command is what's on screen
+    // after the prompt
+    fn submit_handed_over_line(&mut self) {
+        let Some((line, col)) = self.handed_over.take() else {
+            return;
+        };
+        let command = self.grid.main_line(line)
+            .map(|cells| cells_text(cells.get(col..).unwrap_or_default()))
+            .unwrap_or_default();
+        self.track_command(command.trim_end(), line);
+    }
+
+    pub fn take_finished_commands(&mut self) -> Vec<CommandRecord> {
+        std::mem::take(&mut self.finished_commands)
+    }
+
+    fn delete_word_backward(&mut self) {
+        let word_chars = &self.config;
+        self.command_buffer.delete_word_backward(|c| word_chars.is_word_char(c));
+    }
+
+    // Typed or composed text: to the program if it reads keys, else the history search
+    // or the line being typed
+    fn insert_text(&mut self, text: &str) {
+        if self.keys_to_program() {
+            self.write_to_pty(text);
+        } else if let Some(search) = &mut self.history_search {
+            search.query.push_str(text);
+            search.skip = 0;
+            self.show_history_match();
+        } else {
+            // In normal mode, add to command buffer
+            self.command_buffer.insert(text);
+        }
+    }
+
+    // The input method's composition in progress, at the cursor over the output and
+    // underlined, until it's committed
+    fn paint_preedit(&self, ui: &egui::Ui, cursor: egui::Rect) {
+        let color = self.header.get_terminal_text_color_imm();
+        let galley = ui.painter().layout_no_wrap(self.ime_preedit.clone(), egui::FontId::monospace(self.text_size), color);
+        let rect = egui::Rect::from_min_size(cursor.min, galley.size());
+        ui.painter().rect_filled(rect, 0.0, self.header.get_terminal_bg_color_imm());
+        ui.painter().hline(rect.x_range(), rect.bottom(), egui::Stroke::new(1.0, color));
+        ui.painter().galley(rect.min, galley, color);
+    }
+
+    // What a key sends, in the kitty keyboard protocol if the program asked for it
+    fn send_key(&mut self, key: egui::Key, modifiers: egui::Modifiers, event_type: KeyEventType) {
+        let sequence = match self.grid.keyboard_flags() {
+            0 if event_type == KeyEventType::Release => None,
+            0 => encode_key(key, modifiers, self.grid.app_cursor_keys),
+            flags => encode_kitty_key(key, modifiers, event_type, flags),
+        };
+        if let Some(sequence) = sequence {
+            self.write_to_pty(&sequence);
+        }
+    }
+
+    fn write_to_pty(&mut self, data: &str) {
+        self.write_bytes_to_pty(data.as_bytes());
+    }
+
+    fn write_bytes_to_pty(&mut self, data: &[u8]) {
+        if let Some(pty) = &self.pty
+            && let Ok(mut stream) = pty.get_raw_handle()
+        {
+            let _ = stream.write_all(data);
+            let _ = stream.flush();
+        }
+    }
+
+    // Take in whatever the reader thread has read from the PTY since the last frame
+    pub fn read_output(&mut self, ctx: &egui::Context) {
+        if self.reader.is_none()
+            && let Some(pty) = &self.pty
+            && let Ok(stream) = pty.get_raw_handle()
+        {
+            self.reader = Some(PtyReader::spawn(stream, ctx.clone()));
+        }
+
+        let chunks = self.reader.as_ref().map(PtyReader::drain).unwrap_or_default();
+        let played = self.play_recording();
+        if self.playback.as_ref().is_some_and(Playback::is_playing) {
+            // Recorded output has no reader thread to wake the UI
+            ctx.request_repaint();
+        }
+        if !chunks.is_empty() || played {
+            self.unseen_output = true;
+            self.last_output_at = Some(std::time::Instant::now());
+        } else if let Some(remaining) = self.silence_remaining()
+            && !remaining.is_zero()
+        {
+            // Nothing else wakes the UI to show the silence badge
+            ctx.request_repaint_after(remaining);
+        }
+        for chunk in chunks {
+            if let Some(log) = &mut self.log {
+                log.write(&chunk);
+            }
+
+            let new_output = self.utf8_decoder.decode(&chunk);
+            self.take_in(&new_output);
+        }
+
+        // Scrollback lines can also be dropped by a lower limit from the config or profile,
+        // or all at once by ED 3 (`clear`)
+        let dropped = self.grid.take_dropped_lines();
+        if dropped > 0 {
+            self.line_info.drop_front(dropped);
+            self.shift_line_marks(dropped);
+        }
+    }
+
+    // Put output on the screen. The grid keeps partial escape sequences between reads, so
+    // alternate screen switches (CSI ?1049h/l, ?47h/l), colors and the bell survive chunk
+    // boundaries; fullscreen programs get raw mode
+    fn take_in(&mut self, output: &str) {
+        self.grid.pane_colors = self.header.pane_colors().map(|color| (color.r(), color.g(), color.b()));
+        self.grid.feed(output);
+        self.header.set_program_colors(self.grid.program_colors().map(|color| color.map(|(r, g, b)| egui::Color32::from_rgb(r, g, b))));
+        let responses = self.grid.take_responses();
+        if !responses.is_empty() {
+            self.write_to_pty(&responses);
+        }
+        self.search.invalidate();
+        if self.raw_mode != self.grid.alt_screen() {
+            // Line numbers mean something else on the other screen
+            self.selection = None;
+            self.copy_mode = None;
+        }
+        self.raw_mode = self.grid.alt_screen();
+        if let Some(title) = self.grid.take_title() {
+            self.header.set_program_title(&title);
+        }
+        for (mark, line) in self.grid.take_shell_marks() {
+            self.shell_integration = true;
+            match mark {
+                ShellMark::PromptStart if self.prompt_lines.last() != Some(&line) => self.prompt_lines.push(line),
+                ShellMark::OutputStart => self.output_started = Some(std::time::Instant::now()),
+                // Shells send D before the first prompt too, with no command to end
+                ShellMark::CommandEnd(exit_code) => {
+                    if let Some(started) = self.output_started.take() {
+                        let duration = started.elapsed();
+                        self.command_blocks.push(CommandBlock {
+                            last_line: line.saturating_sub(1),
+                            exit_code,
+                            duration,
+                        });
+                        // Only commands the shell timed count; without shell integration there's nothing to go on
+                        if let Some(command) = self.running_command.take() {
+                            self.finished_commands.push(CommandRecord {
+                                command,
+                                pane: self.get_title(),
+                                started_at: std::time::SystemTime::now() - duration,
+                                duration,
+                            });
                         }
                     }
-                    _ => {}
                 }
+                _ => {}
+            }
+        }
+        if self.grid.take_bell() {
+            self.ring_bell();
+        }
+    }
+
+    // Show the recording up to where playback has got to, starting the screen over
+    // after a seek backward; true if anything was shown
+    fn play_recording(&mut self) -> bool {
+        let Some(mut playback) = self.playback.take() else {
+            return false;
+        };
+        let (cols, rows) = playback.size;
+        let (rewound, events) = playback.advance();
+        if rewound {
+            self.grid = Grid::new(cols, rows);
+            self.grid.set_scrollback_limit(self.scrollback_override.unwrap_or(self.config.scrollback_lines));
+            self.line_info = LineInfoCache::default();
+            self.prompt_lines.clear();
+            self.command_blocks.clear();
+            self.selection = None;
+            self.copy_mode = None;
+            self.top_line = None;
+            self.lines_when_scrolled_up = None;
+            self.search.invalidate();
+        }
+        let played = rewound || !events.is_empty();
+        for (_, event) in events {
+            match event {
+                CastEvent::Output(output) => self.take_in(output),
+                CastEvent::Resize(cols, rows) => self.grid.resize(*cols, *rows),
             }
         }
+        self.playback = Some(playback);
+        played
+    }
+
+    fn ring_bell(&mut self) {
+        let bell = self.bell_override.unwrap_or(self.config.bell);
+        if bell == Bell::Off {
+            return;
+        }
+        // A program printing a run of BELs gets one flash and one sound
+        if self.bell_at.is_some_and(|t| t.elapsed().as_millis() < 200) {
+            return;
+        }
+        self.bell_at = Some(std::time::Instant::now());
+        self.bell_pending = true;
+        if matches!(bell, Bell::Sound | Bell::Both) {
+            play_bell_sound(self.config.bell_sound.as_deref());
+        }
+    }
+
+    // Time until unseen output counts as gone silent, if silence_seconds is set
+    fn silence_remaining(&self) -> Option<std::time::Duration> {
+        let seconds = self.silence_override.or(self.config.silence_seconds)?;
+        let last_output_at = self.last_output_at.filter(|_| self.unseen_output)?;
+        Some(std::time::Duration::from_secs(seconds).saturating_sub(last_output_at.elapsed()))
+    }
+
+    // What to mark the pane's header and tab with
+    pub fn badge(&self) -> Option<Badge> {
+        if self.bell_pending {
+            Some(Badge::Bell)
+        } else if self.silence_remaining().is_some_and(|remaining| remaining.is_zero()) {
+            Some(Badge::Silence)
+        } else if self.unseen_output {
+            Some(Badge::Activity)
+        } else {
+            None
+        }
+    }
+
+    // Drop everything that's scrolled off the top, the way `clear` does
+    fn clear_scrollback(&mut self) {
+        self.grid.clear_scrollback();
+        let dropped = self.grid.take_dropped_lines();
+        if dropped > 0 {
+            self.line_info.drop_front(dropped);
+            self.shift_line_marks(dropped);
+        }
+        self.search.invalidate();
+    }
+
+    // Lines fell off the top of the scrollback; shift minimap marks and the scroll position to match
+    fn shift_line_marks(&mut self, removed_lines: usize) {
+        self.prompt_lines.retain(|&line| line >= removed_lines);
+        for line in &mut self.prompt_lines {
+            *line -= removed_lines;
+        }
+        if !self.raw_mode
+            && let Some(copy_mode) = &mut self.copy_mode
+        {
+            copy_mode.shift(removed_lines);
+        }
+        self.command_blocks.retain(|block| block.last_line >= removed_lines);
+        for block in &mut self.command_blocks {
+            block.last_line -= removed_lines;
+        }
+        // The view is measured in pixels, which now land further down the output; keep the
+        // lines being read in place instead
+        self.top_line = self.top_line.map(|line| line.saturating_sub(removed_lines));
+        self.anchor_line = self.top_line;
+        self.lines_when_scrolled_up = self.lines_when_scrolled_up.map(|count| count.saturating_sub(removed_lines));
+        self.handed_over = self.handed_over.map(|(line, col)| (line.saturating_sub(removed_lines), col));
+        if !self.raw_mode {
+            self.selection = self.selection.and_then(|selection| selection.shifted(removed_lines));
+            self.search.shift(removed_lines);
+        }
     }
 
     // Returns true if terminal was clicked
@@ -152,22 +991,42 @@ impl Terminal {
         let mut header_action: HeaderAction = HeaderAction::None;
         
         ui.push_id(self.id, |ui| {
-            self.read_output();
-            
-            // Toggle cursor visibility
-            if self.last_cursor_toggle.elapsed().as_millis() > 500 {
-                self.cursor_visible = !self.cursor_visible;
-                self.last_cursor_toggle = std::time::Instant::now();
+            self.layout_cache.begin_frame(ui.ctx());
+            // Blink the cursor, only in the focused pane of a focused window; the blink is
+            // the only thing that repaints an idle terminal, so elsewhere it stays still
+            if self.is_active && ui.input(|i| i.focused) {
+                if self.last_cursor_toggle.elapsed() >= CURSOR_BLINK {
+                    self.cursor_visible = !self.cursor_visible;
+                    self.last_cursor_toggle = std::time::Instant::now();
+                }
+                ui.ctx().request_repaint_after(CURSOR_BLINK.saturating_sub(self.last_cursor_toggle.elapsed()));
+            } else {
+                self.cursor_visible = true;
             }
             
-            let stroke = if self.is_active {
+            // Seen once the pane is on screen and focused
+            if self.is_active {
+                self.bell_pending = false;
+                self.unseen_output = false;
+            }
+            self.header.set_badge(self.badge());
+            let visual_bell = matches!(self.bell_override.unwrap_or(self.config.bell), Bell::Visual | Bell::Both);
+            let bell_flashing = visual_bell && self.bell_at.is_some_and(|t| t.elapsed().as_millis() < 200);
+            if bell_flashing && let Some(bell_at) = self.bell_at {
+                // A frame to take the flash down again
+                ui.ctx().request_repaint_after(std::time::Duration::from_millis(200).saturating_sub(bell_at.elapsed()));
+            }
+            let stroke = if bell_flashing {
+                egui::Stroke::new(2.0, self.header.color_set.warning)
+            } else if self.is_active {
                 egui::Stroke::new(2.0, self.header.get_primary_color())
             } else {
                 egui::Stroke::new(2.0, egui::Color32::from_gray(100))
             };
             
+            let opacity = self.header.get_opacity();
             let frame_response = egui::Frame::default()
-                .fill(self.header.get_terminal_bg_color_imm())
+                .fill(self.header.get_terminal_bg_color_imm().gamma_multiply(opacity))
                 .stroke(stroke)  // border to show active state
                 .show(ui, |ui| {
                     ui.set_max_width(self.width-2.0);
@@ -175,6 +1034,10 @@ impl Terminal {
                     
                     // Allocate the full rect for the terminal
                     let rect = ui.available_rect_before_wrap();
+                    let background = self.header.get_terminal_bg_color_imm();
+                    if let Some((image, texture)) = self.header.background_texture(ui.ctx()) {
+                        background::paint(ui.painter(), rect, texture, opacity, image.dim, background);
+                    }
 
                     ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui|{
                         header_action = self.header.render(ui, self.is_active);
@@ -183,22 +1046,94 @@ impl Terminal {
                             HeaderAction::CloseTerminal => terminal_response = TerminalResponse::CloseMe,
                             HeaderAction::MinimizeTerminal => terminal_response = TerminalResponse::MinimizeMe,
                             HeaderAction::MaximizeTerminal => terminal_response = TerminalResponse::MaximizeMe,
-                            HeaderAction::None => {},
+                            HeaderAction::RestartShell if self.playback.is_none() => self.restart_shell(),
+                            HeaderAction::StartLog => {
+                                let (path, options) = self.header.get_log_request();
+                                self.start_log(&resolve_path(&path, self.shell_cwd().as_deref()), options);
+                            }
+                            HeaderAction::StopLog => self.stop_log(),
+                            HeaderAction::Export(format) => self.export_scrollback(format),
+                            HeaderAction::RestartShell | HeaderAction::None => {},
                         };
+
+                        if let Some(error) = self.spawn_error.clone() {
+                            self.render_spawn_error(ui, &error);
+                            return;
+                        }
+                        if let Some(code) = self.exit_code {
+                            match self.config.on_exit {
+                                OnExit::Close => terminal_response = TerminalResponse::CloseMe,
+                                OnExit::CloseOnSuccess if code == 0 => terminal_response = TerminalResponse::CloseMe,
+                                _ => {}
+                            }
+                            if let Some(response) = self.render_exit_banner(ui, code) {
+                                terminal_response = response;
+                            }
+                        }
                         
-                        let color_set = self.header.color_set.clone();
+                        let palette = self.header.palette();
                         let default_color = self.header.get_terminal_text_color_imm();
+                        let default_background = self.header.get_terminal_bg_color_imm();
+                        self.line_info.update(self.grid.scrollback());
+
+                        if self.history_search.is_some() {
+                            self.render_history_search(ui);
+                        }
+                        if self.copy_mode.is_some() {
+                            self.render_copy_mode_bar(ui);
+                        }
+                        if self.playback.is_some() {
+                            self.render_playback_bar(ui);
+                        }
+                        let jump = if self.search.open { self.render_search_bar(ui) } else { None };
+                        let moved = if self.raw_mode {
+                            self.search.update(self.grid.rows().iter().map(Vec::as_slice))
+                        } else {
+                            self.search.update(self.grid.main_lines())
+                        };
+                        let moved = match jump {
+                            Some(SearchJump::Previous) => self.search.previous().is_some(),
+                            Some(SearchJump::Next) => self.search.next().is_some(),
+                            None => moved,
+                        };
+                        if moved
+                            && let Some(found) = self.search.current_match()
+                        {
+                            // Copy mode's cursor follows the search, so `/` then `v` selects from a match
+                            if let Some(copy_mode) = &mut self.copy_mode {
+                                copy_mode.move_to((found.line, found.start));
+                                self.selection = copy_mode.selection();
+                            }
+                            if !self.raw_mode {
+                                self.scroll_to_line = Some((found.line, Some(egui::Align::Center)));
+                            }
+                        }
                         
+                        let scroll_target = self.scroll_to_line.take();
+                        let anchor = self.anchor_line.take();
+                        let scroll_to_end = std::mem::take(&mut self.scroll_to_end);
+                        let mut line_count = 0;
+                        let mut error_lines: Vec<usize> = Vec::new();
+                        let mut token_action: Option<TokenAction> = None;
+                        let mut first_visible_line: Option<usize> = None;
+                        let mut line_rects: Vec<(usize, egui::Rect)> = Vec::new();
+                        let mut ime_cursor: Option<egui::Rect> = None;
+                        // Out of self while the closures below borrow it
+                        let mut layouts = std::mem::take(&mut self.layout_cache);
+
                         let scroll_area = egui::ScrollArea::vertical()
                             .scroll_bar_visibility(ScrollBarVisibility::VisibleWhenNeeded)
                             .auto_shrink([false; 2])
-                            .stick_to_bottom(true)
+                            .stick_to_bottom(self.top_line.is_none())  // Scrolled up, output doesn't pull the view down
+                            .animated(anchor.is_none() && !scroll_to_end)  // Staying on the same lines and jumping to the end happen at once
+                            .scroll_source(egui::scroll_area::ScrollSource { drag: false, ..Default::default() })  // Dragging selects text
                             .max_width(self.width - 4.0); // Constrain width to prevent expansion
                         
-                        scroll_area.show(ui, |ui| {
+                        let scroll_output = scroll_area.show_viewport(ui, |ui, viewport| {
                             ui.set_max_width(self.width - 4.0); // Also constrain the inner ui
                             ui.style_mut().spacing.item_spacing.x = 0.0;
                             ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Wrap);
+                            ui.style_mut().interaction.selectable_labels = false;  // We do our own selection
                             
                             // Add left padding by wrapping content
                             ui.horizontal(|ui| {
@@ -208,118 +1143,229 @@ impl Terminal {
                                     ui.set_max_width(self.width - 20.0); // Constrain content width
                                     ui.spacing_mut().item_spacing.x = 0.0;
                                     
-                            let parsed_segments = parse_ansi_output(
-                                &self.output_buffer,
-                                &color_set,
-                                default_color
-                            );
-                            
                             if self.raw_mode {
-                                // In raw mode, just show the raw text as-is in a simple label
-                                // This won't be perfect but works for basic interactive programs
-                                let raw_text = self.output_buffer
-                                    .replace("\x1b[?1049h", "") // Remove alternate screen enter
-                                    .replace("\x1b[?1049l", "") // Remove alternate screen exit
-                                    .replace("\x1b[?25l", "")   // Remove hide cursor
-                                    .replace("\x1b[?25h", "");  // Remove show cursor
-                                
-                                ui.label(egui::RichText::new(raw_text)
-                                    .size(self.text_size)
-                                    .color(default_color)
-                                    .monospace()
-                                );
+                                // In raw mode, draw the screen grid the program painted
+                                line_rects = self.render_grid(ui, &mut layouts).into_iter().enumerate().collect();
+                                let (cursor_row, cursor_col) = self.grid.cursor();
+                                let char_width = ui.fonts_mut(|f| f.glyph_width(&egui::FontId::monospace(self.text_size), 'M'));
+                                ime_cursor = line_rects.get(cursor_row).map(|(_, rect)| {
+                                    egui::Rect::from_min_size(egui::pos2(rect.left() + cursor_col as f32 * char_width, rect.top()), egui::vec2(1.0, rect.height()))
+                                });
                             } else {
-                                // Normal mode: use the existing line-by-line rendering
-                            
-                            let mut current_line_segments: Vec<TerminalOutput> = Vec::new();
-                            
-                            for segment in parsed_segments {
-                                let text = segment.text.replace("\r\n", "\n");
-                                let lines: Vec<&str> = text.split(|c| c == '\n' || c == '\r').collect();
-                                
-                                for (i, line) in lines.iter().enumerate() {
-                                    if i > 0 {
-                                        ui.horizontal(|ui| {
-                                            ui.spacing_mut().item_spacing.x = 0.0;
-                                            if current_line_segments.is_empty() {
-                                                ui.label(egui::RichText::new(" ")
+                                // Normal mode: the main screen line by line, scrollback first. Only the
+                                // lines in view are laid out; the rest just take up the room they would,
+                                // worked out from how wide they are.
+
+                            let screen_lines = self.grid.screen_lines();
+                            let scrollback_len = self.line_info.lines.len();
+                            let (_, cursor_col) = self.grid.cursor();
+                            let cursor_line = self.grid.cursor_line();
+                            let hidden_input = self.input_hidden();
+                            let typing = self.is_active && self.exit_code.is_none();
+                            line_count = scrollback_len + screen_lines.len();
+
+                            // Where each line starts in the content, wrapping where it's wider than the pane
+                            let row_height = ui.fonts_mut(|f| f.row_height(&egui::FontId::monospace(self.text_size)));
+                            let spacing = ui.spacing().item_spacing.y;
+                            let first_row = row_height.max(ui.spacing().interact_size.y);
+                            let cols = self.grid.cols().max(1);
+                            let input_len = self.command_buffer.before_cursor().chars().count() + self.command_buffer.after_cursor().chars().count() + 1;
+                            let mut tops = Vec::with_capacity(line_count + 1);
+                            let mut top = 0.0;
+                            for index in 0..line_count {
+                                let columns = match self.line_info.lines.get(index) {
+                                    Some(line) => line.columns as usize,
+                                    None => {
+                                        let cells = &screen_lines[index - scrollback_len];
+                                        let used = cells.iter().rposition(|cell| *cell != Cell::default()).map_or(0, |i| i + 1);
+                                        if index == cursor_line && typing { used.max(cursor_col) + input_len } else { used }
+                                    }
+                                };
+                                tops.push(top);
+                                top += (columns.div_ceil(cols).max(1) as f32 * row_height).max(first_row) + spacing;
+                            }
+                            tops.push(top);
+
+                            error_lines = self.line_info.lines.iter().enumerate()
+                                .filter(|(_, line)| line.error)
+                                .map(|(index, _)| index)
+                                .collect();
+                            for (row, cells) in screen_lines.iter().enumerate() {
+                                if is_error_text(&cells_text(cells)) {
+                                    error_lines.push(scrollback_len + row);
+                                }
+                            }
+
+                            if let Some(line) = anchor
+                                && line < line_count
+                            {
+                                let origin = ui.cursor().top();
+                                let rect = egui::Rect::from_x_y_ranges(ui.max_rect().x_range(), origin + tops[line]..=origin + tops[line + 1] - spacing);
+                                ui.scroll_to_rect(rect, Some(egui::Align::TOP));
+                                // Drawn where it was, the output would jump for a frame
+                                ui.ctx().request_discard("keep the view on the same lines");
+                            }
+                            if let Some((target, align)) = scroll_target
+                                && target < line_count
+                            {
+                                let origin = ui.cursor().top();
+                                let rect = egui::Rect::from_x_y_ranges(ui.max_rect().x_range(), origin + tops[target]..=origin + tops[target + 1] - spacing);
+                                ui.scroll_to_rect(rect, align);
+                            }
+                            if scroll_to_end {
+                                // Past the end, so the area stops right at the bottom and sticks there
+                                let bottom = ui.cursor().top() + tops[line_count];
+                                let rect = egui::Rect::from_x_y_ranges(ui.max_rect().x_range(), bottom..=bottom + viewport.height());
+                                ui.scroll_to_rect(rect, Some(egui::Align::BOTTOM));
+                                ui.ctx().request_discard("scroll to the end");
+                            }
+
+                            // A line whose gap is all that shows isn't in view: scrolling a line to the
+                            // top leaves that much of the one above it
+                            let first = tops[1..].partition_point(|&next| next <= viewport.top() + spacing);
+                            let last = tops[..line_count].partition_point(|&top| top < viewport.bottom()).max(first);
+                            first_visible_line = (first < line_count).then_some(first);
+                            ui.add_space(tops[first]);
+
+                            for index in first..last {
+                                let is_cursor_line = index == cursor_line;
+                                let show_input = is_cursor_line && typing;
+                                let built_segments;
+                                let (segments, rest): (&[TerminalOutput], &[TerminalOutput]) = if index < scrollback_len {
+                                    let cells = self.grid.scrollback().get(index).unwrap_or_default();
+                                    built_segments = (line_segments(&cells, &palette, default_color, default_background), Vec::new());
+                                    (&built_segments.0, &built_segments.1)
+                                } else {
+                                    let cells = &screen_lines[index - scrollback_len];
+                                    // Keep the blanks up to the cursor, e.g. the space after a prompt
+                                    let used = cells.iter().rposition(|cell| *cell != Cell::default()).map_or(0, |i| i + 1);
+                                    let end = if is_cursor_line { used.max(cursor_col).min(cells.len()) } else { used };
+                                    // After a carriage return the cursor sits inside the line: input and the
+                                    // cursor go there, covering the cell under it, and the rest of the line follows
+                                    let split = if show_input { cursor_col.min(end) } else { end };
+                                    built_segments = (
+                                        line_segments(&cells[..split], &palette, default_color, default_background),
+                                        line_segments(&cells[(split + 1).min(end)..end], &palette, default_color, default_background),
+                                    );
+                                    (&built_segments.0, &built_segments.1)
+                                };
+
+                                let line_response = ui.horizontal_wrapped(|ui| {
+                                    ui.spacing_mut().item_spacing.x = 0.0;
+                                    // Lines without the input on them are drawn from their layout
+                                    // of the last frame, unless they've changed
+                                    if !show_input {
+                                        let layout = layouts.get(ui, segments, self.text_size, ui.available_width(), &self.config.token_actions);
+                                        token_action = self.render_line(ui, &layout).or(token_action.take());
+                                        return;
+                                    }
+                                    for seg in segments {
+                                        token_action = self.render_segment(ui, seg).or(token_action.take());
+                                    }
+
+                                    // Show command buffer and cursor on the cursor's line
+                                    if show_input {
+                                        // A password being typed shows as dots
+                                        let shown = |text: &str| if hidden_input { "•".repeat(text.chars().count()) } else { text.to_string() };
+                                        let (before, after) = (shown(self.command_buffer.before_cursor()), shown(self.command_buffer.after_cursor()));
+                                        if !before.is_empty() {
+                                            ui.label(egui::RichText::new(before)
+                                                .size(self.text_size)
+                                                .color(default_color)
+                                                .monospace()
+                                            );
+                                        }
+
+                                        ime_cursor = Some(egui::Rect::from_min_size(ui.cursor().min, egui::vec2(1.0, self.text_size)));
+
+                                        // Show cursor: a block at the end of the line, else
+                                        // the character under it in inverse
+                                        let mut rest_chars = after.chars();
+                                        match rest_chars.next() {
+                                            Some(under) => {
+                                                let under = egui::RichText::new(under.to_string())
+                                                    .size(self.text_size)
+                                                    .monospace();
+                                                ui.label(if self.cursor_visible {
+                                                    under.color(default_background).background_color(self.header.get_cursor_color_imm())
+                                                } else {
+                                                    under.color(default_color)
+                                                });
+                                            }
+                                            None if self.cursor_visible => {
+                                                ui.label(egui::RichText::new("█")
+                                                    .size(self.text_size)
+                                                    .color(self.header.get_cursor_color_imm())
+                                                    .monospace()
+                                                );
+                                            }
+                                            None => {
+                                                ui.label(egui::RichText::new("▂")
                                                     .size(self.text_size)
                                                     .monospace()
                                                 );
-                                            } else {
-                                                for seg in &current_line_segments {
-                                                    let mut text = egui::RichText::new(&seg.text)
-                                                        .size(self.text_size)
-                                                        .color(seg.color)
-                                                        .monospace();
-                                                    if seg.bold {
-                                                        text = text.strong();
-                                                    }
-                                                    ui.label(text);
-                                                }
                                             }
-                                        });
-                                        current_line_segments.clear();
+                                        }
+                                        if !rest_chars.as_str().is_empty() {
+                                            ui.label(egui::RichText::new(rest_chars.as_str())
+                                                .size(self.text_size)
+                                                .color(default_color)
+                                                .monospace()
+                                            );
+                                        }
                                     }
-                                    
-                                    if !line.is_empty() {
-                                        current_line_segments.push(TerminalOutput {
-                                            text: line.to_string(),
-                                            color: segment.color,
-                                            bold: segment.bold,
-                                        });
+                                    for seg in rest {
+                                        token_action = self.render_segment(ui, seg).or(token_action.take());
                                     }
+                                }).response;
+
+                                if ui.clip_rect().intersects(line_response.rect) {
+                                    line_rects.push((index, line_response.rect));
                                 }
                             }
-                            
-                            ui.horizontal_wrapped(|ui| {
-                                ui.spacing_mut().item_spacing.x = 0.0;
-                                
-                                for seg in &current_line_segments {
-                                    let mut text = egui::RichText::new(&seg.text)
-                                        .size(self.text_size)
-                                        .color(seg.color)
-                                        .monospace();
-                                    if seg.bold {
-                                        text = text.strong();
-                                    }
-                                    ui.label(text);
-                                }
-                                
-                                // Show command buffer and cursor if active and NOT in raw mode
-                                if self.is_active && !self.raw_mode {
-                                    if !self.command_buffer.is_empty() {
-                                        ui.label(egui::RichText::new(&self.command_buffer)
-                                            .size(self.text_size)
-                                            .color(default_color)
-                                            .monospace()
-                                        );
-                                    }
-                                    
-                                    // Show cursor
-                                    if self.cursor_visible {
-                                        ui.label(egui::RichText::new("█")
-                                            .size(self.text_size)
-                                            .color(default_color)
-                                            .monospace()
-                                        );
-                                    } else {
-                                        ui.label(egui::RichText::new("▂")
-                                            .size(self.text_size)
-                                            .monospace()
-                                        );
-                                    }
-                                }
-                            });
+                            ui.add_space(tops[line_count] - tops[last]);
                             } // Close else block
                                 }); // Close vertical
                             }); // Close horizontal
                         }); // Close ScrollArea
+                        self.layout_cache = layouts;
+
+                        self.sync_window_size(ui, scroll_output.inner_rect.size());
+                        self.ime_cursor = ime_cursor;
+                        if let Some(cursor) = ime_cursor
+                            && !self.ime_preedit.is_empty()
+                        {
+                            self.paint_preedit(ui, cursor);
+                        }
+                        if !self.report_mouse(ui, scroll_output.inner_rect, &line_rects) {
+                            self.update_selection(ui, scroll_output.inner_rect, &line_rects, token_action.is_some());
+                            if let Some(direction) = self.context_menu(ui, scroll_output.inner_rect) {
+                                terminal_response = TerminalResponse::SplitMe(direction);
+                            }
+                        }
+                        self.paint_highlights(ui, scroll_output.inner_rect, &line_rects);
+                        if !self.raw_mode {
+                            self.keep_scroll_position(&scroll_output, first_visible_line, line_count, scroll_target.is_some() || anchor.is_some() || scroll_to_end);
+                            self.first_visible_line = first_visible_line.unwrap_or(0);
+                            self.paint_prompt_separators(ui, scroll_output.inner_rect, &line_rects);
+                            self.paint_command_blocks(ui, scroll_output.inner_rect, &line_rects);
+                            self.render_minimap(ui, scroll_output.inner_rect, line_count, &error_lines);
+                            self.render_new_lines_pill(ui, scroll_output.inner_rect, line_count);
+                        }
+                        if let Some(action) = token_action {
+                            self.run_token_action(ui.ctx(), action);
+                        }
                     });
                     
                     rect 
                 });
             
+            // Ctrl+scroll zooms the pane under the pointer (egui turns it into a zoom delta)
+            let zoom_delta = ui.input(|i| i.zoom_delta());
+            if zoom_delta != 1.0 && ui.rect_contains_pointer(frame_response.response.rect) {
+                self.set_text_size(self.text_size * zoom_delta);
+            }
+
             if !self.is_active {
                 let response = ui.interact(
                     frame_response.inner,
@@ -327,139 +1373,1105 @@ impl Terminal {
                     egui::Sense::click()
                 );
                 
-                if response.clicked() { terminal_response = TerminalResponse::WasClicked;}
+                if response.clicked() || response.secondary_clicked() || response.middle_clicked() { terminal_response = TerminalResponse::WasClicked;}
             }
             
-            if self.is_active && !self.header.is_editing_title() {
-                self.handle_keyboard_input(ui);
+            if self.is_active
+                && !self.header.is_editing_title()
+                && !self.header.is_picking_emoji()
+                && !self.search.focused
+                && self.spawn_error.is_none()
+            {
+                // Lets the input method (fcitx, ibus, dead keys) compose text for the pane
+                if let Some(cursor) = self.ime_cursor {
+                    let rect = frame_response.response.rect;
+                    ui.ctx().output_mut(|o| o.ime = Some(egui::output::IMEOutput { rect, cursor_rect: cursor }));
+                }
+                if self.playback.is_some() {
+                    self.handle_playback_keys(ui);
+                } else if self.exit_code.is_some() {
+                    // Nothing is reading input any more; Enter starts a new shell
+                    let restart = self.config.keybindings.restart_shell;
+                    if ui.input_mut(|i| KeyChord::plain(egui::Key::Enter).consume(i) || restart.consume(i)) {
+                        self.restart_shell();
+                    }
+                } else {
+                    self.handle_keyboard_input(ui);
+                }
             }
-            
-            ui.ctx().request_repaint();
         });
         
         terminal_response
     }
 
+    // Shown instead of the output when the shell couldn't be started
+    fn render_spawn_error(&mut self, ui: &mut egui::Ui, error: &str) {
+        let text_color = self.header.get_terminal_text_color_imm();
+        ui.add_space(12.0);
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            ui.vertical(|ui| {
+                ui.label(egui::RichText::new("Couldn't start the shell").size(20.0).color(self.header.color_set.alert));
+                ui.add_space(4.0);
+                ui.label(egui::RichText::new(error).monospace().color(text_color));
+                ui.label(egui::RichText::new(spawn_error_hint(error)).color(text_color));
+                ui.add_space(8.0);
+
+                ui.horizontal(|ui| {
+                    if ui.button("Retry").clicked() {
+                        let shell = self.shell.clone();
+                        self.start_shell(&shell);
+                    }
+                    if ui.button("Copy error").clicked() {
+                        ui.ctx().copy_text(error.to_string());
+                    }
+                });
+                ui.add_space(8.0);
+
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("Another shell:").color(text_color));
+                    egui::ComboBox::from_id_salt("shell_choice")
+                        .selected_text(&self.shell_choice)
+                        .show_ui(ui, |ui| {
+                            for shell in installed_shells() {
+                                ui.selectable_value(&mut self.shell_choice, shell.clone(), shell);
+                            }
+                        });
+                    ui.add(egui::TextEdit::singleline(&mut self.shell_choice).desired_width(160.0));
+                    if ui.button("Start").clicked() && !self.shell_choice.trim().is_empty() {
+                        let shell = self.shell_choice.trim().to_string();
+                        self.start_shell(&shell);
+                    }
+                });
+            });
+        });
+    }
+
+    // Shown above the output once the shell has exited, unless on_exit closes the pane
+    fn render_exit_banner(&mut self, ui: &mut egui::Ui, code: i32) -> Option<TerminalResponse> {
+        let mut response = None;
+        let color = if code == 0 { self.header.get_terminal_text_color_imm() } else { self.header.color_set.alert };
+        let status = match code {
+            -1 => "Shell exited".to_string(),
+            code if code > 128 => format!("Shell exited with status {} (signal {})", code, code - 128),
+            code => format!("Shell exited with status {}", code),
+        };
+        ui.horizontal(|ui| {
+            ui.add_space(8.0);
+            ui.label(egui::RichText::new(status).color(color));
+            if ui.button("Restart").on_hover_text("Start a new shell here (Enter)").clicked() {
+                self.restart_shell();
+            }
+            if ui.button("Close").clicked() {
+                response = Some(TerminalResponse::CloseMe);
+            }
+        });
+        response
+    }
+
+    // Ctrl+R prompt above the output; the match itself is in the command buffer
+    fn render_history_search(&self, ui: &mut egui::Ui) {
+        let Some(search) = &self.history_search else {
+            return;
+        };
+        let text_color = self.header.get_terminal_text_color_imm();
+        ui.horizontal(|ui| {
+            ui.add_space(8.0);
+            ui.label(egui::RichText::new(format!("History: {}", search.query)).monospace().color(text_color));
+            if self.history.search(&search.query, search.skip).is_none() {
+                ui.label(egui::RichText::new("No match").color(self.header.color_set.alert));
+            }
+            ui.label(egui::RichText::new("Ctrl+R older, Enter run, Escape cancel").weak());
+        });
+    }
+
+    fn start_history_search(&mut self) {
+        match &mut self.history_search {
+            Some(search) => {
+                if self.history.search(&search.query, search.skip + 1).is_some() {
+                    search.skip += 1;
+                }
+            }
+            None => {
+                self.history_search = Some(HistorySearch {
+                    query: String::new(),
+                    skip: 0,
+                    original: self.command_buffer.as_str().to_string(),
+                });
+            }
+        }
+        self.show_history_match();
+    }
+
+    // Put the current Ctrl+R match in the command buffer
+    fn show_history_match(&mut self) {
+        if let Some(search) = &self.history_search
+            && let Some(entry) = self.history.search(&search.query, search.skip)
+        {
+            self.command_buffer.set(entry);
+        }
+    }
+
+    // Keys while Ctrl+R is open. Returns true if the key was used up; others end the
+    // search, keeping the match, and then act as usual (so Enter runs it).
+    fn handle_history_search_key(&mut self, key: egui::Key, modifiers: egui::Modifiers) -> bool {
+        let Some(search) = &mut self.history_search else {
+            return false;
+        };
+        match key {
+            egui::Key::R if modifiers.ctrl => {
+                self.start_history_search();
+                true
+            }
+            egui::Key::Backspace => {
+                search.query.pop();
+                search.skip = 0;
+                self.show_history_match();
+                true
+            }
+            egui::Key::Escape => {
+                self.command_buffer.set(&search.original);
+                self.history_search = None;
+                true
+            }
+            _ => {
+                self.history_search = None;
+                false
+            }
+        }
+    }
+
+    // Search bar above the output (Ctrl+Shift+F by default). Enter goes to the previous (older) match,
+    // Shift+Enter to the next one, Escape closes it.
+    fn render_search_bar(&mut self, ui: &mut egui::Ui) -> Option<SearchJump> {
+        let text_color = self.header.get_terminal_text_color_imm();
+        let mut jump = None;
+        let mut close = false;
+        ui.horizontal(|ui| {
+            ui.add_space(8.0);
+            let response = ui.add(egui::TextEdit::singleline(&mut self.search.query)
+                .hint_text("Search")
+                .desired_width(200.0)
+            );
+            if std::mem::take(&mut self.search.take_focus) {
+                response.request_focus();
+            }
+            if response.lost_focus() {
+                // Keep the keys that ended editing away from the shell
+                let (enter, escape, shift) = ui.input_mut(|i| (
+                    i.consume_key(egui::Modifiers::NONE, egui::Key::Enter) || i.consume_key(egui::Modifiers::SHIFT, egui::Key::Enter),
+                    i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+                    i.modifiers.shift,
+                ));
+                if escape {
+                    close = true;
+                } else if enter {
+                    jump = Some(if shift { SearchJump::Next } else { SearchJump::Previous });
+                    response.request_focus();
+                }
+            }
+            self.search.focused = response.has_focus();
+
+            ui.toggle_value(&mut self.search.case_sensitive, "Aa").on_hover_text("Match case");
+            ui.toggle_value(&mut self.search.regex, ".*").on_hover_text("Regular expression");
+            if ui.button("▲").on_hover_text("Previous match (Enter)").clicked() {
+                jump = Some(SearchJump::Previous);
+            }
+            if ui.button("▼").on_hover_text("Next match (Shift+Enter)").clicked() {
+                jump = Some(SearchJump::Next);
+            }
+
+            if let Some(error) = &self.search.error {
+                ui.label(egui::RichText::new(error.lines().last().unwrap_or("Invalid regex")).color(self.header.color_set.alert))
+                    .on_hover_text(error);
+            } else if !self.search.query.is_empty() {
+                let status = match self.search.current {
+                    Some(current) => format!("{}/{}", current + 1, self.search.matches.len()),
+                    None => "No matches".to_string(),
+                };
+                ui.label(egui::RichText::new(status).color(text_color));
+            }
+            if ui.button("×").on_hover_text("Close (Escape)").clicked() {
+                close = true;
+            }
+        });
+        if close {
+            self.search.close();
+        }
+        jump
+    }
+
+    // Switching between the split and maximized views puts the output in a different
+    // scroll area, and resizing re-wraps it, so pixel offsets don't carry over. Remember
+    // the first visible line instead and scroll back to it when the layout changes.
+    // While scrolled up, the lines arriving below are counted for the new lines pill.
+    fn keep_scroll_position<R>(&mut self, scroll_output: &egui::scroll_area::ScrollAreaOutput<R>, first_visible_line: Option<usize>, line_count: usize, scrolling: bool) {
+        let layout = (scroll_output.id, self.width.round() as u32);
+        if self.scroll_layout.is_some_and(|last| last != layout) {
+            self.scroll_layout = Some(layout);
+            self.anchor_line = self.top_line;
+            return;
+        }
+        self.scroll_layout = Some(layout);
+
+        // Wait for a requested scroll to land before recording where we are
+        if scrolling {
+            return;
+        }
+        let max_offset = (scroll_output.content_size.y - scroll_output.inner_rect.height()).max(0.0);
+        let at_bottom = scroll_output.state.offset.y >= max_offset - 2.0;
+        self.top_line = if at_bottom { None } else { first_visible_line };
+        self.lines_when_scrolled_up = self.top_line.map(|_| self.lines_when_scrolled_up.unwrap_or(line_count));
+    }
+
+    // "N new lines ↓" at the bottom while output arrives below a view scrolled up into
+    // the history; clicking it follows the output again
+    fn render_new_lines_pill(&mut self, ui: &egui::Ui, area: egui::Rect, line_count: usize) {
+        let Some(new_lines) = self.lines_when_scrolled_up.map(|count| line_count.saturating_sub(count)).filter(|&n| n > 0) else {
+            return;
+        };
+        let text = if new_lines == 1 { "1 new line ↓".to_string() } else { format!("{} new lines ↓", new_lines) };
+        // Its own layer, so clicking it doesn't start a selection underneath
+        let clicked = egui::Area::new(ui.id().with("new_lines"))
+            .pivot(egui::Align2::CENTER_BOTTOM)
+            .fixed_pos(egui::pos2(area.center().x, area.bottom() - 12.0))
+            .show(ui.ctx(), |ui| {
+                ui.add(egui::Button::new(egui::RichText::new(text).color(self.header.color_set.on_primary))
+                    .fill(self.header.color_set.primary)
+                    .corner_radius(12.0))
+                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                    .clicked()
+            })
+            .inner;
+        if clicked {
+            self.scroll_to_end = true;
+        }
+    }
+
+    // Scroll the prompt above (or below) the top of the view up to the top
+    fn jump_to_prompt(&mut self, forward: bool) {
+        let from = self.first_visible_line;
+        let target = if forward {
+            self.prompt_lines.iter().find(|&&line| line > from)
+        } else {
+            self.prompt_lines.iter().rev().find(|&&line| line < from)
+        };
+        if let Some(&line) = target {
+            self.scroll_to_line = Some((line, Some(egui::Align::TOP)));
+        }
+    }
+
+    // A faint rule above each prompt but the first, setting the commands' output apart
+    fn paint_prompt_separators(&self, ui: &egui::Ui, area: egui::Rect, line_rects: &[(usize, egui::Rect)]) {
+        let painter = ui.painter_at(area);
+        let stroke = egui::Stroke::new(1.0, self.header.get_terminal_text_color_imm().gamma_multiply(0.2));
+        for &(line, rect) in line_rects {
+            if line > 0 && self.prompt_lines.contains(&line) {
+                let y = rect.top() - 1.0;
+                painter.line_segment([egui::pos2(area.left() + 4.0, y), egui::pos2(area.right() - 20.0, y)], stroke);
+            }
+        }
+    }
+
+    // Exit status and run time at the right end of each finished command's last line,
+    // on red if it failed
+    fn paint_command_blocks(&self, ui: &egui::Ui, area: egui::Rect, line_rects: &[(usize, egui::Rect)]) {
+        let painter = ui.painter_at(area);
+        let font_id = egui::FontId::monospace(self.text_size * 0.75);
+        for block in &self.command_blocks {
+            let Some(&(_, rect)) = line_rects.iter().find(|(line, _)| *line == block.last_line) else {
+                continue;
+            };
+            let duration = format_duration(block.duration);
+            let (text, fill, color) = match block.exit_code {
+                Some(0) => (format!("✔ {}", duration), self.header.color_set.on_primary.gamma_multiply(0.15), self.header.get_terminal_text_color_imm()),
+                Some(code) => (format!("✘ {}  {}", code, duration), self.header.color_set.alert, egui::Color32::WHITE),
+                None => (duration, self.header.color_set.on_primary.gamma_multiply(0.15), self.header.get_terminal_text_color_imm()),
+            };
+            let galley = painter.layout_no_wrap(text, font_id.clone(), color);
+            // Left of the minimap strip
+            let badge = egui::Rect::from_min_size(
+                egui::pos2(area.right() - 24.0 - galley.size().x - 8.0, rect.top() + (rect.height() - galley.size().y - 2.0) / 2.0),
+                galley.size() + egui::vec2(8.0, 2.0),
+            );
+            painter.rect_filled(badge, 3.0, fill);
+            painter.galley(badge.min + egui::vec2(4.0, 1.0), galley, color);
+        }
+    }
+
+    // Slim strip along the right edge marking prompts (primary color), lines that
+    // look like errors (red) and search matches (yellow). Clicking jumps to the nearest mark.
+    fn render_minimap(&mut self, ui: &mut egui::Ui, area: egui::Rect, total_lines: usize, error_lines: &[usize]) {
+        let search_lines: Vec<usize> = self.search.matches.iter().map(|found| found.line).collect();
+        if self.prompt_lines.is_empty() && error_lines.is_empty() && search_lines.is_empty() {
+            return;
+        }
+
+        // Sit just left of the floating scroll bar
+        let strip = egui::Rect::from_min_max(
+            egui::pos2(area.right() - 16.0, area.top()),
+            egui::pos2(area.right() - 10.0, area.bottom()),
+        );
+        let line_y = |line: usize| strip.top() + strip.height() * (line as f32 + 0.5) / total_lines.max(1) as f32;
+
+        let painter = ui.painter_at(strip);
+        painter.rect_filled(strip, 2.0, self.header.color_set.on_primary.gamma_multiply(0.3));
+        for &line in error_lines {
+            let y = line_y(line);
+            painter.line_segment([egui::pos2(strip.left(), y), egui::pos2(strip.right(), y)], egui::Stroke::new(2.0, self.header.color_set.alert));
+        }
+        for &line in &search_lines {
+            let y = line_y(line);
+            painter.line_segment([egui::pos2(strip.left(), y), egui::pos2(strip.right(), y)], egui::Stroke::new(2.0, self.header.color_set.warning));
+        }
+        for &line in &self.prompt_lines {
+            let y = line_y(line);
+            painter.line_segment([egui::pos2(strip.left(), y), egui::pos2(strip.right(), y)], egui::Stroke::new(2.0, self.header.color_set.primary));
+        }
+
+        let response = ui.interact(strip, ui.id().with("minimap"), egui::Sense::click());
+        if response.clicked()
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            let nearest = self.prompt_lines.iter().chain(error_lines).chain(&search_lines)
+                .min_by(|&&a, &&b| (line_y(a) - pos.y).abs().total_cmp(&(line_y(b) - pos.y).abs()));
+            self.scroll_to_line = nearest.map(|&line| (line, Some(egui::Align::Center)));
+        }
+    }
+
+    // Draw the cell grid, one label per run of equally styled cells, with a block cursor.
+    // Returns where each row went.
+    // Rows that didn't change since the last frame are drawn from their cached layout
+    fn render_grid(&self, ui: &mut egui::Ui, layouts: &mut LayoutCache) -> Vec<egui::Rect> {
+        let default_color = self.header.get_terminal_text_color_imm();
+        let default_background = self.header.get_terminal_bg_color_imm();
+        let palette = self.header.palette();
+        let font_id = egui::FontId::monospace(self.text_size);
+        let char_width = ui.fonts_mut(|f| f.glyph_width(&font_id, 'M'));
+        let (cursor_row, cursor_col) = self.grid.cursor();
+        let mut row_rects = Vec::new();
+
+        for (row_index, row) in self.grid.rows().iter().enumerate() {
+            let row_rect = ui.horizontal(|ui| {
+                let segments = line_segments(row, &palette, default_color, default_background);
+                let layout = layouts.get(ui, &segments, self.text_size, f32::INFINITY, &self.config.token_actions);
+                ui.add(egui::Label::new(Arc::clone(&layout.galley)));
+            }).response.rect;
+
+            if row_index == cursor_row && self.grid.cursor_visible && self.is_active && self.cursor_visible {
+                let cursor_rect = egui::Rect::from_min_size(
+                    egui::pos2(row_rect.left() + cursor_col as f32 * char_width, row_rect.top()),
+                    egui::vec2(row.get(cursor_col).map_or(1, |cell| cell.width.max(1)) as f32 * char_width, row_rect.height()),
+                );
+                ui.painter().rect_filled(cursor_rect, 0.0, self.header.get_cursor_color_imm().gamma_multiply(0.6));
+            }
+            row_rects.push(row_rect);
+        }
+        row_rects
+    }
+
+    // Dragging over the output selects it, a click clears the selection, a double
+    // click selects a word and a triple click the line; Shift+click extends the
+    // selection and Alt+drag selects a block. Lines are monospace, so the pointer maps to a column by the character
+    // width; soft-wrapped lines are taken to wrap every (rect width / character width)
+    // columns.
+    fn update_selection(&mut self, ui: &mut egui::Ui, area: egui::Rect, line_rects: &[(usize, egui::Rect)], token_action: bool) {
+        let font_id = egui::FontId::monospace(self.text_size);
+        let (char_width, row_height) = ui.fonts_mut(|f| (f.glyph_width(&font_id, 'M'), f.row_height(&font_id)));
+        let columns_in = |rect: egui::Rect| ((rect.width() / char_width).round() as usize).max(1);
+
+        // The boundary between cells nearest the pointer, or with `under` the cell it's on
+        let cell_at = |pos: egui::Pos2, under: bool| -> Option<(usize, usize)> {
+            let distance = |rect: &egui::Rect| (rect.top() - pos.y).max(pos.y - rect.bottom()).max(0.0);
+            let &(line, rect) = line_rects.iter().min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))?;
+            let columns = columns_in(rect);
+            let row = ((pos.y.min(rect.bottom() - 1.0) - rect.top()) / row_height).max(0.0) as usize;
+            let col = (pos.x - rect.left()) / char_width;
+            let col = if under { col.floor() } else { col.round() }.clamp(0.0, columns as f32) as usize;
+            Some((line, row * columns + col))
+        };
+
+        // Leave the minimap strip and the scroll bar alone
+        let text_area = area.with_max_x(area.right() - 16.0);
+        let (pressed, down, pos, modifiers) = ui.input(|i| (i.pointer.primary_pressed(), i.pointer.primary_down(), i.pointer.interact_pos(), i.modifiers));
+        if let Some(pos) = pos {
+            if pressed && ui.rect_contains_pointer(text_area) {
+                match (&mut self.selection, cell_at(pos, false)) {
+                    (Some(selection), Some(head)) if modifiers.shift => selection.head = head,
+                    // Alt+drag selects a block
+                    (selection, at) if modifiers.alt => *selection = at.map(Selection::block),
+                    (selection, at) => *selection = at.map(Selection::new),
+                }
+                self.selecting = self.selection.is_some();
+            } else if self.selecting
+                && down
+                && let Some(head) = cell_at(pos, false)
+                && let Some(selection) = &mut self.selection
+            {
+                selection.head = head;
+            }
+        }
+        // Middle-click pastes the clipboard, as Ctrl+Shift+V does
+        if ui.input(|i| i.pointer.button_pressed(egui::PointerButton::Middle))
+            && ui.rect_contains_pointer(text_area)
+        {
+            self.paste_requested = true;
+            ui.ctx().send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+        }
+        if self.selecting && !down {
+            self.selecting = false;
+            if self.selection.is_some_and(|selection| selection.is_empty()) {
+                self.selection = None;
+            }
+        }
+        let (double, triple) = ui.input(|i| (
+            i.pointer.button_double_clicked(egui::PointerButton::Primary),
+            i.pointer.button_triple_clicked(egui::PointerButton::Primary),
+        ));
+        // A double-click that ran a token's action doesn't select the word as well
+        if (triple || (double && !token_action))
+            && ui.rect_contains_pointer(text_area)
+            && let Some((line, col)) = pos.and_then(|pos| cell_at(pos, true))
+        {
+            let cells = if self.raw_mode { self.grid.rows().get(line).map(|row| Cow::Borrowed(row.as_slice())) } else { self.grid.main_line(line) };
+            let config = &self.config;
+            self.selection = Some(if triple {
+                Selection::line(line)
+            } else {
+                Selection::word(line, cells.as_deref().unwrap_or_default(), col, |c| config.is_word_char(c))
+            });
+        }
+
+    }
+
+    // Mouse reporting ====================================
+    // When a fullscreen program (tmux, htop, vim) turns on mouse tracking, clicks, drags
+    // and the wheel over the grid go to it as xterm mouse reports instead of selecting.
+    // Holding Shift selects as usual. Returns true if the mouse went to the program.
+    fn report_mouse(&mut self, ui: &egui::Ui, area: egui::Rect, line_rects: &[(usize, egui::Rect)]) -> bool {
+        let tracking = self.grid.mouse_tracking;
+        if tracking == MouseTracking::Off || !self.raw_mode || ui.input(|i| i.modifiers.shift) {
+            self.mouse_button = None;
+            self.mouse_cell = None;
+            return false;
+        }
+
+        let font_id = egui::FontId::monospace(self.text_size);
+        let (char_width, row_height) = ui.fonts_mut(|f| (f.glyph_width(&font_id, 'M'), f.row_height(&font_id)));
+        let cols = self.grid.cols();
+        let cell_at = |pos: egui::Pos2| -> Option<(usize, usize)> {
+            let &(row, rect) = line_rects.iter().find(|(_, rect)| rect.y_range().contains(pos.y))?;
+            let col = ((pos.x - rect.left()) / char_width).floor().clamp(0.0, (cols - 1) as f32) as usize;
+            Some((col, row))
+        };
+
+        // Leave the scroll bar alone
+        let text_area = area.with_max_x(area.right() - 16.0);
+        let hovered = ui.rect_contains_pointer(text_area);
+        let sgr = self.grid.sgr_mouse;
+        let mut reports: Vec<u8> = Vec::new();
+        let events = ui.input(|i| i.events.clone());
+        for event in events {
+            match event {
+                egui::Event::PointerButton { pos, button, pressed, modifiers } => {
+                    let code = match button {
+                        egui::PointerButton::Primary => 0,
+                        egui::PointerButton::Middle => 1,
+                        egui::PointerButton::Secondary => 2,
+                        _ => continue,
+                    };
+                    // Releases go to the program even outside the pane, so it doesn't think a button is stuck
+                    if pressed && !hovered || !pressed && self.mouse_button != Some(code) {
+                        continue;
+                    }
+                    let Some(cell) = cell_at(pos).or(self.mouse_cell) else {
+                        continue;
+                    };
+                    self.mouse_button = pressed.then_some(code);
+                    self.mouse_cell = Some(cell);
+                    encode_mouse(&mut reports, code + mouse_modifiers(modifiers), cell, !pressed, sgr);
+                }
+                egui::Event::PointerMoved(pos) => {
+                    let reported = match self.mouse_button {
+                        Some(_) => tracking != MouseTracking::Click,
+                        None => tracking == MouseTracking::Motion && hovered,
+                    };
+                    if let Some(cell) = cell_at(pos)
+                        && reported
+                        && self.mouse_cell != Some(cell)
+                    {
+                        self.mouse_cell = Some(cell);
+                        // 32 marks motion; 3 is "no button"
+                        let code = 32 + self.mouse_button.unwrap_or(3) + ui.input(|i| mouse_modifiers(i.modifiers));
+                        encode_mouse(&mut reports, code, cell, false, sgr);
+                    }
+                }
+                egui::Event::MouseWheel { unit, delta, modifiers } if hovered => {
+                    self.wheel_delta += match unit {
+                        egui::MouseWheelUnit::Point => delta.y / row_height,
+                        egui::MouseWheelUnit::Line => delta.y,
+                        egui::MouseWheelUnit::Page => delta.y * self.grid.rows().len() as f32,
+                    };
+                    let Some(cell) = ui.input(|i| i.pointer.hover_pos()).and_then(cell_at) else {
+                        continue;
+                    };
+                    // 64 is wheel up, 65 wheel down; one report per row scrolled
+                    while self.wheel_delta.abs() >= 1.0 {
+                        let code = if self.wheel_delta > 0.0 { 64 } else { 65 };
+                        self.wheel_delta -= self.wheel_delta.signum();
+                        encode_mouse(&mut reports, code + mouse_modifiers(modifiers), cell, false, sgr);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !reports.is_empty() {
+            self.write_bytes_to_pty(&reports);
+        }
+        true
+    }
+
+    // Search matches (the current one stronger) and the selection, over the output
+    fn paint_highlights(&self, ui: &egui::Ui, area: egui::Rect, line_rects: &[(usize, egui::Rect)]) {
+        let font_id = egui::FontId::monospace(self.text_size);
+        let (char_width, row_height) = ui.fonts_mut(|f| (f.glyph_width(&font_id, 'M'), f.row_height(&font_id)));
+        let painter = ui.painter_at(area);
+        let paint = |rect: egui::Rect, from: usize, to: usize, color: egui::Color32| {
+            let columns = ((rect.width() / char_width).round() as usize).max(1);
+            let rows = ((rect.height() / row_height).round() as usize).max(1);
+            let to = to.min(columns * rows);
+            for row in from / columns..rows {
+                let start = from.max(row * columns) - row * columns;
+                let end = to.min((row + 1) * columns).saturating_sub(row * columns);
+                if start >= end {
+                    break;
+                }
+                let top = rect.top() + row as f32 * row_height;
+                painter.rect_filled(
+                    egui::Rect::from_min_max(
+                        egui::pos2(rect.left() + start as f32 * char_width, top),
+                        egui::pos2(rect.left() + end as f32 * char_width, top + row_height),
+                    ),
+                    0.0,
+                    color,
+                );
+            }
+        };
+
+        let current = self.search.current_match();
+        for m in &self.search.matches {
+            if let Some(&(_, rect)) = line_rects.iter().find(|(line, _)| *line == m.line) {
+                let strength = if Some(*m) == current { 0.7 } else { 0.3 };
+                paint(rect, m.start, m.end, self.header.color_set.warning.gamma_multiply(strength));
+            }
+        }
+        if let Some(selection) = self.selection {
+            for &(line, rect) in line_rects {
+                if let Some((from, to)) = selection.columns(line) {
+                    paint(rect, from, to, self.header.get_primary_color_imm().gamma_multiply(0.4));
+                }
+            }
+        }
+        if let Some(copy_mode) = &self.copy_mode
+            && let Some(&(_, rect)) = line_rects.iter().find(|(line, _)| *line == copy_mode.cursor.0)
+        {
+            let (_, col) = copy_mode.cursor;
+            paint(rect, col, col + 1, self.header.get_primary_color_imm().gamma_multiply(0.8));
+        }
+    }
+
+    // Text of the selection, with line breaks between lines
+    fn selected_text(&self) -> Option<String> {
+        let selection = self.selection?;
+        Some(if self.raw_mode {
+            selection.text(|index| self.grid.rows().get(index).map(|row| Cow::Borrowed(row.as_slice())))
+        } else {
+            selection.text(|index| self.grid.main_line(index))
+        })
+    }
+
+    // The selection with the escape sequences or HTML that color it, like an export
+    fn selected_export(&self, format: ExportFormat) -> Option<String> {
+        let selection = self.selection?;
+        let lines = if self.raw_mode {
+            selection.cells(|index| self.grid.rows().get(index).map(|row| Cow::Borrowed(row.as_slice())))
+        } else {
+            selection.cells(|index| self.grid.main_line(index))
+        };
+        let palette = self.header.palette();
+        let colors = ExportColors {
+            palette: &palette,
+            foreground: self.header.get_terminal_text_color_imm(),
+            background: self.header.get_terminal_bg_color_imm(),
+        };
+        let text = export(lines.into_iter(), format, &colors);
+        // Like a plain copy, without a newline after the last line
+        Some(match format {
+            ExportFormat::Html => text,
+            _ => text.strip_suffix('\n').unwrap_or(&text).to_string(),
+        })
+    }
+
+    // Right-click on the output: the selection copied as plain text, or with its colors
+    // to paste into an HTML document or another terminal (the clipboard only takes text,
+    // so the HTML goes in as its source), and the pane's other actions
+    fn context_menu(&mut self, ui: &mut egui::Ui, area: egui::Rect) -> Option<SplitDirection> {
+        let text_area = area.with_max_x(area.right() - 16.0);
+        let opened = ui.input(|i| i.pointer.secondary_clicked()) && ui.rect_contains_pointer(text_area);
+        let has_selection = self.selection.is_some_and(|selection| !selection.is_empty());
+        let mut picked = None;
+        egui::Popup::new(ui.id().with("context_menu"), ui.ctx().clone(), egui::PopupAnchor::PointerFixed, ui.layer_id())
+            .kind(egui::PopupKind::Menu)
+            .layout(egui::Layout::top_down_justified(egui::Align::Min))
+            .style(egui::containers::menu::menu_style)
+            .open_memory(opened.then_some(egui::SetOpenCommand::Bool(true)))
+            .show(|ui| {
+                ui.add_enabled_ui(has_selection, |ui| {
+                    for (label, format) in [
+                        ("Copy", ExportFormat::Text),
+                        ("Copy as HTML", ExportFormat::Html),
+                        ("Copy with ANSI", ExportFormat::Ansi),
+                    ] {
+                        if ui.button(label).clicked() {
+                            picked = Some(MenuAction::Copy(format));
+                        }
+                    }
+                });
+                if ui.button("Paste").clicked() {
+                    picked = Some(MenuAction::Paste);
+                }
+                if ui.button("Select All").clicked() {
+                    picked = Some(MenuAction::SelectAll);
+                }
+                ui.separator();
+                if ui.button("Search").clicked() {
+                    picked = Some(MenuAction::Search);
+                }
+                if ui.button("Clear Scrollback").clicked() {
+                    picked = Some(MenuAction::ClearScrollback);
+                }
+                // To the path set in the header menu
+                ui.menu_button("Export", |ui| {
+                    for (format, label) in [
+                        (ExportFormat::Text, "Plain text"),
+                        (ExportFormat::Ansi, "Text with colors (ANSI)"),
+                        (ExportFormat::Html, "HTML"),
+                    ] {
+                        if ui.button(label).clicked() {
+                            picked = Some(MenuAction::Export(format));
+                        }
+                    }
+                });
+                ui.separator();
+                if ui.button("Split Right").clicked() {
+                    picked = Some(MenuAction::Split(SplitDirection::SideBySide));
+                }
+                if ui.button("Split Down").clicked() {
+                    picked = Some(MenuAction::Split(SplitDirection::Stacked));
+                }
+            });
+
+        match picked? {
+            MenuAction::Copy(format) => {
+                let text = match format {
+                    ExportFormat::Text => self.selected_text(),
+                    format => self.selected_export(format),
+                };
+                if let Some(text) = text {
+                    ui.ctx().copy_text(text);
+                }
+            }
+            MenuAction::Paste => {
+                self.paste_requested = true;
+                ui.ctx().send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+            }
+            MenuAction::SelectAll => {
+                let lines = if self.raw_mode {
+                    self.grid.rows().len()
+                } else {
+                    self.grid.scrollback().len() + self.grid.screen_lines().len()
+                };
+                self.selection = Some(Selection { anchor: (0, 0), head: (lines.saturating_sub(1), usize::MAX), block: false });
+            }
+            MenuAction::Search => self.search.open(),
+            MenuAction::ClearScrollback => self.clear_scrollback(),
+            MenuAction::Export(format) => self.export_scrollback(format),
+            MenuAction::Split(direction) => return Some(direction),
+        }
+        None
+    }
+
+    // A double-width character in exactly two cells, whatever its glyph's width in
+    // the font, so the columns after it line up
+    fn wide_label(&self, ui: &mut egui::Ui, text: egui::RichText) {
+        let font_id = egui::FontId::monospace(self.text_size);
+        let (char_width, row_height) = ui.fonts_mut(|f| (f.glyph_width(&font_id, 'M'), f.row_height(&font_id)));
+        ui.add_sized(egui::vec2(2.0 * char_width, row_height), egui::Label::new(text));
+    }
+
+    // Render one styled segment, underlining URLs and paths and previewing them on hover.
+    // Returns the token action to run if a configured token was double-clicked.
+    fn render_segment(&self, ui: &mut egui::Ui, seg: &TerminalOutput) -> Option<TokenAction> {
+        if seg.wide {
+            self.wide_label(ui, seg.rich_text(&seg.text, self.text_size));
+            return None;
+        }
+        let mut spans: Vec<(usize, usize, Span)> = find_links(&seg.text).into_iter()
+            .map(|link| (link.start, link.end, Span::Link(link.target)))
+            .collect();
+        spans.extend(find_token_actions(&seg.text, &self.config.token_actions).into_iter()
+            .map(|(start, end, rule)| (start, end, Span::Token(rule))));
+        spans.sort_by_key(|&(start, ..)| start);
+
+        let mut action = None;
+        let mut pos = 0;
+        for (start, end, span) in spans {
+            if start < pos {
+                continue;
+            }
+            if start > pos {
+                ui.label(seg.rich_text(&seg.text[pos..start], self.text_size));
+            }
+            let text = &seg.text[start..end];
+            let response = ui.add(egui::Label::new(seg.rich_text(text, self.text_size).underline()).sense(egui::Sense::click()));
+            action = self.span_response(ui, response, text, &span).or(action);
+            pos = end;
+        }
+        if pos < seg.text.len() {
+            ui.label(seg.rich_text(&seg.text[pos..], self.text_size));
+        }
+        action
+    }
+
+    // A whole line from its cached layout. Links and tokens are found under the pointer
+    // in the galley rather than being widgets of their own.
+    fn render_line(&self, ui: &mut egui::Ui, layout: &LineLayout) -> Option<TokenAction> {
+        let sense = if layout.spans.is_empty() { egui::Sense::hover() } else { egui::Sense::click() };
+        let response = ui.add(egui::Label::new(Arc::clone(&layout.galley)).sense(sense));
+        let line_span = response.hover_pos().and_then(|pos| layout.span_at(pos - response.rect.min))?;
+        let span = match &line_span.kind {
+            SpanKind::Link(target) => Span::Link(target.clone()),
+            SpanKind::Token(rule) => Span::Token(self.config.token_actions.get(*rule)?),
+        };
+        self.span_response(ui, response, &line_span.text, &span)
+    }
+
+    // Hovering a link previews it and Ctrl+click opens it, so a plain click can still start
+    // a selection; a token action runs on a double click
+    fn span_response(&self, ui: &egui::Ui, response: egui::Response, text: &str, span: &Span) -> Option<TokenAction> {
+        match span {
+            Span::Link(target) => {
+                let ctrl = ui.input(|i| i.modifiers.ctrl);
+                let mut response = response.on_hover_ui(|ui| self.link_tooltip(ui, target));
+                if ctrl {
+                    response = response.on_hover_cursor(egui::CursorIcon::PointingHand);
+                }
+                if response.clicked() && ctrl {
+                    let url = match target {
+                        LinkTarget::Url(url) => url.clone(),
+                        LinkTarget::Path(path) => resolve_path(path, self.shell_cwd().as_deref()).display().to_string(),
+                    };
+                    return Some(TokenAction { kind: TokenActionKind::Open, token: text.to_string(), url: Some(url) });
+                }
+                None
+            }
+            Span::Token(rule) => {
+                let response = response
+                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                    .on_hover_text(rule.describe(text));
+                response.double_clicked().then(|| rule.action_for(text))
+            }
+        }
+    }
+
+    fn run_token_action(&mut self, ctx: &egui::Context, action: TokenAction) {
+        match action.kind {
+            TokenActionKind::Copy => ctx.copy_text(action.token),
+            TokenActionKind::Open => match action.url {
+                Some(url) => open_url(&url),
+                None => eprintln!("Warning: Token action \"open\" needs a url"),
+            },
+            TokenActionKind::Send => self.outgoing_text = Some(action.token),
+        }
+    }
+
+    // Text a token action wants typed into the active pane
+    pub fn take_outgoing_text(&mut self) -> Option<String> {
+        self.outgoing_text.take()
+    }
+
+    // Paste into the program, wrapped in bracketed paste markers if it asked for them so
+    // shells and editors take it as text instead of typed keys
+    fn paste(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        // Typed here, it's more input to edit, newlines and all; only Enter sends it
+        if !self.keys_to_program() {
+            self.command_buffer.insert(&text.replace("\r\n", "\n").replace('\r', "\n"));
+            return;
+        }
+        let data = if self.grid.bracketed_paste {
+            bracketed(text)
+        } else {
+            text.replace("\r\n", "\r").replace('\n', "\r")
+        };
+        self.write_to_pty(&data);
+    }
+
+    // Run a command in the shell; it queues in the PTY until the shell reads it
+    pub fn run_command(&mut self, command: &str) {
+        self.write_to_pty(&format!("{}\n", command));
+    }
+
+    // Type text into the pane as if it came from the keyboard
+    pub fn send_text(&mut self, text: &str) {
+        if self.keys_to_program() {
+            self.write_to_pty(text);
+        } else {
+            self.command_buffer.insert(text);
+        }
+    }
+
+    fn link_tooltip(&self, ui: &mut egui::Ui, target: &LinkTarget) {
+        match target {
+            LinkTarget::Url(url) => {
+                ui.label(egui::RichText::new(url).monospace());
+            }
+            LinkTarget::Path(path) => {
+                let resolved = resolve_path(path, self.shell_cwd().as_deref());
+                ui.label(egui::RichText::new(resolved.display().to_string()).monospace());
+                ui.label(describe_path(&resolved));
+            }
+        }
+        ui.label(egui::RichText::new("Ctrl+click to open").weak());
+    }
+
+    // Current working directory of the shell, read from /proc
+    fn shell_cwd(&self) -> Option<std::path::PathBuf> {
+        let pid = self.pty.as_ref()?.pid();
+        std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+    }
+
     fn handle_keyboard_input(&mut self, ui: &mut egui::Ui) {
+        if self.copy_mode.is_some() {
+            self.handle_copy_mode_input(ui);
+            return;
+        }
+        let mut copy_selection = false;
+        let mut request_paste = false;
+        let mut restart = false;
+        let mut enter_copy_mode = false;
+        let mut pasted: Option<String> = None;
+        let keys_to_program = self.keys_to_program();
         ui.input(|i| {
             for event in &i.events {
+                // Ctrl+C, Ctrl+X and Ctrl+V (with or without Shift) arrive as clipboard
+                // events rather than keys; look them up as chords all the same
+                let chord = match event {
+                    egui::Event::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+                    egui::Event::Copy => Some((egui::Key::C, i.modifiers)),
+                    egui::Event::Cut => Some((egui::Key::X, i.modifiers)),
+                    egui::Event::Paste(_) => Some((egui::Key::V, i.modifiers)),
+                    _ => None,
+                };
+                if let egui::Event::Paste(text) = event
+                    && std::mem::take(&mut self.paste_requested)
+                {
+                    pasted = Some(text.clone());
+                    continue;
+                }
+                if let Some((key, modifiers)) = chord {
+                    let bindings = &self.config.keybindings;
+                    if bindings.copy.matches(key, modifiers) {
+                        copy_selection = true;
+                        continue;
+                    }
+                    if bindings.paste.matches(key, modifiers) {
+                        match event {
+                            egui::Event::Paste(text) => pasted = Some(text.clone()),
+                            // Other chords have to ask for the clipboard
+                            _ => request_paste = true,
+                        }
+                        continue;
+                    }
+                    if bindings.search.matches(key, modifiers) {
+                        self.search.open();
+                        continue;
+                    }
+                    // Shift+[ may come through as {, so the key's position counts too
+                    let physical_key = match event {
+                        egui::Event::Key { physical_key: Some(physical_key), .. } => *physical_key,
+                        _ => key,
+                    };
+                    if bindings.copy_mode.matches(key, modifiers) || bindings.copy_mode.matches(physical_key, modifiers) {
+                        enter_copy_mode = true;
+                        continue;
+                    }
+                    if bindings.restart_shell.matches(key, modifiers) {
+                        restart = true;
+                        continue;
+                    }
+                    if !self.raw_mode && bindings.previous_prompt.matches(key, modifiers) {
+                        self.jump_to_prompt(false);
+                        continue;
+                    }
+                    if !self.raw_mode && bindings.next_prompt.matches(key, modifiers) {
+                        self.jump_to_prompt(true);
+                        continue;
+                    }
+                    let zoom = [
+                        (bindings.zoom_in, self.text_size + 1.0),
+                        (bindings.zoom_out, self.text_size - 1.0),
+                        (bindings.zoom_reset, self.font_size_override.unwrap_or(self.config.font_size)),
+                    ].into_iter().find(|(chord, _)| chord.matches(key, modifiers));
+                    if let Some((_, size)) = zoom {
+                        self.set_text_size(size);
+                        continue;
+                    }
+                    // Fullscreen programs keep the F-keys for themselves
+                    if bindings.rename.matches(key, modifiers) && !self.raw_mode {
+                        self.header.start_editing_title();
+                        continue;
+                    }
+                }
+
                 match event {
+                    // Programs using the kitty keyboard protocol can ask for releases too
+                    egui::Event::Key { key, pressed: false, modifiers, .. } if keys_to_program => {
+                        self.send_key(*key, *modifiers, KeyEventType::Release);
+                    }
+                    // Unbound clipboard chords go to the program as control characters
+                    egui::Event::Copy => {
+                        self.handed_over = None;
+                        self.send_key(egui::Key::C, i.modifiers, KeyEventType::Press);
+                        if !keys_to_program {
+                            self.command_buffer.clear();
+                            self.history_search = None;
+                        }
+                    }
+                    egui::Event::Cut => self.send_key(egui::Key::X, i.modifiers, KeyEventType::Press),
+                    // Quoted insert
+                    egui::Event::Paste(_) if keys_to_program => self.send_key(egui::Key::V, i.modifiers, KeyEventType::Press),
                     egui::Event::Text(text) => {
-                        if self.raw_mode {
-                            // In raw mode, send text directly to PTY
-                            if let Some(pty) = &mut self.pty {
-                                if let Ok(mut stream) = pty.get_raw_handle() {
-                                    let _ = write!(stream, "{}", text);
-                                    let _ = stream.flush();
-                                }
+                        if keys_to_program {
+                            // Send text directly to PTY. With the kitty keyboard
+                            // protocol, Alt combos (and all typing, if asked) go from the key events.
+                            let flags = self.grid.keyboard_flags();
+                            let from_key = flags != 0
+                                && (i.modifiers.alt || (flags & KITTY_ALL_KEYS != 0 && text.chars().all(|c| c.is_ascii_graphic() || c == ' ')));
+                            if !from_key {
+                                self.write_to_pty(&encode_text(text, i.modifiers));
+                            }
+                        } else if i.modifiers.alt {
+                            // Alt+B and Alt+F move by words within the line being typed;
+                            // other Meta combos (Alt+., Alt+D...) are the shell's
+                            let config = &self.config;
+                            match text.as_str() {
+                                "b" if !self.command_buffer.is_empty() => self.command_buffer.move_word_left(|c| config.is_word_char(c)),
+                                "f" if !self.command_buffer.is_empty() => self.command_buffer.move_word_right(|c| config.is_word_char(c)),
+                                _ => self.write_to_pty(&encode_text(text, i.modifiers)),
                             }
                         } else {
-                            // In normal mode, add to command buffer
-                            self.command_buffer.push_str(text);
+                            self.insert_text(text);
                         }
                     }
-                    egui::Event::Key { key, pressed: true, modifiers, .. } => {
-                        if self.raw_mode {
-                            // In raw mode, send all keys directly to PTY
-                            if let Some(pty) = &mut self.pty {
-                                if let Ok(mut stream) = pty.get_raw_handle() {
-                                    let key_seq = match key {
-                                        egui::Key::Enter => "\r",
-                                        egui::Key::Backspace => "\x7f",
-                                        egui::Key::Tab => "\t",
-                                        egui::Key::Escape => "\x1b",
-                                        egui::Key::ArrowUp => "\x1b[A",
-                                        egui::Key::ArrowDown => "\x1b[B",
-                                        egui::Key::ArrowRight => "\x1b[C",
-                                        egui::Key::ArrowLeft => "\x1b[D",
-                                        egui::Key::Home => "\x1b[H",
-                                        egui::Key::End => "\x1b[F",
-                                        egui::Key::PageUp => "\x1b[5~",
-                                        egui::Key::PageDown => "\x1b[6~",
-                                        egui::Key::Delete => "\x1b[3~",
-                                        egui::Key::C if modifiers.ctrl => "\x03",
-                                        egui::Key::D if modifiers.ctrl => "\x04",
-                                        egui::Key::Z if modifiers.ctrl => "\x1a",
-                                        egui::Key::L if modifiers.ctrl => "\x0c",
-                                        _ => "",
-                                    };
-                                    
-                                    if !key_seq.is_empty() {
-                                        let _ = write!(stream, "{}", key_seq);
-                                        let _ = stream.flush();
-                                    }
-                                }
+                    egui::Event::Ime(ime) => match ime {
+                        egui::ImeEvent::Preedit(text) => self.ime_preedit = text.clone(),
+                        egui::ImeEvent::Commit(text) => {
+                            self.ime_preedit.clear();
+                            self.insert_text(text);
+                        }
+                        egui::ImeEvent::Enabled | egui::ImeEvent::Disabled => self.ime_preedit.clear(),
+                    },
+                    // Keys pressed while composing are the input method's (picking a candidate)
+                    egui::Event::Key { .. } if !self.ime_preedit.is_empty() => {}
+                    egui::Event::Key { key, pressed: true, modifiers, repeat, .. } => {
+                        let event_type = if *repeat { KeyEventType::Repeat } else { KeyEventType::Press };
+                        if keys_to_program {
+                            if *key == egui::Key::Enter && self.handed_over.is_some() {
+                                self.submit_handed_over_line();
                             }
+                            // Send all keys directly to PTY
+                            self.send_key(*key, *modifiers, event_type);
                         } else {
+                            if self.handle_history_search_key(*key, *modifiers) {
+                                continue;
+                            }
                             // In normal mode, handle keys for command buffer
                             match key {
                                 egui::Key::Enter => {
                                     // Send command to PTY
-                                    if let Some(pty) = &mut self.pty {
-                                        if let Ok(mut stream) = pty.get_raw_handle() {
-                                            let _ = write!(stream, "{}\n", self.command_buffer);
-                                            let _ = stream.flush();
-                                        }
+                                    let command = self.command_buffer.take();
+                                    self.track_command(&command, self.grid.cursor_line());
+                                    // A pasted block goes as a paste if the shell takes those,
+                                    // so its lines are edited together rather than run one by one
+                                    if command.contains('\n') && self.grid.bracketed_paste {
+                                        self.write_to_pty(&format!("{}\n", bracketed(&command)));
+                                    } else {
+                                        self.write_to_pty(&format!("{}\n", command));
                                     }
-                                    self.command_buffer.clear();
+                                }
+                                egui::Key::Tab if self.shell_at_prompt() => self.hand_over_line(*modifiers),
+                                egui::Key::Backspace if modifiers.ctrl => {
+                                    self.delete_word_backward();
+                                }
+                                egui::Key::W if modifiers.ctrl => {
+                                    self.delete_word_backward();
                                 }
                                 egui::Key::Backspace => {
-                                    self.command_buffer.pop();
+                                    self.command_buffer.backspace();
+                                }
+                                // Editing keys work within the line being typed. With nothing
+                                // typed they go to the shell (or program) as before.
+                                egui::Key::Delete if !self.command_buffer.is_empty() => {
+                                    self.command_buffer.delete();
+                                }
+                                egui::Key::ArrowLeft if !self.command_buffer.is_empty() => {
+                                    if modifiers.ctrl {
+                                        let config = &self.config;
+                                        self.command_buffer.move_word_left(|c| config.is_word_char(c));
+                                    } else {
+                                        self.command_buffer.move_left();
+                                    }
                                 }
+                                egui::Key::ArrowRight if !self.command_buffer.is_empty() => {
+                                    if modifiers.ctrl {
+                                        let config = &self.config;
+                                        self.command_buffer.move_word_right(|c| config.is_word_char(c));
+                                    } else {
+                                        self.command_buffer.move_right();
+                                    }
+                                }
+                                egui::Key::Home if !self.command_buffer.is_empty() => self.command_buffer.move_home(),
+                                egui::Key::End if !self.command_buffer.is_empty() => self.command_buffer.move_end(),
+                                egui::Key::A if modifiers.ctrl => self.command_buffer.move_home(),
+                                egui::Key::E if modifiers.ctrl => self.command_buffer.move_end(),
+                                egui::Key::K if modifiers.ctrl && !self.command_buffer.is_empty() => self.command_buffer.delete_to_end(),
+                                egui::Key::U if modifiers.ctrl && !self.command_buffer.is_empty() => self.command_buffer.delete_to_start(),
                                 egui::Key::C if modifiers.ctrl => {
                                     // Send Ctrl+C
-                                    if let Some(pty) = &mut self.pty {
-                                        if let Ok(mut stream) = pty.get_raw_handle() {
-                                            let _ = write!(stream, "\x03");
-                                            let _ = stream.flush();
-                                        }
-                                    }
+                                    self.write_to_pty("\x03");
                                     self.command_buffer.clear();
                                 }
-                                egui::Key::D if modifiers.ctrl => {
-                                    // Send Ctrl+D
-                                    if let Some(pty) = &mut self.pty {
-                                        if let Ok(mut stream) = pty.get_raw_handle() {
-                                            let _ = write!(stream, "\x04");
-                                            let _ = stream.flush();
-                                        }
-                                    }
+                                egui::Key::R if modifiers.ctrl => {
+                                    self.start_history_search();
                                 }
-                                egui::Key::L if modifiers.ctrl => {
-                                    // Send Ctrl+L (clear screen)
-                                    if let Some(pty) = &mut self.pty {
-                                        if let Ok(mut stream) = pty.get_raw_handle() {
-                                            let _ = write!(stream, "\x0c");
-                                            let _ = stream.flush();
-                                        }
+                                // At the prompt the arrows step through this pane's history;
+                                // a running program gets them
+                                egui::Key::ArrowUp if self.shell_at_prompt() => {
+                                    if let Some(entry) = self.history.previous(self.command_buffer.as_str()) {
+                                        self.command_buffer.set(entry);
                                     }
                                 }
-                                // Send arrow keys and other special keys to PTY
-                                _ => {
-                                    if let Some(pty) = &mut self.pty {
-                                        if let Ok(mut stream) = pty.get_raw_handle() {
-                                            let key_seq = match key {
-                                                egui::Key::Tab => "\t",
-                                                egui::Key::Escape => "\x1b",
-                                                egui::Key::ArrowUp => "\x1b[A",
-                                                egui::Key::ArrowDown => "\x1b[B",
-                                                egui::Key::ArrowRight => "\x1b[C",
-                                                egui::Key::ArrowLeft => "\x1b[D",
-                                                egui::Key::Home => "\x1b[H",
-                                                egui::Key::End => "\x1b[F",
-                                                egui::Key::PageUp => "\x1b[5~",
-                                                egui::Key::PageDown => "\x1b[6~",
-                                                egui::Key::Delete => "\x1b[3~",
-                                                _ => "",
-                                            };
-                                            
-                                            if !key_seq.is_empty() {
-                                                let _ = write!(stream, "{}", key_seq);
-                                                let _ = stream.flush();
-                                            }
-                                        }
+                                egui::Key::ArrowDown if self.shell_at_prompt() => {
+                                    if let Some(entry) = self.history.next() {
+                                        self.command_buffer.set(entry);
                                     }
                                 }
+                                // Send arrow keys, other special keys and the rest of the
+                                // control characters (Ctrl+D, Ctrl+L, Ctrl+Z...) to PTY
+                                _ => self.send_key(*key, *modifiers, event_type),
                             }
                         }
                     }
@@ -467,20 +2479,317 @@ impl Terminal {
                 }
             }
         });
+
+        if request_paste {
+            self.paste_requested = true;
+            ui.ctx().send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+        }
+        if copy_selection && let Some(text) = self.selected_text() {
+            ui.ctx().copy_text(text);
+        }
+        if let Some(text) = pasted {
+            self.paste(&text);
+        }
+        if restart {
+            self.restart_shell();
+        }
+        if enter_copy_mode {
+            self.enter_copy_mode();
+        }
+    }
+
+    // Copy mode ==========================================
+    // Keys move a cursor over the output instead of going to the shell; see copy_mode.rs
+
+    fn enter_copy_mode(&mut self) {
+        let (row, col) = self.grid.cursor();
+        let line = if self.raw_mode { row } else { self.grid.cursor_line() };
+        self.copy_mode = Some(CopyMode::new((line, col)));
+        self.selection = None;
+        self.selecting = false;
+    }
+
+    fn exit_copy_mode(&mut self) {
+        self.copy_mode = None;
+        self.selection = None;
+    }
+
+    fn handle_copy_mode_input(&mut self, ui: &mut egui::Ui) {
+        let events = ui.input(|i| i.events.clone());
+        let page = self.grid.rows().len().saturating_sub(1).max(1);
+        let exit_chord = self.config.keybindings.copy_mode;
+        let mut actions = Vec::new();
+        {
+            let Some(copy_mode) = &mut self.copy_mode else {
+                return;
+            };
+            let main_lines: Vec<Cow<[Cell]>> = if self.raw_mode { Vec::new() } else { self.grid.main_lines().collect() };
+            let lines: Vec<&[Cell]> = if self.raw_mode {
+                self.grid.rows().iter().map(Vec::as_slice).collect()
+            } else {
+                main_lines.iter().map(AsRef::as_ref).collect()
+            };
+            let config = &self.config;
+            for event in &events {
+                actions.push(match event {
+                    egui::Event::Key { key, physical_key, pressed: true, modifiers, .. } => {
+                        if exit_chord.matches(*key, *modifiers) || physical_key.is_some_and(|key| exit_chord.matches(key, *modifiers)) {
+                            CopyModeAction::Exit
+                        } else {
+                            copy_mode.key(*key, &lines, page)
+                        }
+                    }
+                    egui::Event::Text(text) => copy_mode.text(text, &lines, |c| config.is_word_char(c)),
+                    egui::Event::Copy => CopyModeAction::Copy,
+                    _ => continue,
+                });
+            }
+        }
+        if actions.is_empty() {
+            return;
+        }
+
+        for action in actions {
+            let Some(copy_mode) = &mut self.copy_mode else {
+                break;
+            };
+            let found = match action {
+                CopyModeAction::None => None,
+                CopyModeAction::Search => {
+                    self.search.open();
+                    None
+                }
+                CopyModeAction::NextMatch => self.search.next(),
+                CopyModeAction::PreviousMatch => self.search.previous(),
+                CopyModeAction::Copy => {
+                    if let Some(text) = self.selected_text() {
+                        ui.ctx().copy_text(text);
+                    }
+                    self.exit_copy_mode();
+                    continue;
+                }
+                CopyModeAction::Exit => {
+                    self.exit_copy_mode();
+                    continue;
+                }
+            };
+            if let Some(found) = found {
+                copy_mode.move_to((found.line, found.start));
+            }
+        }
+
+        if let Some(copy_mode) = &self.copy_mode {
+            self.selection = copy_mode.selection();
+            self.scroll_to_line = Some((copy_mode.cursor.0, None));
+        }
+    }
+
+    fn render_copy_mode_bar(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add_space(8.0);
+            ui.label(egui::RichText::new("COPY").monospace().strong().color(self.header.get_primary_color_imm()));
+            ui.label(egui::RichText::new("hjkl move, v select, y copy, / search, n/N next/previous match, q quit").weak());
+        });
+    }
+
+    // Play/pause, a seek bar with the time, and the speed
+    fn render_playback_bar(&mut self, ui: &mut egui::Ui) {
+        let Some(playback) = &mut self.playback else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.add_space(8.0);
+            let icon = if playback.is_playing() { "⏸" } else { "▶" };
+            if ui.button(icon).on_hover_text("Play/pause (Space)").clicked() {
+                playback.toggle();
+            }
+            let duration = playback.duration();
+            let mut position = playback.position();
+            ui.spacing_mut().slider_width = (self.width - 220.0).max(60.0);
+            let seek_bar = ui.add(egui::Slider::new(&mut position, 0.0..=duration.max(0.001)).show_value(false))
+                .on_hover_text("←/→ skip 5 seconds");
+            if seek_bar.changed() {
+                playback.seek(position);
+            }
+            ui.label(egui::RichText::new(format!("{} / {}", format_time(position), format_time(duration))).monospace());
+            egui::ComboBox::from_id_salt("playback_speed")
+                .width(56.0)
+                .selected_text(format!("{}×", playback.speed()))
+                .show_ui(ui, |ui| {
+                    for speed in SPEEDS {
+                        if ui.selectable_label(playback.speed() == speed, format!("{}×", speed)).clicked() {
+                            playback.set_speed(speed);
+                        }
+                    }
+                });
+        });
+    }
+
+    // Nothing reads input in a recording; Space plays and pauses, the arrows seek
+    fn handle_playback_keys(&mut self, ui: &mut egui::Ui) {
+        let Some(playback) = &mut self.playback else {
+            return;
+        };
+        ui.input_mut(|i| {
+            if i.consume_key(egui::Modifiers::NONE, egui::Key::Space) {
+                playback.toggle();
+            }
+            if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowLeft) {
+                playback.step(false);
+            }
+            if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowRight) {
+                playback.step(true);
+            }
+        });
     }
 }
 
+// The default ANSI colors with the config's [palette] changes
+fn configured_palette(config: &Config) -> Palette {
+    let mut palette = default_palette();
+    apply_edits(&mut palette, &parse_palette(&config.palette));
+    palette
+}
+
+// One segment per run of equally styled cells, and one for each wide character
+fn line_segments(cells: &[Cell], palette: &Palette, default_color: egui::Color32, default_background: egui::Color32) -> Vec<TerminalOutput> {
+    cells.chunk_by(|a, b| a.style == b.style && a.width == 1 && b.width == 1)
+        .filter(|run| run[0].width != 0)
+        .map(|run| TerminalOutput {
+            wide: run[0].width == 2,
+            ..TerminalOutput::new(cells_text(run), &run[0].style, palette, default_color, default_background)
+        })
+        .collect()
+}
+
+// Text between the bracketed paste markers (DECSET 2004), which tell the program it
+// was pasted rather than typed
+fn bracketed(text: &str) -> String {
+    // Don't let the text end the paste early
+    format!("\x1b[200~{}\x1b[201~", text.replace("\x1b[201~", ""))
+}
+
+fn is_error_text(text: &str) -> bool {
+    text.contains("error") || text.contains("Error") || text.contains("ERROR")
+}
+
 impl Drop for Terminal {
     fn drop(&mut self) {
-        if let Some(mut pty) = self.pty.take() {
-            match pty.exit(true) {
-                Ok(_) => {
-                    // PTY process successfully terminated
-                }
-                Err(e) => {
-                    eprintln!("Warning: Failed to cleanly exit PTY process: {}", e);
-                }
+        self.shutdown();
+    }
+}
+
+// Open a file that doesn't exist yet, so an export never replaces an earlier one:
+// `path`, else "name-2.ext", "name-3.ext", ..., updating `path` to the one created
+fn create_new_file(path: &mut PathBuf) -> std::io::Result<std::fs::File> {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let extension = path.extension().map(|ext| ext.to_string_lossy().into_owned());
+    let mut number = 1;
+    loop {
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&*path) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && number < 1000 => {
+                number += 1;
+                let name = match &extension {
+                    Some(ext) => format!("{}-{}.{}", stem, number, ext),
+                    None => format!("{}-{}", stem, number),
+                };
+                path.set_file_name(name);
             }
+            result => return result,
         }
     }
-}
\ No newline at end of file
+}
+
+// Shells listed in /etc/shells, for picking a replacement when the default one won't start
+fn installed_shells() -> Vec<String> {
+    let contents = std::fs::read_to_string("/etc/shells").unwrap_or_default();
+    let mut shells: Vec<String> = contents.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('/'))
+        .map(str::to_string)
+        .collect();
+    shells.dedup();
+    shells
+}
+
+// A process's command line from /proc, with the program's path cut down to its name.
+// The process group's id is its leader's pid, usually the program the shell started.
+fn process_command_line(pid: i32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let mut args = cmdline.split(|&b| b == 0).filter(|arg| !arg.is_empty()).map(String::from_utf8_lossy);
+    let program = args.next()?;
+    let name = program.rsplit('/').next().unwrap_or(&program).to_string();
+    Some(std::iter::once(name).chain(args.map(|arg| arg.into_owned())).collect::<Vec<_>>().join(" "))
+}
+
+// Pids of a process's children, from the parent pid in each /proc/<pid>/stat
+// ("1234 (bash) S 1200 ..."; the name can have spaces and parentheses in it)
+fn child_processes(parent: i32) -> Vec<i32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<i32>().ok())
+        .filter(|pid| {
+            std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()
+                .and_then(|stat| stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse::<i32>().ok())
+                == Some(parent)
+        })
+        .collect()
+}
+
+// Play the bell sound without waiting for it, through PulseAudio/PipeWire
+fn play_bell_sound(sound: Option<&str>) {
+    let mut command = match sound {
+        Some(path) => {
+            let mut command = Command::new("paplay");
+            command.arg(path);
+            command
+        }
+        None => {
+            let mut command = Command::new("canberra-gtk-play");
+            command.args(["--id", "bell"]);
+            command
+        }
+    };
+    let result = command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    match result {
+        // Reap it once it's done so it doesn't linger as a zombie
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("Warning: Failed to play the bell sound: {}", e),
+    }
+}
+
+fn spawn_error_hint(error: &str) -> &'static str {
+    if error.contains("ENOENT") {
+        "The program doesn't exist or isn't on PATH."
+    } else if error.contains("EACCES") {
+        "The program isn't executable."
+    } else {
+        "Allocating a PTY for it failed; too many open terminals or a restricted sandbox can cause this."
+    }
+}
+
+// Shift, Alt and Ctrl bits of a mouse report's button code
+fn mouse_modifiers(modifiers: egui::Modifiers) -> u8 {
+    (if modifiers.shift { 4 } else { 0 }) + (if modifiers.alt { 8 } else { 0 }) + (if modifiers.ctrl { 16 } else { 0 })
+}
+
+// One mouse report for the cell at (col, row). SGR reports (1006) say which button was
+// released; the legacy encoding sends button 3 for any release and can't go past column
+// or row 223.
+fn encode_mouse(out: &mut Vec<u8>, code: u8, (col, row): (usize, usize), release: bool, sgr: bool) {
+    if sgr {
+        let end = if release { 'm' } else { 'M' };
+        out.extend_from_slice(format!("\x1b[<{};{};{}{}", code, col + 1, row + 1, end).as_bytes());
+    } else if col < 223 && row < 223 {
+        let code = if release { code & !3 | 3 } else { code };
+        out.extend_from_slice(&[0x1b, b'[', b'M', 32 + code, 33 + col as u8, 33 + row as u8]);
+    }
+}
@@ -0,0 +1,272 @@
+use eframe::egui;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::config::config_dir;
+
+// Color schemes ======================================
+// What a pane's output is drawn in: text, background and the 16 ANSI colors. Panes
+// follow their hue (with DEFAULT_PALETTE for the ANSI colors) unless given a scheme,
+// either one of the built in ones or one imported from ~/.config/sigmaterm/themes/,
+// which takes the usual formats:
+//
+//   base16 YAML (.yaml, .yml)
+//   iTerm2 (.itermcolors)
+//   Windows Terminal JSON (.json), a single scheme or a settings file's "schemes"
+
+// ANSI colors 0-15: black, red, green, yellow, blue, magenta, cyan, white, then the
+// bright variants (SGR 90-97 and 100-107)
+pub type Palette = [egui::Color32; 16];
+
+// Names of the ANSI colors in [palette] tables, e.g.
+//
+//   [palette]
+//   red = "#e06c75"
+//   bright_black = "#5c6370"
+pub const COLOR_NAMES: [&str; 16] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    "bright_black", "bright_red", "bright_green", "bright_yellow", "bright_blue", "bright_magenta", "bright_cyan", "bright_white",
+];
+
+// Tango's colors, readable on both the dark and the light pane backgrounds
+const DEFAULT_COLORS: [&str; 16] = [
+    "2e3436", "cc0000", "4e9a06", "c4a000", "3465a4", "75507b", "06989a", "d3d7cf",
+    "555753", "ef2929", "8ae234", "fce94f", "729fcf", "ad7fa8", "34e2e2", "eeeeec",
+];
+
+pub fn default_palette() -> Palette {
+    DEFAULT_COLORS.map(|hex| hex_color(hex).unwrap_or_default())
+}
+
+// Changes to a palette, one per ANSI color that was changed
+pub type PaletteEdits = [Option<egui::Color32>; 16];
+
+// Edits from a [palette] table; unknown names and colors that aren't "#rrggbb" are skipped
+pub fn parse_palette(table: &BTreeMap<String, String>) -> PaletteEdits {
+    let mut edits = [None; 16];
+    for (name, value) in table {
+        match (COLOR_NAMES.iter().position(|n| n == name), hex_color(value)) {
+            (Some(index), Some(color)) => edits[index] = Some(color),
+            (None, _) => eprintln!("Warning: Unknown palette color \"{}\"", name),
+            (_, None) => eprintln!("Warning: Palette color {} = \"{}\" isn't #rrggbb", name, value),
+        }
+    }
+    edits
+}
+
+// Back to a [palette] table, for saving
+pub fn palette_table(edits: &PaletteEdits) -> BTreeMap<String, String> {
+    COLOR_NAMES.iter().zip(edits)
+        .filter_map(|(name, color)| color.map(|c| (name.to_string(), format!("#{:02x}{:02x}{:02x}", c.r(), c.g(), c.b()))))
+        .collect()
+}
+
+pub fn apply_edits(palette: &mut Palette, edits: &PaletteEdits) {
+    for (color, edit) in palette.iter_mut().zip(edits) {
+        if let Some(edit) = edit {
+            *color = *edit;
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct ColorScheme {
+    pub name: String,
+    pub foreground: egui::Color32,
+    pub background: egui::Color32,
+    pub palette: Palette,
+}
+
+
+// Built in schemes ===================================
+// Name, background, foreground, then ANSI 0-15
+const BUILT_IN: &[(&str, &str, &str, [&str; 16])] = &[
+    ("Solarized Dark", "002b36", "839496", [
+        "073642", "dc322f", "859900", "b58900", "268bd2", "d33682", "2aa198", "eee8d5",
+        "002b36", "cb4b16", "586e75", "657b83", "839496", "6c71c4", "93a1a1", "fdf6e3",
+    ]),
+    ("Solarized Light", "fdf6e3", "657b83", [
+        "073642", "dc322f", "859900", "b58900", "268bd2", "d33682", "2aa198", "eee8d5",
+        "002b36", "cb4b16", "586e75", "657b83", "839496", "6c71c4", "93a1a1", "fdf6e3",
+    ]),
+    ("Dracula", "282a36", "f8f8f2", [
+        "21222c", "ff5555", "50fa7b", "f1fa8c", "bd93f9", "ff79c6", "8be9fd", "f8f8f2",
+        "6272a4", "ff6e6e", "69ff94", "ffffa5", "d6acff", "ff92df", "a4ffff", "ffffff",
+    ]),
+    ("Nord", "2e3440", "d8dee9", [
+        "3b4252", "bf616a", "a3be8c", "ebcb8b", "81a1c1", "b48ead", "88c0d0", "e5e9f0",
+        "4c566a", "bf616a", "a3be8c", "ebcb8b", "81a1c1", "b48ead", "8fbcbb", "eceff4",
+    ]),
+    ("Gruvbox Dark", "282828", "ebdbb2", [
+        "282828", "cc241d", "98971a", "d79921", "458588", "b16286", "689d6a", "a89984",
+        "928374", "fb4934", "b8bb26", "fabd2f", "83a598", "d3869b", "8ec07c", "ebdbb2",
+    ]),
+    ("One Dark", "282c34", "abb2bf", [
+        "282c34", "e06c75", "98c379", "e5c07b", "61afef", "c678dd", "56b6c2", "abb2bf",
+        "5c6370", "e06c75", "98c379", "e5c07b", "61afef", "c678dd", "56b6c2", "ffffff",
+    ]),
+];
+
+fn built_in_schemes() -> Vec<ColorScheme> {
+    BUILT_IN.iter()
+        .map(|(name, background, foreground, palette)| ColorScheme {
+            name: name.to_string(),
+            foreground: hex_color(foreground).unwrap_or_default(),
+            background: hex_color(background).unwrap_or_default(),
+            palette: palette.map(|hex| hex_color(hex).unwrap_or_default()),
+        })
+        .collect()
+}
+
+// Every scheme there is: the built in ones, then the imported ones. Imported files are
+// read once, the first time a scheme is needed.
+pub fn schemes() -> &'static [ColorScheme] {
+    static SCHEMES: OnceLock<Vec<ColorScheme>> = OnceLock::new();
+    SCHEMES.get_or_init(|| {
+        let mut schemes = built_in_schemes();
+        schemes.extend(import_schemes());
+        schemes
+    })
+}
+
+pub fn find_scheme(name: &str) -> Option<&'static ColorScheme> {
+    let scheme = schemes().iter().find(|scheme| scheme.name.eq_ignore_ascii_case(name));
+    if scheme.is_none() {
+        eprintln!("Warning: Unknown color scheme \"{}\"", name);
+    }
+    scheme
+}
+
+// Importing ==========================================
+
+fn import_schemes() -> Vec<ColorScheme> {
+    let Some(dir) = config_dir().map(|dir| dir.join("themes")) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+
+    let mut schemes = Vec::new();
+    for path in paths {
+        match load_schemes(&path) {
+            Ok(loaded) => schemes.extend(loaded),
+            Err(e) => eprintln!("Warning: Failed to load color scheme {}: {}", path.display(), e),
+        }
+    }
+    schemes
+}
+
+// The schemes in a file, by its extension. Files of other kinds give none.
+pub fn load_schemes(path: &Path) -> Result<Vec<ColorScheme>, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    // Named after the file unless it says otherwise
+    let file_name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Imported").to_string();
+    match extension.as_str() {
+        "yaml" | "yml" => {
+            let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            parse_base16(&contents, file_name).map(|scheme| vec![scheme])
+        }
+        "itermcolors" => parse_itermcolors(path, file_name).map(|scheme| vec![scheme]),
+        "json" => {
+            let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            parse_windows_terminal(&contents, file_name)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+// base16: base00-base0F as hex strings, e.g. `base08: "ab4642"`. The newer tinted-theming
+// files nest them under `palette:`, so indentation doesn't matter.
+fn parse_base16(contents: &str, file_name: String) -> Result<ColorScheme, String> {
+    let mut name = file_name;
+    let mut bases: [Option<egui::Color32>; 16] = [None; 16];
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        let value = value.split('#').next().unwrap_or_default().trim().trim_matches(['"', '\'']);
+        if matches!(key, "scheme" | "name") && !value.is_empty() {
+            name = value.to_string();
+        } else if let Some(index) = key.strip_prefix("base").and_then(|n| u8::from_str_radix(n, 16).ok())
+            && index < 16
+        {
+            bases[index as usize] = hex_color(value);
+        }
+    }
+    let base = |index: usize| bases[index].ok_or_else(|| format!("base{:02X} is missing", index));
+    // The usual base16 terminal mapping
+    let ansi = [0x0, 0x8, 0xB, 0xA, 0xD, 0xE, 0xC, 0x5, 0x3, 0x8, 0xB, 0xA, 0xD, 0xE, 0xC, 0x7];
+    let mut palette = [egui::Color32::BLACK; 16];
+    for (color, &index) in palette.iter_mut().zip(&ansi) {
+        *color = base(index)?;
+    }
+    Ok(ColorScheme { name, foreground: base(0x5)?, background: base(0x0)?, palette })
+}
+
+// iTerm2: a plist of "Ansi 0 Color" ... "Ansi 15 Color", "Foreground Color" and
+// "Background Color", each with 0-1 red, green and blue components
+fn parse_itermcolors(path: &Path, name: String) -> Result<ColorScheme, String> {
+    let plist = plist::Value::from_file(path).map_err(|e| e.to_string())?;
+    let dict = plist.as_dictionary().ok_or("not a dictionary")?;
+    let color = |key: &str| -> Result<egui::Color32, String> {
+        let entry = dict.get(key).and_then(plist::Value::as_dictionary).ok_or_else(|| format!("\"{}\" is missing", key))?;
+        let component = |name: &str| {
+            let value = entry.get(name).and_then(plist::Value::as_real).unwrap_or(0.0);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        Ok(egui::Color32::from_rgb(component("Red Component"), component("Green Component"), component("Blue Component")))
+    };
+    let mut palette = [egui::Color32::BLACK; 16];
+    for (index, slot) in palette.iter_mut().enumerate() {
+        *slot = color(&format!("Ansi {} Color", index))?;
+    }
+    Ok(ColorScheme { name, foreground: color("Foreground Color")?, background: color("Background Color")?, palette })
+}
+
+// Windows Terminal: an object with "name", "foreground", "background" and "black" ...
+// "brightWhite" as "#rrggbb", or a settings file with a list of them under "schemes"
+fn parse_windows_terminal(contents: &str, file_name: String) -> Result<Vec<ColorScheme>, String> {
+    const KEYS: [&str; 16] = [
+        "black", "red", "green", "yellow", "blue", "purple", "cyan", "white",
+        "brightBlack", "brightRed", "brightGreen", "brightYellow", "brightBlue", "brightPurple", "brightCyan", "brightWhite",
+    ];
+    let json: serde_json::Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    let objects = match json.get("schemes").and_then(serde_json::Value::as_array) {
+        Some(schemes) => schemes.iter().collect(),
+        None => vec![&json],
+    };
+
+    objects.into_iter().map(|scheme| {
+        let color = |key: &str| {
+            scheme.get(key)
+                .and_then(serde_json::Value::as_str)
+                .and_then(hex_color)
+                .ok_or_else(|| format!("\"{}\" is missing or not a color", key))
+        };
+        let mut palette = [egui::Color32::BLACK; 16];
+        for (slot, key) in palette.iter_mut().zip(KEYS) {
+            *slot = color(key)?;
+        }
+        Ok(ColorScheme {
+            name: scheme.get("name").and_then(serde_json::Value::as_str).map_or(file_name.clone(), str::to_string),
+            foreground: color("foreground")?,
+            background: color("background")?,
+            palette,
+        })
+    }).collect()
+}
+
+// "rrggbb" or "#rrggbb"
+fn hex_color(hex: &str) -> Option<egui::Color32> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(egui::Color32::from_rgb(channel(0)?, channel(2)?, channel(4)?))
+}
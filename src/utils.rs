@@ -26,7 +26,7 @@ fn hsl_to_egui_color32(h: f32, s: f32, l: f32) -> egui::Color32 {
         ((b + m) * 255.0) as u8)
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct ColorSet {
     pub primary: egui::Color32,
     pub light: egui::Color32,
@@ -50,19 +50,29 @@ impl Default for ColorSet {
     }
 }
 
+// Saturation and lightness of a pane's primary color unless the user picks their own
+pub const DEFAULT_SATURATION: f32 = 0.6;
+pub const DEFAULT_LIGHTNESS: f32 = 0.6;
+
 pub fn get_set_from_hue(h: f32) -> ColorSet {
+    get_set_from_hsl(h, DEFAULT_SATURATION, DEFAULT_LIGHTNESS)
+}
+
+// Low saturation gives pastel panes, high lightness washed-out ones; the light and
+// dark backgrounds keep their lightness so text on them stays readable
+pub fn get_set_from_hsl(h: f32, s: f32, l: f32) -> ColorSet {
     ColorSet  {
-        primary: hsl_to_egui_color32(h, 0.6, 0.6),
-        light: hsl_to_egui_color32((h + 10.0) % 360.0,  0.6, 0.95),
-        dark: hsl_to_egui_color32((h - 10.0 + 360.0) % 360.0,  0.1, 0.15),
-        on_primary: hsl_to_egui_color32(h, 0.6, 0.2),
+        primary: hsl_to_egui_color32(h, s, l),
+        light: hsl_to_egui_color32((h + 10.0) % 360.0,  s, 0.95),
+        dark: hsl_to_egui_color32((h - 10.0 + 360.0) % 360.0,  s / 6.0, 0.15),
+        on_primary: hsl_to_egui_color32(h, s, l / 3.0),
         on_light: egui::Color32::BLACK,
         on_dark: egui::Color32::WHITE,
         alert: egui::Color32::RED,
         warning: egui::Color32::YELLOW,
-        alternate_1: hsl_to_egui_color32((h + 90.0) % 360.0,  0.6, 0.6),
-        alternate_2: hsl_to_egui_color32((h + 180.0) % 360.0,  0.6, 0.6),
-        alternate_3: hsl_to_egui_color32((h + 270.0) % 360.0,  0.6, 0.6),
+        alternate_1: hsl_to_egui_color32((h + 90.0) % 360.0,  s, l),
+        alternate_2: hsl_to_egui_color32((h + 180.0) % 360.0,  s, l),
+        alternate_3: hsl_to_egui_color32((h + 270.0) % 360.0,  s, l),
     }
 }
 
@@ -91,4 +101,62 @@ pub fn window_button(ui: &mut egui::Ui, text: &str, button_color: egui::Color32,
     );
     
     response.clicked()
+}
+
+// Dialog asking before closing something that still has programs running in it.
+// Some(true) to go ahead, Some(false) to keep it, None while it's still up.
+pub fn confirm_close(ctx: &egui::Context, heading: &str, processes: &[String], action: &str) -> Option<bool> {
+    let mut choice = None;
+    // The pane, tab and quit dialogs each get their own id, by heading
+    let response = egui::Modal::new(egui::Id::new(("confirm_close", heading))).show(ctx, |ui| {
+        ui.heading(heading);
+        ui.label("These are still running:");
+        for process in processes {
+            ui.label(egui::RichText::new(process).monospace());
+        }
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            if ui.button(action).clicked() {
+                choice = Some(true);
+            }
+            if ui.button("Cancel").clicked() {
+                choice = Some(false);
+            }
+        });
+    });
+    if response.should_close() {
+        choice.get_or_insert(false);
+    }
+    choice
+}
+
+// Byte index where the word ending at `end` starts (skipping any separators first),
+// for Ctrl+W / Ctrl+Backspace style deletion
+pub fn prev_word_start(text: &str, end: usize, is_word_char: impl Fn(char) -> bool) -> usize {
+    let mut chars = text[..end].char_indices().rev().peekable();
+    while chars.next_if(|&(_, c)| !is_word_char(c)).is_some() {}
+    while chars.next_if(|&(_, c)| is_word_char(c)).is_some() {}
+    chars.peek().map_or(0, |&(i, c)| i + c.len_utf8())
+}
+
+// Byte index where the word starting at or after `start` ends, for Ctrl+Right
+pub fn next_word_end(text: &str, start: usize, is_word_char: impl Fn(char) -> bool) -> usize {
+    let mut chars = text[start..].char_indices().peekable();
+    while chars.next_if(|&(_, c)| !is_word_char(c)).is_some() {}
+    while chars.next_if(|&(_, c)| is_word_char(c)).is_some() {}
+    chars.peek().map_or(text.len(), |&(i, _)| start + i)
+}
+
+// Short human-readable duration, e.g. "850ms", "12.3s", "4m 05s", "1h 02m"
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else if secs >= 1 {
+        format!("{:.1}s", duration.as_secs_f32())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
 }
\ No newline at end of file
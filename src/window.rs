@@ -1,13 +1,32 @@
 use eframe::egui;
-use egui::Stroke;
+use crate::settings::{default_archive_path, export_settings, import_settings};
 use crate::utils::window_button;
 
+// Window bar action signals
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowAction {
+    None,
+    AddTerminal,
+    AddProfileTerminal(usize),  // Index into the config's profiles
+    AddTab,
+    ReloadConfig,
+    ToggleStats,
+}
+
 pub struct WindowBar {
+    title: String,
     bg_color: egui::Color32,
     button_color: egui::Color32,
     hover_color: egui::Color32,
     close_hover_color: egui::Color32,
     dark_mode: bool,
+    always_on_top: bool,
+    fullscreen: bool,
+    settings_open: bool,
+    settings_path: String,
+    settings_status: String,
+    add_terminal_enabled: bool,  // False once the active tab is at max_terminals
+    profiles: Vec<String>,  // Names for the menu next to the add button
 }
 
 impl Default for WindowBar {
@@ -19,20 +38,69 @@ impl Default for WindowBar {
 impl WindowBar {
     pub fn new() -> Self {
         Self {
+            title: "Sigmaterm".to_string(),
             bg_color: egui::Color32::from_gray(30),
             button_color: egui::Color32::from_gray(180),
             hover_color: egui::Color32::from_gray(60),
             close_hover_color: egui::Color32::from_rgb(200, 50, 50),
             dark_mode: true,
+            always_on_top: false,
+            fullscreen: false,
+            settings_open: false,
+            settings_path: default_archive_path().display().to_string(),
+            settings_status: String::new(),
+            add_terminal_enabled: true,
+            profiles: Vec::new(),
         }
     }
     
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    pub fn set_profiles(&mut self, profiles: Vec<String>) {
+        self.profiles = profiles;
+    }
+
+    pub fn set_add_terminal_enabled(&mut self, enabled: bool) {
+        self.add_terminal_enabled = enabled;
+    }
+
+    pub fn set_dark_mode(&mut self, dark_mode: bool) {
+        self.dark_mode = dark_mode;
+    }
+
     pub fn is_dark_mode(&self) -> bool {
         self.dark_mode
     }
 
-    pub fn render(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) -> bool {
-        let mut add_terminal: bool = false;
+    pub fn is_always_on_top(&self) -> bool {
+        self.always_on_top
+    }
+
+    // Keep the window above the others
+    pub fn set_always_on_top(&mut self, ctx: &egui::Context, always_on_top: bool) {
+        self.always_on_top = always_on_top;
+        let level = if always_on_top { egui::WindowLevel::AlwaysOnTop } else { egui::WindowLevel::Normal };
+        ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(level));
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    // Borderless fullscreen; the window bar stays for its buttons
+    pub fn set_fullscreen(&mut self, ctx: &egui::Context, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(fullscreen));
+    }
+
+    pub fn render(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) -> WindowAction {
+        let mut action = WindowAction::None;
+        // The window manager can take the window out of fullscreen too
+        if let Some(fullscreen) = ctx.input(|i| i.viewport().fullscreen) {
+            self.fullscreen = fullscreen;
+        }
         
         // Add resize handles for custom window decorations
         self.render_resize_handles(ctx);
@@ -43,9 +111,33 @@ impl WindowBar {
                 .inner_margin(8.0))
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
-                    // Left side: Add terminal button
-                    if window_button(ui, "❮+❯", self.button_color, self.hover_color) {
-                        add_terminal = true;
+                    // Left side: Add terminal button, dimmed at the pane limit (clicking it
+                    // then explains why nothing opens)
+                    let add_color = if self.add_terminal_enabled { self.button_color } else { self.hover_color };
+                    if window_button(ui, "❮+❯", add_color, self.hover_color) {
+                        action = WindowAction::AddTerminal;
+                    }
+                    if !self.profiles.is_empty() {
+                        ui.menu_button(egui::RichText::new("▾").color(add_color), |ui| {
+                            for (index, name) in self.profiles.iter().enumerate() {
+                                if ui.button(name).clicked() {
+                                    action = WindowAction::AddProfileTerminal(index);
+                                }
+                            }
+                        }).response.on_hover_text("New pane with a profile");
+                    }
+
+                    if window_button(ui, "📑", self.button_color, self.hover_color) {
+                        action = WindowAction::AddTab;
+                    }
+
+                    if window_button(ui, "⚙", self.button_color, self.hover_color) {
+                        self.settings_open = !self.settings_open;
+                        self.settings_status.clear();
+                    }
+
+                    if window_button(ui, "⏱", self.button_color, self.hover_color) {
+                        action = WindowAction::ToggleStats;
                     }
                     
                     // Allocate space for right buttons first
@@ -68,6 +160,16 @@ impl WindowBar {
                                 ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
                             }
 
+                            let fullscreen_color = if self.fullscreen { egui::Color32::WHITE } else { self.button_color };
+                            if window_button(ui, "⛶", fullscreen_color, self.hover_color) {
+                                self.set_fullscreen(ctx, !self.fullscreen);
+                            }
+
+                            let pin_color = if self.always_on_top { egui::Color32::WHITE } else { self.button_color };
+                            if window_button(ui, "📌", pin_color, self.hover_color) {
+                                self.set_always_on_top(ctx, !self.always_on_top);
+                            }
+
                             if self.dark_mode_toggle_button(ui, self.dark_mode) {
                                 self.dark_mode = !self.dark_mode;
                             }
@@ -82,7 +184,7 @@ impl WindowBar {
                             ui.painter().text(
                                 title_response.rect.center(),
                                 egui::Align2::CENTER_CENTER,
-                                "Sigmaterm",
+                                &self.title,
                                 egui::FontId::proportional(14.0),
                                 egui::Color32::from_gray(200),
                             );
@@ -95,8 +197,51 @@ impl WindowBar {
                     );
                 });
             });
+
+        if self.settings_open && self.render_settings_window(ctx) {
+            action = WindowAction::ReloadConfig;
+        }
         
-        add_terminal
+        action
+    }
+
+    // Export/import popup. Returns true when new settings were imported.
+    fn render_settings_window(&mut self, ctx: &egui::Context) -> bool {
+        let mut imported = false;
+
+        egui::Window::new("Settings")
+            .open(&mut self.settings_open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Archive file");
+                ui.add(egui::TextEdit::singleline(&mut self.settings_path).desired_width(320.0));
+
+                ui.horizontal(|ui| {
+                    let path = std::path::PathBuf::from(&self.settings_path);
+                    if ui.button("Export settings").clicked() {
+                        self.settings_status = match export_settings(&path) {
+                            Ok(count) => format!("Exported {} file(s)", count),
+                            Err(e) => format!("Export failed: {}", e),
+                        };
+                    }
+                    if ui.button("Import settings").clicked() {
+                        self.settings_status = match import_settings(&path) {
+                            Ok(count) => {
+                                imported = true;
+                                format!("Imported {} file(s)", count)
+                            }
+                            Err(e) => format!("Import failed: {}", e),
+                        };
+                    }
+                });
+
+                if !self.settings_status.is_empty() {
+                    ui.label(&self.settings_status);
+                }
+            });
+
+        imported
     }
 
     fn dark_mode_toggle_button(&self, ui: &mut egui::Ui, dark_mode: bool) -> bool {
@@ -0,0 +1,8 @@
+use std::env;
+
+// Prints its arguments back, one per line
+fn main() {
+    for (i, arg) in env::args().skip(1).enumerate() {
+        println!("{}: {}", i, arg);
+    }
+}
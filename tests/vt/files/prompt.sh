@@ -0,0 +1 @@
+PS1='$ '
@@ -0,0 +1,56 @@
+#!/bin/bash
+# Records a conformance fixture: runs a command in a detached tmux pane of the given
+# size, types the keys into it, and writes what the command printed along with the
+# screen and cursor tmux ended up with. tmux is the reference, so check the capture
+# looks right before adding it.
+#
+#   tests/vt/record.sh vim_insert 40x12 "vim -u NONE -N -i NONE main.rs" "jA // hi" Escape
+#
+# Keys are tmux send-keys arguments, sent one at a time. Commands run in tests/vt/files.
+set -e
+if [ $# -lt 3 ]; then
+    echo "usage: $0 NAME COLSxROWS COMMAND [KEYS...]" >&2
+    exit 1
+fi
+name=$1 size=$2 command=$3
+shift 3
+cols=${size%x*} rows=${size#*x}
+here=$(cd "$(dirname "$0")" && pwd)
+raw=$(mktemp)
+tmux="tmux -L sigmaterm-record -f /dev/null"
+
+$tmux new-session -d -x "$cols" -y "$rows" -c "$here/files" \
+    "sleep 0.5; TERM=xterm-256color $command; sleep 60"
+$tmux set -g escape-time 0
+$tmux set -g status off
+$tmux pipe-pane -o "cat > $raw"
+sleep 1.5
+for keys in "$@"; do
+    $tmux send-keys "$keys"
+    sleep 0.5
+done
+sleep 1
+# Stop recording before reading the screen, so they agree
+$tmux pipe-pane
+screen=$($tmux capture-pane -p)
+# tmux puts the cursor past the last column while a wrap is pending; xterm keeps it on it
+cursor=$($tmux display -p '#{cursor_y},#{cursor_x}' | awk -F, -v cols="$cols" '{ print $1 "," ($2 < cols ? $2 : cols - 1) }')
+$tmux kill-server
+
+{
+    echo "# Recorded with: $command"
+    [ $# -gt 0 ] && echo "# Keys: $*"
+    echo "size $size"
+    echo "== input"
+    perl -0777 -pe 's/\\/\\\\/g; s/\e/\\e/g; s/\r/\\r/g; s/\t/\\t/g; s/\a/\\a/g;
+        s/([\x00-\x09\x0b-\x1f\x7f])/sprintf("\\x%02x", ord $1)/ge; s/\n/\\n\n/g;
+        s/([\xc2-\xdf][\x80-\xbf]|[\xe0-\xef][\x80-\xbf]{2}|[\xf0-\xf4][\x80-\xbf]{3})|([\x80-\xff])/
+            defined $1 ? $1 : sprintf("\\x%02x", ord $2)/ge' "$raw"
+    echo
+    echo "== screen"
+    printf '%s\n' "$screen" | sed 's/[[:space:]]*$//'
+    echo "== cursor"
+    echo "$cursor"
+} > "$here/$name.vt"
+rm "$raw"
+echo "Wrote $here/$name.vt"